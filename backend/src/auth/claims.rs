@@ -168,6 +168,24 @@ impl Claims {
             .get(&store_id)
             .map_or(false, |perms| perms.contains(&num))
     }
+
+    pub fn tool_manager_store_ids(&self) -> Vec<i32> {
+        let num = Roles::ToolManager as i32;
+        self.permissions
+            .store
+            .iter()
+            .filter(|(_, perms)| perms.contains(&num))
+            .map(|(store_id, _)| *store_id)
+            .collect()
+    }
+
+    pub fn is_any_tool_manager(&self) -> bool {
+        let num = Roles::ToolManager as i32;
+        self.permissions
+            .store
+            .values()
+            .any(|perms| perms.contains(&num))
+    }
 }
 
 #[axum::async_trait]
@@ -1,4 +1,4 @@
-use crate::db_structs::user;
+use crate::db_structs::{store_policy, user};
 use axum::{
     extract::Json,
     http::StatusCode,
@@ -8,18 +8,52 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 pub const PAGE_SIZE: i64 = 20;
+pub const MAX_CONDITION_REPORT_NOTES_LENGTH: usize = 5000;
+pub const MAX_CONDITION_REPORT_PHOTOS_LENGTH: i32 = 20;
+pub const MAX_DEPOSIT_REASON_LENGTH: usize = 1000;
+pub const MAX_TOOL_STATUS_REASON_LENGTH: usize = 1000;
+pub const MAX_MAINTENANCE_SCHEDULE_NAME_LENGTH: usize = 250;
+pub const MAX_MAINTENANCE_EVERY_DAYS: i32 = 3650;
+pub const MAX_SERVICE_LOG_NOTES_LENGTH: usize = 5000;
+pub const MAX_SERVICE_LOG_PHOTOS_LENGTH: i32 = 20;
+pub const MAX_REPAIR_TICKET_DESCRIPTION_LENGTH: usize = 5000;
+pub const MAX_REPAIR_TICKET_RESOLUTION_LENGTH: usize = 5000;
+pub const MAX_REPAIR_TICKET_PHOTOS_LENGTH: i32 = 20;
+pub const MAX_FEE_GRACE_HOURS: i32 = 8760;
 pub const MAX_GRIEVANCE_TITLE_LENGTH: usize = 250;
 pub const MAX_GRIEVANCE_DESCRIPTION_LENGTH: usize = 5000;
 pub const MAX_GRIEVANCE_REPLY_TEXT_LENGTH: usize = 5000;
+pub const HOLD_PICKUP_WINDOW_HOURS: i32 = 48;
+pub const MAX_LABEL_GRID_COLUMNS: i32 = 10;
+pub const MAX_LABEL_GRID_ROWS: i32 = 30;
+pub const MAX_LABEL_SHEET_TOOLS: i64 = 2000;
+pub const MAX_LEDGER_AMOUNT: i32 = 100_000_000;
+pub const MAX_LEDGER_NOTE_LENGTH: usize = 1000;
 pub const MAX_LIBRARY_NAME_LENGTH: usize = 400;
+pub const MAX_OVERDUE_ESCALATION_DAYS: i32 = 3650;
+pub const MAX_POLICY_ACCOUNT_AGE_DAYS: i32 = 3650;
+pub const OVERDUE_CHECK_INTERVAL_SECONDS: u64 = 300;
 pub const MAX_TOOL_RENTAL_CHECK_IN_COUNT: usize = 200;
 pub const MAX_TOOL_RENTAL_CHECK_OUT_COUNT: usize = 200;
+pub const MAX_RENTAL_EXTENSION_NOTE_LENGTH: usize = 1000;
+pub const MAX_RENTAL_SCANS_LENGTH: usize = 202; // a full check out, plus a user and a store code
+pub const MAX_RESERVATION_DAYS_AHEAD: i64 = 90;
+pub const MAX_RESERVATION_HOURS: i64 = 336;
 pub const MAX_STORE_TITLE_LENGTH: usize = 250;
 pub const MAX_STORE_LOCATION_LENGTH: usize = 250;
 pub const MAX_STORE_EMAIL_LENGTH: usize = 400;
 pub const MAX_STORE_PHONE_LENGTH: usize = 20;
 pub const MAX_STORE_RENTAL_INFO_LENGTH: usize = 5000;
 pub const MAX_STORE_OTHER_INFO_LENGTH: usize = 5000;
+pub const MAX_STORE_HOLIDAY_NAME_LENGTH: usize = 250;
+pub const MAX_STORE_HOURS_LENGTH: i32 = 50;
+pub const MAX_STOCKTAKE_NOTE_LENGTH: usize = 1000;
+pub const MAX_SEARCH_CANDIDATES: i64 = 1000;
+pub const SEARCH_REINDEX_BATCH_SIZE: i64 = 500;
+pub const SEARCH_INDEXER_RETRY_SECONDS: u64 = 5;
+pub const SEARCH_INDEXER_PREFETCH: u16 = 50;
+pub const MAX_STOCKTAKE_SCANS_LENGTH: usize = 500;
+pub const MAX_TOOL_ASSET_FIELD_LENGTH: usize = 120;
 pub const MAX_TOOL_CATEGORY_NAME_LENGTH: usize = 250;
 pub const MAX_TOOL_CATEGORY_DESCRIPTION_LENGTH: usize = 1000;
 pub const MAX_TOOL_CATEGORY_SYNONYMS_LENGTH: usize = 400;
@@ -28,6 +62,12 @@ pub const MAX_TOOL_LONG_DESCRIPTION_LENGTH: usize = 1000;
 pub const MAX_TOOL_REAL_ID_LENGTH: usize = 120;
 pub const MAX_TOOL_CATEGORIES_LENGTH: i32 = 50;
 pub const MAX_TOOL_PHOTOS_LENGTH: i32 = 50;
+pub const MAX_TOOL_IMPORT_ROWS: usize = 1000;
+pub const MAX_TOOL_KIT_MEMBERS_LENGTH: i32 = 50;
+pub const MAX_TOOL_LOCATION_LENGTH: usize = 64;
+pub const MAX_TOOL_QUANTITY: i32 = 10_000;
+pub const MAX_TOOL_TRANSFER_NOTE_LENGTH: usize = 1000;
+pub const MAX_TOOL_TRANSFER_TOOLS_LENGTH: i32 = 1000;
 pub const MAX_USERNAME_LENGTH: usize = 64;
 
 pub fn calculate_offset_limit(page: i64) -> (i64, i64) {
@@ -70,19 +110,11 @@ pub struct DateBetween {
     pub end: Option<time::OffsetDateTime>,
 }
 
-impl DateBetween {
-    pub fn none() -> Self {
-        Self {
-            start: None,
-            end: None,
-        }
-    }
-}
-
 pub struct ErrResponse {
     pub status: StatusCode,
     pub err_code: String,
     pub details: String,
+    pub violations: Vec<store_policy::PolicyViolation>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -91,6 +123,8 @@ pub struct ErrResponseJsonPart {
     status: u16,
     err_code: String,
     details: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    violations: Vec<store_policy::PolicyViolation>,
 }
 
 impl IntoResponse for ErrResponse {
@@ -99,6 +133,7 @@ impl IntoResponse for ErrResponse {
             status: self.status.as_u16(),
             err_code: self.err_code.clone(),
             details: self.details.clone(),
+            violations: self.violations,
         });
 
         (self.status, json_part).into_response()
@@ -111,8 +146,15 @@ impl ErrResponse {
             status,
             err_code: err_code.to_string(),
             details: details.to_string(),
+            violations: vec![],
         }
     }
+
+    // lets the client show every broken rule at once, rather than just the first
+    pub fn with_violations(mut self, violations: Vec<store_policy::PolicyViolation>) -> Self {
+        self.violations = violations;
+        self
+    }
 }
 
 pub fn rnd_code_str(pre: &str) -> String {
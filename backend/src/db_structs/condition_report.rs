@@ -0,0 +1,28 @@
+use super::{rental, tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ToolId = tool::Id;
+pub type RentalId = rental::Id;
+pub type ReporterId = user::Id;
+pub type Grade = i32;
+pub type Notes = String;
+pub type ToolStatus = tool::Status;
+pub type CreatedAt = time::OffsetDateTime;
+
+// 1 is excellent, then good, fair and poor, down to 5 for damaged
+pub const BEST_GRADE: Grade = 1;
+pub const WORST_GRADE: Grade = 5;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionReport {
+    pub id: Id,
+    pub tool_id: ToolId,
+    pub rental_id: Option<RentalId>,
+    pub reporter_id: ReporterId,
+    pub grade: Grade,
+    pub notes: Option<Notes>,
+    pub tool_status: ToolStatus,
+    pub created_at: CreatedAt,
+}
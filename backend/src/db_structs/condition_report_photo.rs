@@ -0,0 +1,16 @@
+use super::{condition_report, tool_photo};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ReportId = condition_report::Id;
+pub type PhotoKey = tool_photo::PhotoKey;
+pub type OriginalName = tool_photo::OriginalName;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionReportPhoto {
+    pub id: Id,
+    pub report_id: ReportId,
+    pub photo_key: PhotoKey,
+    pub original_name: OriginalName,
+}
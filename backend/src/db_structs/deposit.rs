@@ -0,0 +1,42 @@
+use super::{condition_report, rental, store, tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type RentalId = rental::Id;
+pub type ToolId = tool::Id;
+pub type UserId = user::Id;
+pub type StoreId = store::Id;
+pub type Amount = i32;
+pub type Status = i32;
+pub type KeptAmount = i32;
+pub type Reason = String;
+pub type ConditionReportId = condition_report::Id;
+pub type TakenAt = time::OffsetDateTime;
+pub type SettledAt = time::OffsetDateTime;
+pub type SettledBy = user::Id;
+
+pub enum DepositStatus {
+    Held = 1,
+    Released = 2,
+    Kept = 3,
+    PartiallyKept = 4,
+}
+
+// amounts are in the smallest unit of the local currency, like the ledger
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deposit {
+    pub id: Id,
+    pub rental_id: RentalId,
+    pub tool_id: ToolId,
+    pub user_id: UserId,
+    pub store_id: StoreId,
+    pub amount: Amount,
+    pub status: Status,
+    pub kept_amount: Option<KeptAmount>,
+    pub reason: Option<Reason>,
+    pub condition_report_id: Option<ConditionReportId>,
+    pub taken_at: TakenAt,
+    pub settled_at: Option<SettledAt>,
+    pub settled_by: Option<SettledBy>,
+}
@@ -0,0 +1,21 @@
+use super::store;
+use serde::{Deserialize, Serialize};
+
+pub type StoreId = store::Id;
+pub type GraceHours = i32;
+pub type FeePerDay = i32;
+pub type FeeCap = i32;
+pub type MaxBalance = i32;
+pub type UpdatedAt = time::OffsetDateTime;
+
+// amounts are in the smallest unit of the local currency, e.g. cents
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeSchedule {
+    pub store_id: StoreId,
+    pub grace_hours: GraceHours,
+    pub fee_per_day: FeePerDay,
+    pub fee_cap: Option<FeeCap>,
+    pub max_balance: Option<MaxBalance>,
+    pub updated_at: UpdatedAt,
+}
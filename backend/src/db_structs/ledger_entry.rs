@@ -0,0 +1,34 @@
+use super::{rental, store, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type UserId = user::Id;
+pub type StoreId = store::Id;
+pub type Kind = i32;
+pub type Amount = i32;
+pub type RentalId = rental::Id;
+pub type Note = String;
+pub type CreatedBy = user::Id;
+pub type CreatedAt = time::OffsetDateTime;
+
+// a positive amount is owed by the user, a negative one is credited to them
+pub enum LedgerEntryKind {
+    OverdueFee = 1,
+    Adjustment = 2,
+    Waiver = 3,
+    Payment = 4,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntry {
+    pub id: Id,
+    pub user_id: UserId,
+    pub store_id: StoreId,
+    pub kind: Kind,
+    pub amount: Amount,
+    pub rental_id: Option<RentalId>,
+    pub note: Option<Note>,
+    pub created_by: Option<CreatedBy>,
+    pub created_at: CreatedAt,
+}
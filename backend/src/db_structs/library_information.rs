@@ -2,10 +2,16 @@ use serde::{Deserialize, Serialize};
 
 pub type Uuid = uuid::Uuid;
 pub type Name = String;
+pub type OverdueReminderIntervalDays = i32;
+pub type OverdueLostAfterDays = i32;
+pub type OverdueGrievanceAfterDays = i32;
 
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LibraryInformation {
     pub uuid: Uuid,
     pub name: Name,
-}
\ No newline at end of file
+    pub overdue_reminder_interval_days: OverdueReminderIntervalDays,
+    pub overdue_lost_after_days: Option<OverdueLostAfterDays>,
+    pub overdue_grievance_after_days: Option<OverdueGrievanceAfterDays>,
+}
@@ -0,0 +1,27 @@
+use super::user;
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ReceiptNumber = String;
+pub type RenterId = user::Id;
+pub type PerformedBy = user::Id;
+pub type Channel = i32;
+pub type CreatedAt = time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoanChannel {
+    Staff = 1,
+    SelfCheckout = 2,
+    Kiosk = 3,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Loan {
+    pub id: Id,
+    pub receipt_number: ReceiptNumber,
+    pub renter_id: RenterId,
+    pub performed_by: PerformedBy,
+    pub channel: Channel,
+    pub created_at: CreatedAt,
+}
@@ -0,0 +1,24 @@
+use super::{tool, tool_category};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ToolId = tool::Id;
+pub type CategoryId = tool_category::Id;
+pub type Name = String;
+pub type EveryRentals = i32;
+pub type EveryDays = i32;
+pub type CreatedAt = time::OffsetDateTime;
+
+// set for either a single tool or every tool in a category, and due after
+// every_rentals rentals or every_days days, whichever comes first
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceSchedule {
+    pub id: Id,
+    pub tool_id: Option<ToolId>,
+    pub category_id: Option<CategoryId>,
+    pub name: Name,
+    pub every_rentals: Option<EveryRentals>,
+    pub every_days: Option<EveryDays>,
+    pub created_at: CreatedAt,
+}
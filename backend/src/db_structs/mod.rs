@@ -1,11 +1,38 @@
+pub mod condition_report;
+pub mod condition_report_photo;
+pub mod deposit;
+pub mod fee_schedule;
 pub mod grievance;
 pub mod grievance_reply;
+pub mod ledger_entry;
 pub mod library_information;
+pub mod loan;
+pub mod maintenance_schedule;
 pub mod permission;
 pub mod rental;
+pub mod rental_extension;
+pub mod rental_reminder;
+pub mod repair_ticket;
+pub mod repair_ticket_photo;
+pub mod reservation;
+pub mod service_log_entry;
+pub mod service_log_photo;
+pub mod stocktake;
+pub mod stocktake_discrepancy;
+pub mod stocktake_scan;
 pub mod store;
+pub mod store_holiday;
+pub mod store_hours;
+pub mod store_policy;
 pub mod tool;
+pub mod tool_asset;
 pub mod tool_category;
 pub mod tool_classification;
+pub mod tool_hold;
+pub mod tool_kit;
+pub mod tool_kit_member;
 pub mod tool_photo;
+pub mod tool_status_change;
+pub mod tool_transfer;
+pub mod tool_transfer_item;
 pub mod user;
\ No newline at end of file
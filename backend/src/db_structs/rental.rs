@@ -1,4 +1,4 @@
-use super::user;
+use super::{loan, user};
 use serde::{Deserialize, Serialize};
 
 pub type Id = i32;
@@ -6,6 +6,10 @@ pub type ToolId = i32;
 pub type RenterId = user::Id;
 pub type StartDate = time::OffsetDateTime;
 pub type EndDate = time::OffsetDateTime;
+pub type DueDate = time::OffsetDateTime;
+pub type LoanId = loan::Id;
+pub type Quantity = i32;
+pub type LostQuantity = i32;
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Rental {
@@ -14,4 +18,8 @@ pub struct Rental {
     pub renter_id: RenterId,
     pub start_date: StartDate,
     pub end_date: Option<EndDate>,
+    pub due_date: DueDate,
+    pub loan_id: Option<LoanId>,
+    pub quantity: Quantity,
+    pub lost_quantity: LostQuantity,
 }
@@ -0,0 +1,37 @@
+use super::{rental, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type RentalId = rental::Id;
+pub type RequestedBy = user::Id;
+pub type PreviousDueDate = time::OffsetDateTime;
+pub type RequestedDueDate = time::OffsetDateTime;
+pub type Note = String;
+pub type Status = i32;
+pub type CreatedAt = time::OffsetDateTime;
+pub type DecidedBy = user::Id;
+pub type DecidedAt = time::OffsetDateTime;
+pub type DecisionNote = String;
+
+pub enum ExtensionStatus {
+    Pending = 1,
+    Approved = 2,
+    Denied = 3,
+    Cancelled = 4,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentalExtension {
+    pub id: Id,
+    pub rental_id: RentalId,
+    pub requested_by: RequestedBy,
+    pub previous_due_date: PreviousDueDate,
+    pub requested_due_date: RequestedDueDate,
+    pub note: Option<Note>,
+    pub status: Status,
+    pub created_at: CreatedAt,
+    pub decided_by: Option<DecidedBy>,
+    pub decided_at: Option<DecidedAt>,
+    pub decision_note: Option<DecisionNote>,
+}
@@ -0,0 +1,24 @@
+use super::rental;
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type RentalId = rental::Id;
+pub type Kind = i32;
+pub type DaysOverdue = i32;
+pub type CreatedAt = time::OffsetDateTime;
+
+pub enum ReminderKind {
+    Overdue = 1,
+    MarkedLost = 2,
+    GrievanceOpened = 3,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RentalReminder {
+    pub id: Id,
+    pub rental_id: RentalId,
+    pub kind: Kind,
+    pub days_overdue: DaysOverdue,
+    pub created_at: CreatedAt,
+}
@@ -0,0 +1,47 @@
+use super::{tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ToolId = tool::Id;
+pub type ReporterId = user::Id;
+pub type Description = String;
+pub type Status = i32;
+pub type Priority = i32;
+pub type AssignedTo = user::Id;
+pub type Resolution = String;
+pub type CreatedAt = time::OffsetDateTime;
+pub type UpdatedAt = time::OffsetDateTime;
+pub type ClosedAt = time::OffsetDateTime;
+pub type ClosedBy = user::Id;
+
+pub enum RepairTicketStatus {
+    Open = 1,
+    Triaged = 2,
+    Resolved = 3,
+    Dismissed = 4,
+}
+
+#[allow(dead_code)]
+pub enum RepairTicketPriority {
+    Low = 1,
+    Normal = 2,
+    High = 3,
+    Urgent = 4,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairTicket {
+    pub id: Id,
+    pub tool_id: ToolId,
+    pub reporter_id: ReporterId,
+    pub description: Description,
+    pub status: Status,
+    pub priority: Option<Priority>,
+    pub assigned_to: Option<AssignedTo>,
+    pub resolution: Option<Resolution>,
+    pub created_at: CreatedAt,
+    pub updated_at: UpdatedAt,
+    pub closed_at: Option<ClosedAt>,
+    pub closed_by: Option<ClosedBy>,
+}
@@ -0,0 +1,16 @@
+use super::{repair_ticket, tool_photo};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type TicketId = repair_ticket::Id;
+pub type PhotoKey = tool_photo::PhotoKey;
+pub type OriginalName = tool_photo::OriginalName;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairTicketPhoto {
+    pub id: Id,
+    pub ticket_id: TicketId,
+    pub photo_key: PhotoKey,
+    pub original_name: OriginalName,
+}
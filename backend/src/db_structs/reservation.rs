@@ -0,0 +1,32 @@
+use super::{rental, tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ToolId = tool::Id;
+pub type UserId = user::Id;
+pub type StartDate = time::OffsetDateTime;
+pub type EndDate = time::OffsetDateTime;
+pub type Status = i32;
+pub type CreatedAt = time::OffsetDateTime;
+pub type RentalId = rental::Id;
+pub type Quantity = tool::Quantity;
+
+pub enum ReservationStatus {
+    Active = 1,
+    Fulfilled = 2,
+    Cancelled = 3,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reservation {
+    pub id: Id,
+    pub tool_id: ToolId,
+    pub user_id: UserId,
+    pub start_date: StartDate,
+    pub end_date: EndDate,
+    pub status: Status,
+    pub created_at: CreatedAt,
+    pub rental_id: Option<RentalId>,
+    pub quantity: Quantity,
+}
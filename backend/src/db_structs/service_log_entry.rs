@@ -0,0 +1,21 @@
+use super::{maintenance_schedule, tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ToolId = tool::Id;
+pub type ScheduleId = maintenance_schedule::Id;
+pub type PerformedBy = user::Id;
+pub type Notes = String;
+pub type PerformedAt = time::OffsetDateTime;
+
+// an entry without a schedule counts as servicing for every schedule of the tool
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceLogEntry {
+    pub id: Id,
+    pub tool_id: ToolId,
+    pub schedule_id: Option<ScheduleId>,
+    pub performed_by: PerformedBy,
+    pub notes: Option<Notes>,
+    pub performed_at: PerformedAt,
+}
@@ -0,0 +1,16 @@
+use super::{service_log_entry, tool_photo};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type EntryId = service_log_entry::Id;
+pub type PhotoKey = tool_photo::PhotoKey;
+pub type OriginalName = tool_photo::OriginalName;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceLogPhoto {
+    pub id: Id,
+    pub entry_id: EntryId,
+    pub photo_key: PhotoKey,
+    pub original_name: OriginalName,
+}
@@ -0,0 +1,30 @@
+use super::{store, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type StoreId = store::Id;
+pub type OpenedBy = user::Id;
+pub type OpenedAt = time::OffsetDateTime;
+pub type Note = String;
+pub type Status = i32;
+pub type ClosedBy = user::Id;
+pub type ClosedAt = time::OffsetDateTime;
+
+pub enum StocktakeStatus {
+    Open = 1,
+    Closed = 2,
+    Cancelled = 3,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stocktake {
+    pub id: Id,
+    pub store_id: StoreId,
+    pub opened_by: OpenedBy,
+    pub opened_at: OpenedAt,
+    pub note: Option<Note>,
+    pub status: Status,
+    pub closed_by: Option<ClosedBy>,
+    pub closed_at: Option<ClosedAt>,
+}
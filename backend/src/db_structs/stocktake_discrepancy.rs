@@ -0,0 +1,19 @@
+use super::{stocktake, tool};
+use serde::{Deserialize, Serialize};
+
+pub type StocktakeId = stocktake::Id;
+pub type ToolId = tool::Id;
+pub type Scanned = bool;
+pub type ToolStatus = tool::Status;
+
+// Written when a stocktake closes. An available tool nobody scanned is missing (scanned is
+// false); a scanned tool that wasn't available, like one out on a rental, is unexpected.
+// tool_status is what the tool's status was at the time.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StocktakeDiscrepancy {
+    pub stocktake_id: StocktakeId,
+    pub tool_id: ToolId,
+    pub scanned: Scanned,
+    pub tool_status: ToolStatus,
+}
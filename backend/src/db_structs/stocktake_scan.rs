@@ -0,0 +1,16 @@
+use super::{stocktake, tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type StocktakeId = stocktake::Id;
+pub type ToolId = tool::Id;
+pub type ScannedBy = user::Id;
+pub type ScannedAt = time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StocktakeScan {
+    pub stocktake_id: StocktakeId,
+    pub tool_id: ToolId,
+    pub scanned_by: ScannedBy,
+    pub scanned_at: ScannedAt,
+}
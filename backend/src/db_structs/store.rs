@@ -10,6 +10,7 @@ pub type RentalInformation = String;
 pub type OtherInformation = String;
 pub type Code = String;
 pub type CreatedAt = time::OffsetDateTime;
+pub type Timezone = String;
 
 pub enum StoreStatus {
     Active = 1,
@@ -31,4 +32,5 @@ pub struct Store {
     pub other_information: Option<OtherInformation>,
     pub code: Code,
     pub created_at: CreatedAt,
+    pub timezone: Timezone,
 }
@@ -0,0 +1,19 @@
+use super::store;
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type StoreId = store::Id;
+pub type StartsOn = time::Date;
+pub type EndsOn = time::Date;
+pub type Name = String;
+
+// closed for the whole of every day from starts_on to ends_on, inclusive
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreHoliday {
+    pub id: Id,
+    pub store_id: StoreId,
+    pub starts_on: StartsOn,
+    pub ends_on: EndsOn,
+    pub name: Option<Name>,
+}
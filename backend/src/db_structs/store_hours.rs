@@ -0,0 +1,19 @@
+use super::store;
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type StoreId = store::Id;
+pub type Weekday = i32;
+pub type OpensAt = time::Time;
+pub type ClosesAt = time::Time;
+
+// weekdays count from monday = 1, times are local to the store's timezone
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreHours {
+    pub id: Id,
+    pub store_id: StoreId,
+    pub weekday: Weekday,
+    pub opens_at: OpensAt,
+    pub closes_at: ClosesAt,
+}
@@ -0,0 +1,43 @@
+use super::store;
+use serde::{Deserialize, Serialize};
+
+pub type StoreId = store::Id;
+pub type MaxOpenRentals = i32;
+pub type MaxToolsPerCheckOut = i32;
+pub type MinAccountAgeDays = i32;
+pub type BlockOverdue = bool;
+pub type MaxRentalHours = i32;
+pub type UpdatedAt = time::OffsetDateTime;
+
+// a missing limit means the store does not care
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorePolicy {
+    pub store_id: StoreId,
+    pub max_open_rentals: Option<MaxOpenRentals>,
+    pub max_tools_per_check_out: Option<MaxToolsPerCheckOut>,
+    pub min_account_age_days: Option<MinAccountAgeDays>,
+    pub block_overdue: BlockOverdue,
+    pub max_rental_hours: Option<MaxRentalHours>,
+    pub updated_at: UpdatedAt,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PolicyRule {
+    MaxOpenRentals,
+    MaxToolsPerCheckOut,
+    MinAccountAge,
+    NoOverdueRentals,
+    MaxRentalHours,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyViolation {
+    pub store_id: StoreId,
+    pub rule: PolicyRule,
+    pub limit: i64,
+    pub actual: i64,
+    pub message: String,
+}
@@ -8,7 +8,13 @@ pub type RentalHours = i32;
 pub type ShortDescription = String;
 pub type LongDescription = String;
 pub type Status = i32;
+pub type Deposit = i32;
+pub type Quantity = i32;
+pub type LocationRoom = String;
+pub type LocationShelf = String;
+pub type LocationBin = String;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ToolStatus {
     Available = 1,
     Rented = 2,
@@ -20,7 +26,7 @@ pub enum ToolStatus {
     Unknown = 8,
 }
 
-#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Tool {
     pub id: Id,
@@ -30,4 +36,9 @@ pub struct Tool {
     pub short_description: ShortDescription,
     pub long_description: Option<LongDescription>,
     pub status: Status,
+    pub deposit: Option<Deposit>,
+    pub quantity: Quantity,
+    pub location_room: Option<LocationRoom>,
+    pub location_shelf: Option<LocationShelf>,
+    pub location_bin: Option<LocationBin>,
 }
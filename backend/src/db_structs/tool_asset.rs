@@ -0,0 +1,37 @@
+use super::{tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type ToolId = tool::Id;
+pub type Brand = String;
+pub type Model = String;
+pub type SerialNumber = String;
+pub type AcquiredOn = time::Date;
+pub type AcquisitionSource = i32;
+pub type DonatedBy = user::Id;
+pub type ReplacementValue = i32;
+pub type WarrantyExpiresOn = time::Date;
+pub type UpdatedAt = time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToolAcquisitionSource {
+    Purchased = 1,
+    Donated = 2,
+}
+
+// What the library knows about a tool as an asset, for insurance and loss reports. Kept
+// apart from the tool itself, and only there once someone fills it in. A pooled tool's
+// replacement value is for one unit.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAsset {
+    pub tool_id: ToolId,
+    pub brand: Option<Brand>,
+    pub model: Option<Model>,
+    pub serial_number: Option<SerialNumber>,
+    pub acquired_on: Option<AcquiredOn>,
+    pub acquisition_source: Option<AcquisitionSource>,
+    pub donated_by: Option<DonatedBy>,
+    pub replacement_value: Option<ReplacementValue>,
+    pub warranty_expires_on: Option<WarrantyExpiresOn>,
+    pub updated_at: UpdatedAt,
+}
@@ -0,0 +1,32 @@
+use super::{tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ToolId = tool::Id;
+pub type UserId = user::Id;
+pub type Position = i32;
+pub type Status = i32;
+pub type CreatedAt = time::OffsetDateTime;
+pub type ReadyAt = time::OffsetDateTime;
+pub type ExpiresAt = time::OffsetDateTime;
+
+pub enum HoldStatus {
+    Waiting = 1,
+    Ready = 2,
+    Fulfilled = 3,
+    Expired = 4,
+    Cancelled = 5,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolHold {
+    pub id: Id,
+    pub tool_id: ToolId,
+    pub user_id: UserId,
+    pub position: Position,
+    pub status: Status,
+    pub created_at: CreatedAt,
+    pub ready_at: Option<ReadyAt>,
+    pub expires_at: Option<ExpiresAt>,
+}
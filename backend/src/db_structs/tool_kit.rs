@@ -0,0 +1,20 @@
+use super::{store, tool};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type RealId = tool::RealId;
+pub type StoreId = store::Id;
+pub type ShortDescription = tool::ShortDescription;
+pub type LongDescription = tool::LongDescription;
+pub type CreatedAt = time::OffsetDateTime;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolKit {
+    pub id: Id,
+    pub real_id: RealId,
+    pub store_id: StoreId,
+    pub short_description: ShortDescription,
+    pub long_description: Option<LongDescription>,
+    pub created_at: CreatedAt,
+}
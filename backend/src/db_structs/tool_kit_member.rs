@@ -0,0 +1,12 @@
+use super::{tool, tool_kit};
+use serde::{Deserialize, Serialize};
+
+pub type KitId = tool_kit::Id;
+pub type ToolId = tool::Id;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolKitMember {
+    pub kit_id: KitId,
+    pub tool_id: ToolId,
+}
@@ -0,0 +1,40 @@
+use super::{tool, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type ToolId = tool::Id;
+pub type FromStatus = tool::Status;
+pub type ToStatus = tool::Status;
+pub type Action = i32;
+pub type Reason = String;
+pub type ChangedBy = user::Id;
+pub type ChangedAt = time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusAction {
+    Created = 1,
+    Edited = 2,
+    CheckedOut = 3,
+    CheckedIn = 4,
+    Found = 5,
+    MarkedLost = 6,
+    StoreClosed = 7,
+    TicketOpened = 8,
+    Transferred = 9,
+    Stocktake = 10,
+}
+
+// from_status is missing for the first entry, when the tool was created;
+// changed_by is missing when the change was made by the server itself
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolStatusChange {
+    pub id: Id,
+    pub tool_id: ToolId,
+    pub from_status: Option<FromStatus>,
+    pub to_status: ToStatus,
+    pub action: Action,
+    pub reason: Option<Reason>,
+    pub changed_by: Option<ChangedBy>,
+    pub changed_at: ChangedAt,
+}
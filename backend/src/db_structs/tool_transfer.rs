@@ -0,0 +1,35 @@
+use super::{store, user};
+use serde::{Deserialize, Serialize};
+
+pub type Id = i32;
+pub type FromStoreId = store::Id;
+pub type ToStoreId = store::Id;
+pub type ProposedBy = user::Id;
+pub type Note = String;
+pub type Status = i32;
+pub type CreatedAt = time::OffsetDateTime;
+pub type DecidedBy = user::Id;
+pub type DecidedAt = time::OffsetDateTime;
+pub type DecisionNote = String;
+
+pub enum TransferStatus {
+    Pending = 1,
+    Accepted = 2,
+    Declined = 3,
+    Cancelled = 4,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolTransfer {
+    pub id: Id,
+    pub from_store_id: FromStoreId,
+    pub to_store_id: ToStoreId,
+    pub proposed_by: ProposedBy,
+    pub note: Option<Note>,
+    pub status: Status,
+    pub created_at: CreatedAt,
+    pub decided_by: Option<DecidedBy>,
+    pub decided_at: Option<DecidedAt>,
+    pub decision_note: Option<DecisionNote>,
+}
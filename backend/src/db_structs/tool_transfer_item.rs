@@ -0,0 +1,12 @@
+use super::{tool, tool_transfer};
+use serde::{Deserialize, Serialize};
+
+pub type TransferId = tool_transfer::Id;
+pub type ToolId = tool::Id;
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolTransferItem {
+    pub transfer_id: TransferId,
+    pub tool_id: ToolId,
+}
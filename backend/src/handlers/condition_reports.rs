@@ -0,0 +1,254 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{condition_report, condition_report_photo, rental, tool, tool_photo, user};
+use crate::handlers::photos;
+use crate::queries::condition_reports;
+use crate::tool_lifecycle;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConditionReportData {
+    pub tool_id: condition_report::ToolId,
+    pub grade: condition_report::Grade,
+    pub notes: Option<condition_report::Notes>,
+    pub photo_keys: Option<Vec<tool_photo::PhotoKey>>,
+    pub status: Option<tool::Status>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportWithPhotos {
+    pub id: condition_report::Id,
+    pub tool_id: condition_report::ToolId,
+    pub rental_id: Option<condition_report::RentalId>,
+    pub reporter_id: condition_report::ReporterId,
+    pub reporter_username: user::Username,
+    pub grade: condition_report::Grade,
+    pub notes: Option<condition_report::Notes>,
+    pub tool_status: condition_report::ToolStatus,
+    pub created_at: condition_report::CreatedAt,
+    pub photos: Vec<condition_report_photo::ConditionReportPhoto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportsResponse {
+    pub reports: Vec<ReportWithPhotos>,
+}
+
+// a returned tool can only be sent back to the shelf, or pulled for repair
+pub fn is_check_in_status(status: tool::Status) -> bool {
+    status == tool::ToolStatus::Available as i32
+        || status == tool::ToolStatus::Maintenance as i32
+        || status == tool::ToolStatus::Broken as i32
+}
+
+pub fn verify_reports(
+    reports: &[ConditionReportData],
+    tools: &[tool::Tool],
+) -> Result<(), common::ErrResponse> {
+    for (i, report) in reports.iter().enumerate() {
+        let tool = match tools.iter().find(|t| t.id == report.tool_id) {
+            Some(t) => t,
+            None => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "Condition report is for a tool that is not being checked in",
+                ));
+            }
+        };
+
+        if reports[..i].iter().any(|r| r.tool_id == report.tool_id) {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Only one condition report per tool",
+            ));
+        }
+
+        common::verify_payload_integer_range(
+            report.grade,
+            condition_report::BEST_GRADE,
+            condition_report::WORST_GRADE,
+        )?;
+        common::none_or_verify_payload_text_length(
+            report.notes.as_deref(),
+            1,
+            common::MAX_CONDITION_REPORT_NOTES_LENGTH,
+        )?;
+        common::verify_payload_integer_range(
+            report
+                .photo_keys
+                .as_ref()
+                .map_or(0, |p| p.len())
+                .try_into()
+                .unwrap_or(i32::MAX),
+            0,
+            common::MAX_CONDITION_REPORT_PHOTOS_LENGTH,
+        )?;
+
+        if report.status.is_some_and(|s| !is_check_in_status(s)) {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Checked in tools can only be made available, or sent to maintenance or broken",
+            ));
+        }
+
+        // a retired tool stays retired, whatever its condition
+        if let Some(status) = report.status.filter(|s| *s != tool.status) {
+            if !tool_lifecycle::can_transition(
+                tool.status,
+                status,
+                tool_lifecycle::check_in_action(tool.status),
+            ) {
+                return Err(common::ErrResponse::new(
+                    StatusCode::CONFLICT,
+                    "ERR_CONFLICT",
+                    &format!(
+                        "Condition report cannot change the status of {}",
+                        tool.real_id
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn record_report(
+    report: ConditionReportData,
+    rental_id: Option<rental::Id>,
+    reporter_id: user::Id,
+    tool_status: tool::Status,
+    conn: &mut sqlx::PgConnection,
+) -> Result<condition_report::Id, common::ErrResponse> {
+    let inserted = match condition_reports::insert(
+        report.tool_id,
+        rental_id,
+        reporter_id,
+        report.grade,
+        report.notes,
+        tool_status,
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let photo_keys = report.photo_keys.unwrap_or_default();
+    if photo_keys.is_empty() {
+        return Ok(inserted.id);
+    }
+
+    let taken = photos::claim_unattached(photo_keys, &mut *conn).await?;
+    if let Err(e) = condition_reports::insert_photos(inserted.id, taken, &mut *conn).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    Ok(inserted.id)
+}
+
+async fn select_with_photos(
+    tool_ids: Vec<tool::Id>,
+    rental_ids: Vec<rental::Id>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<ReportWithPhotos>, common::ErrResponse> {
+    let reports = match condition_reports::select(tool_ids, rental_ids, db).await {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut photos =
+        match condition_reports::select_photos(reports.iter().map(|r| r.id).collect(), db).await {
+            Ok(p) => p,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    Ok(reports
+        .into_iter()
+        .map(|r| {
+            let (own, rest) = photos.drain(..).partition(|p| p.report_id == r.id);
+            photos = rest;
+            ReportWithPhotos {
+                id: r.id,
+                tool_id: r.tool_id,
+                rental_id: r.rental_id,
+                reporter_id: r.reporter_id,
+                reporter_username: r.reporter_username,
+                grade: r.grade,
+                notes: r.notes,
+                tool_status: r.tool_status,
+                created_at: r.created_at,
+                photos: own,
+            }
+        })
+        .collect())
+}
+
+pub async fn get_by_tool(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReportsResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    let reports = select_with_photos(vec![tool_id], vec![], &state.db).await?;
+    Ok(Json(ReportsResponse { reports }))
+}
+
+pub async fn get_by_rental(
+    claims: Claims,
+    Path(rental_id): Path<rental::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReportsResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    let reports = select_with_photos(vec![], vec![rental_id], &state.db).await?;
+    Ok(Json(ReportsResponse { reports }))
+}
@@ -0,0 +1,217 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{condition_report, deposit, rental, tool, user};
+use crate::handlers::condition_reports::ConditionReportData;
+use crate::queries::deposits::{self, DepositWithText};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositSettlementData {
+    pub tool_id: tool::Id,
+    pub kept_amount: deposit::KeptAmount,
+    pub reason: Option<deposit::Reason>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreDepositParams {
+    pub statuses: Option<Vec<deposit::Status>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreDepositsResponse {
+    pub deposits: Vec<DepositWithText>,
+    pub total_amount: i64,
+    pub total_kept: i64,
+}
+
+// keeping any of a deposit has to be explained, and backed by a condition report
+pub fn verify_settlements(
+    settlements: &[DepositSettlementData],
+    tool_ids: &[tool::Id],
+    reports: &[ConditionReportData],
+) -> Result<(), common::ErrResponse> {
+    for (i, settlement) in settlements.iter().enumerate() {
+        if !tool_ids.contains(&settlement.tool_id) {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Deposit settlement is for a tool that is not being checked in",
+            ));
+        }
+
+        if settlements[..i]
+            .iter()
+            .any(|s| s.tool_id == settlement.tool_id)
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Only one deposit settlement per tool",
+            ));
+        }
+
+        common::verify_payload_integer_range(settlement.kept_amount, 0, common::MAX_LEDGER_AMOUNT)?;
+        common::none_or_verify_payload_text_length(
+            settlement.reason.as_deref(),
+            1,
+            common::MAX_DEPOSIT_REASON_LENGTH,
+        )?;
+
+        if settlement.kept_amount > 0 {
+            if settlement.reason.is_none() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "A reason is needed to keep a deposit",
+                ));
+            }
+            if !reports.iter().any(|r| r.tool_id == settlement.tool_id) {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "A condition report is needed to keep a deposit",
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// every deposit held against a closed rental is settled; anything not mentioned is released
+pub async fn settle_deposits(
+    closed: &[rental::Rental],
+    settlements: Vec<DepositSettlementData>,
+    report_ids: &[(tool::Id, condition_report::Id)],
+    settled_by: user::Id,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<deposit::Deposit>, common::ErrResponse> {
+    let rental_ids: Vec<rental::Id> = closed.iter().map(|r| r.id).collect();
+    let held: Vec<deposit::Deposit> =
+        match deposits::select_by_rental_ids(rental_ids, &mut *conn).await {
+            Ok(d) => d
+                .into_iter()
+                .filter(|d| d.status == deposit::DepositStatus::Held as i32)
+                .collect(),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    for settlement in &settlements {
+        match held.iter().find(|d| d.tool_id == settlement.tool_id) {
+            Some(d) if settlement.kept_amount > d.amount => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "Cannot keep more than the deposit",
+                ));
+            }
+            Some(_) => {}
+            None => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "No deposit is held for the tool",
+                ));
+            }
+        }
+    }
+
+    let now = time::OffsetDateTime::now_utc();
+    let mut settled = Vec::with_capacity(held.len());
+    for d in held {
+        let settlement = settlements.iter().find(|s| s.tool_id == d.tool_id);
+        let kept_amount = settlement.map_or(0, |s| s.kept_amount);
+        let status = if kept_amount == 0 {
+            deposit::DepositStatus::Released
+        } else if kept_amount == d.amount {
+            deposit::DepositStatus::Kept
+        } else {
+            deposit::DepositStatus::PartiallyKept
+        };
+
+        match deposits::settle(
+            deposits::SettleData {
+                id: d.id,
+                status: status as i32,
+                kept_amount,
+                reason: settlement.and_then(|s| s.reason.clone()),
+                condition_report_id: report_ids
+                    .iter()
+                    .find(|(tool_id, _)| *tool_id == d.tool_id)
+                    .map(|(_, id)| *id),
+                settled_at: now,
+                settled_by,
+            },
+            &mut *conn,
+        )
+        .await
+        {
+            Ok(d) => settled.push(d),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        }
+    }
+
+    Ok(settled)
+}
+
+pub async fn get_by_store(
+    claims: Claims,
+    Path(store_id): Path<i32>,
+    Query(params): Query<StoreDepositParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StoreDepositsResponse>, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) && !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    // outstanding deposits, unless asked otherwise
+    let statuses = params
+        .statuses
+        .unwrap_or(vec![deposit::DepositStatus::Held as i32]);
+
+    match deposits::select_by_store(store_id, statuses, &state.db).await {
+        Ok(deposits) => {
+            let total_amount = deposits.iter().map(|d| i64::from(d.amount)).sum();
+            let total_kept = deposits
+                .iter()
+                .map(|d| i64::from(d.kept_amount.unwrap_or_default()))
+                .sum();
+            Ok(Json(StoreDepositsResponse {
+                deposits,
+                total_amount,
+                total_kept,
+            }))
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
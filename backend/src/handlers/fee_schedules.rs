@@ -0,0 +1,88 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::fee_schedule;
+use crate::queries::fee_schedules;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettableFeeSchedule {
+    pub grace_hours: fee_schedule::GraceHours,
+    pub fee_per_day: fee_schedule::FeePerDay,
+    pub fee_cap: Option<fee_schedule::FeeCap>,
+    pub max_balance: Option<fee_schedule::MaxBalance>,
+}
+
+pub async fn get_by_store(
+    Path(store_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<fee_schedule::FeeSchedule>, common::ErrResponse> {
+    match fee_schedules::select_by_store_ids(vec![store_id], &state.db).await {
+        Ok(mut s) => {
+            if s.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store has no fee schedule",
+                ));
+            }
+            Ok(Json(s.remove(0)))
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn update(
+    claims: Claims,
+    Path(store_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SettableFeeSchedule>,
+) -> Result<Json<fee_schedule::FeeSchedule>, common::ErrResponse> {
+    if !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a store rep",
+        ));
+    }
+
+    common::verify_payload_integer_range(payload.grace_hours, 0, common::MAX_FEE_GRACE_HOURS)?;
+    common::verify_payload_integer_range(payload.fee_per_day, 0, common::MAX_LEDGER_AMOUNT)?;
+    common::none_or_verify_payload_integer_range(payload.fee_cap, 0, common::MAX_LEDGER_AMOUNT)?;
+    common::none_or_verify_payload_integer_range(
+        payload.max_balance,
+        0,
+        common::MAX_LEDGER_AMOUNT,
+    )?;
+
+    match fee_schedules::upsert(
+        fee_schedule::FeeSchedule {
+            store_id,
+            grace_hours: payload.grace_hours,
+            fee_per_day: payload.fee_per_day,
+            fee_cap: payload.fee_cap,
+            max_balance: payload.max_balance,
+            updated_at: time::OffsetDateTime::now_utc(),
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(s) => Ok(Json(s)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
@@ -0,0 +1,151 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::tool;
+use crate::labels::{self, Label, LabelLayout, LabelSheet, PageSize};
+use crate::qr_codes::ScannedCode;
+use crate::queries::{stores, tools};
+use crate::AppState;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelParams {
+    pub layout: Option<LabelLayout>,
+    pub columns: Option<i32>,
+    pub rows: Option<i32>,
+    pub page_size: Option<PageSize>,
+    pub real_ids: Option<Vec<tool::RealId>>,
+    pub statuses: Option<Vec<tool::Status>>,
+}
+
+pub async fn get_store_labels(
+    claims: Claims,
+    Path(store_id): Path<i32>,
+    Query(params): Query<LabelParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) && !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let layout = params.layout.unwrap_or_default();
+    let sheet = match layout {
+        LabelLayout::Grid => {
+            common::verify_payload_integer_range(
+                params.columns.unwrap_or(3),
+                1,
+                common::MAX_LABEL_GRID_COLUMNS,
+            )?;
+            common::verify_payload_integer_range(
+                params.rows.unwrap_or(10),
+                1,
+                common::MAX_LABEL_GRID_ROWS,
+            )?;
+            LabelSheet::grid(
+                params.columns.unwrap_or(3) as usize,
+                params.rows.unwrap_or(10) as usize,
+                params.page_size.unwrap_or_default(),
+            )
+        }
+        _ => LabelSheet::from_layout(layout),
+    };
+
+    let store = match stores::select_by_ids(vec![store_id], &state.db).await {
+        Ok(mut s) => {
+            if s.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store not found",
+                ));
+            }
+            s.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let found = match tools::select(
+        tools::SelectParams {
+            ids: vec![],
+            term: String::new(),
+            statuses: params.statuses.unwrap_or_default(),
+            store_ids: vec![store_id],
+            category_ids: vec![],
+            match_all_categories: false,
+            real_ids: params.real_ids.unwrap_or_default(),
+            asset_store_ids: vec![],
+            min_available: 0,
+            order_by: tools::OrderBy::Id,
+            offset: 0,
+            limit: common::MAX_LABEL_SHEET_TOOLS,
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if found.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "No tools to label",
+        ));
+    }
+
+    let labels: Vec<Label> = found
+        .into_iter()
+        .map(|t| Label {
+            payload: ScannedCode::Tool {
+                store_id: t.store_id,
+                real_id: t.real_id.clone(),
+            }
+            .payload(),
+            real_id: t.real_id,
+            short_description: t.short_description,
+            store_name: store.name.clone(),
+        })
+        .collect();
+
+    let title = format!("{} labels", store.name);
+    let data = match labels::render_sheets(&title, &sheet, &labels) {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_LOGIC",
+                &e,
+            ))
+        }
+    };
+
+    let attachment_header = format!("attachment; filename=\"store-{}-labels.pdf\"", store_id);
+    let headers = [
+        (header::CONTENT_TYPE, "application/pdf"),
+        (header::CONTENT_DISPOSITION, attachment_header.as_str()),
+    ];
+    Ok((headers, data).into_response())
+}
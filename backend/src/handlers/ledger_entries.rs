@@ -0,0 +1,194 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{ledger_entry, user};
+use crate::queries::ledger_entries;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewEntryData {
+    pub store_id: ledger_entry::StoreId,
+    pub kind: ledger_entry::Kind,
+    pub amount: ledger_entry::Amount,
+    pub rental_id: Option<ledger_entry::RentalId>,
+    pub note: Option<ledger_entry::Note>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntriesResponse {
+    pub entries: Vec<ledger_entry::LedgerEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceResponse {
+    pub user_id: user::Id,
+    pub balance: i64,
+    pub accruing_overdue_fees: i64,
+}
+
+fn can_see_ledger(claims: &Claims, user_id: user::Id) -> bool {
+    claims.is_user_admin()
+        || claims.is_any_tool_manager()
+        || claims.subject_as_user_id() == Some(user_id)
+}
+
+pub async fn get_by_user(
+    claims: Claims,
+    Path(user_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<EntriesResponse>, common::ErrResponse> {
+    if !can_see_ledger(&claims, user_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot see this ledger",
+        ));
+    }
+
+    match ledger_entries::select_by_user_id(user_id, &state.db).await {
+        Ok(entries) => Ok(Json(EntriesResponse { entries })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn get_balance(
+    claims: Claims,
+    Path(user_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<BalanceResponse>, common::ErrResponse> {
+    if !can_see_ledger(&claims, user_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot see this ledger",
+        ));
+    }
+
+    let now = time::OffsetDateTime::now_utc();
+    let balance_future = ledger_entries::select_balance(user_id, &state.db);
+    let accruing_future = ledger_entries::select_accruing_overdue_fees(user_id, now, &state.db);
+
+    match tokio::try_join!(balance_future, accruing_future) {
+        Ok((balance, accruing_overdue_fees)) => Ok(Json(BalanceResponse {
+            user_id,
+            balance,
+            accruing_overdue_fees,
+        })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+// overdue fees are only ever charged by check in; everything else is entered by hand
+pub async fn create_new(
+    claims: Claims,
+    Path(user_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewEntryData>,
+) -> Result<Json<ledger_entry::LedgerEntry>, common::ErrResponse> {
+    if !claims.is_tool_manager(payload.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let created_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    common::verify_payload_integer_range(
+        payload.amount,
+        -common::MAX_LEDGER_AMOUNT,
+        common::MAX_LEDGER_AMOUNT,
+    )?;
+    common::none_or_verify_payload_text_length(
+        payload.note.as_deref(),
+        0,
+        common::MAX_LEDGER_NOTE_LENGTH,
+    )?;
+
+    let has_note = payload
+        .note
+        .as_deref()
+        .is_some_and(|n| !n.trim().is_empty());
+    let amount = if payload.kind == ledger_entry::LedgerEntryKind::Adjustment as i32 {
+        if payload.amount == 0 || !has_note {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "An adjustment needs a non-zero amount and a note",
+            ));
+        }
+        payload.amount
+    } else if payload.kind == ledger_entry::LedgerEntryKind::Waiver as i32 {
+        if payload.amount <= 0 || !has_note {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "A waiver needs a positive amount and a note",
+            ));
+        }
+        -payload.amount
+    } else if payload.kind == ledger_entry::LedgerEntryKind::Payment as i32 {
+        if payload.amount <= 0 {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "A payment needs a positive amount",
+            ));
+        }
+        -payload.amount
+    } else {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Only adjustments, waivers and payments can be entered by hand",
+        ));
+    };
+
+    match ledger_entries::insert(
+        ledger_entries::InsertData {
+            user_id,
+            store_id: payload.store_id,
+            kind: payload.kind,
+            amount,
+            rental_id: payload.rental_id,
+            note: payload.note,
+            created_by: Some(created_by),
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(entry) => Ok(Json(entry)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
@@ -13,6 +13,11 @@ use std::sync::Arc;
 #[serde(rename_all = "camelCase")]
 pub struct SettableLibraryInfo {
     pub name: Option<library_information::Name>,
+    pub overdue_reminder_interval_days: Option<library_information::OverdueReminderIntervalDays>,
+    pub overdue_lost_after_days: Option<library_information::OverdueLostAfterDays>,
+    pub overdue_grievance_after_days: Option<library_information::OverdueGrievanceAfterDays>,
+    pub no_overdue_lost_after_days: Option<bool>,
+    pub no_overdue_grievance_after_days: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -29,6 +34,19 @@ pub struct AllStatuses {
     pub tools: Vec<common::Status>,
     pub grievances: Vec<common::Status>,
     pub permissions: Vec<common::Status>,
+    pub holds: Vec<common::Status>,
+    pub reservations: Vec<common::Status>,
+    pub extensions: Vec<common::Status>,
+    pub condition_grades: Vec<common::Status>,
+    pub loan_channels: Vec<common::Status>,
+    pub ledger_entry_kinds: Vec<common::Status>,
+    pub deposits: Vec<common::Status>,
+    pub tool_status_actions: Vec<common::Status>,
+    pub repair_tickets: Vec<common::Status>,
+    pub repair_ticket_priorities: Vec<common::Status>,
+    pub tool_transfers: Vec<common::Status>,
+    pub stocktakes: Vec<common::Status>,
+    pub tool_acquisition_sources: Vec<common::Status>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -62,24 +80,69 @@ pub async fn get_all_statuses(
     let tool_future = crate::queries::tools::select_statuses(&state.db);
     let grievance_future = crate::queries::grievances::select_statuses(&state.db);
     let permission_future = crate::queries::permissions::select_statuses(&state.db);
+    let hold_future = crate::queries::tool_holds::select_statuses(&state.db);
+    let reservation_future = crate::queries::reservations::select_statuses(&state.db);
+    let extension_future = crate::queries::rental_extensions::select_statuses(&state.db);
+    let grade_future = crate::queries::condition_reports::select_grades(&state.db);
+    let channel_future = crate::queries::loans::select_channels(&state.db);
+    let ledger_kind_future = crate::queries::ledger_entries::select_kinds(&state.db);
+    let deposit_future = crate::queries::deposits::select_statuses(&state.db);
+    let tool_action_future = crate::queries::tool_status_history::select_actions(&state.db);
+    let ticket_future = crate::queries::repair_tickets::select_statuses(&state.db);
+    let ticket_priority_future = crate::queries::repair_tickets::select_priorities(&state.db);
+    let transfer_future = crate::queries::tool_transfers::select_statuses(&state.db);
+    let stocktake_future = crate::queries::stocktakes::select_statuses(&state.db);
+    let acquisition_source_future =
+        crate::queries::tool_assets::select_acquisition_sources(&state.db);
 
-    let (store_statuses, user_statuses, tool_statuses, grievance_statuses, permission_statuses) =
-        match tokio::try_join!(
-            store_future,
-            user_future,
-            tool_future,
-            grievance_future,
-            permission_future,
-        ) {
-            Ok(res) => res,
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
-            }
-        };
+    let (
+        store_statuses,
+        user_statuses,
+        tool_statuses,
+        grievance_statuses,
+        permission_statuses,
+        hold_statuses,
+        reservation_statuses,
+        extension_statuses,
+        condition_grades,
+        loan_channels,
+        ledger_entry_kinds,
+        deposit_statuses,
+        tool_status_actions,
+        ticket_statuses,
+        ticket_priorities,
+        transfer_statuses,
+        stocktake_statuses,
+        tool_acquisition_sources,
+    ) = match tokio::try_join!(
+        store_future,
+        user_future,
+        tool_future,
+        grievance_future,
+        permission_future,
+        hold_future,
+        reservation_future,
+        extension_future,
+        grade_future,
+        channel_future,
+        ledger_kind_future,
+        deposit_future,
+        tool_action_future,
+        ticket_future,
+        ticket_priority_future,
+        transfer_future,
+        stocktake_future,
+        acquisition_source_future,
+    ) {
+        Ok(res) => res,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
 
     Ok(Json(AllStatuses {
         stores: store_statuses,
@@ -87,6 +150,19 @@ pub async fn get_all_statuses(
         tools: tool_statuses,
         grievances: grievance_statuses,
         permissions: permission_statuses,
+        holds: hold_statuses,
+        reservations: reservation_statuses,
+        extensions: extension_statuses,
+        condition_grades,
+        loan_channels,
+        ledger_entry_kinds,
+        deposits: deposit_statuses,
+        tool_status_actions,
+        repair_tickets: ticket_statuses,
+        repair_ticket_priorities: ticket_priorities,
+        tool_transfers: transfer_statuses,
+        stocktakes: stocktake_statuses,
+        tool_acquisition_sources,
     }))
 }
 
@@ -129,13 +205,38 @@ pub async fn update_info(
         ));
     }
 
-    common::verify_payload_text_length(
-        payload.name.as_deref().unwrap_or_default(),
+    common::none_or_verify_payload_text_length(
+        payload.name.as_deref(),
         1,
         common::MAX_LIBRARY_NAME_LENGTH,
     )?;
+    common::none_or_verify_payload_integer_range(
+        payload.overdue_reminder_interval_days,
+        1,
+        common::MAX_OVERDUE_ESCALATION_DAYS,
+    )?;
+    common::none_or_verify_payload_integer_range(
+        payload.overdue_lost_after_days,
+        1,
+        common::MAX_OVERDUE_ESCALATION_DAYS,
+    )?;
+    common::none_or_verify_payload_integer_range(
+        payload.overdue_grievance_after_days,
+        1,
+        common::MAX_OVERDUE_ESCALATION_DAYS,
+    )?;
 
-    match library::update_information(payload.name, &state.db).await {
+    match library::update_information(
+        payload.name,
+        payload.overdue_reminder_interval_days,
+        payload.overdue_lost_after_days,
+        payload.overdue_grievance_after_days,
+        payload.no_overdue_lost_after_days.unwrap_or_default(),
+        payload.no_overdue_grievance_after_days.unwrap_or_default(),
+        &state.db,
+    )
+    .await
+    {
         Ok(_) => Ok(Json(common::NoData {})),
         Err(e) => Err(common::ErrResponse::new(
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -0,0 +1,368 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{deposit, loan, rental, user};
+use crate::handlers::condition_reports::ConditionReportData;
+use crate::handlers::deposits::DepositSettlementData;
+use crate::handlers::rentals::{self, CheckInData, RentalWithText};
+use crate::queries::{deposits, library, loans, users};
+use crate::AppState;
+use axum::extract::{Json, Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoanWithRentals {
+    pub id: loan::Id,
+    pub receipt_number: loan::ReceiptNumber,
+    pub renter_id: loan::RenterId,
+    pub renter_username: user::Username,
+    pub performed_by: loan::PerformedBy,
+    pub performed_by_username: user::Username,
+    pub channel: loan::Channel,
+    pub created_at: loan::CreatedAt,
+    pub rentals: Vec<RentalWithText>,
+    pub deposits: Vec<deposit::Deposit>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ReceiptFormat {
+    #[default]
+    Text,
+    Html,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptParams {
+    pub format: Option<ReceiptFormat>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoanCheckInData {
+    pub reports: Option<Vec<ConditionReportData>>,
+    pub deposits: Option<Vec<DepositSettlementData>>,
+}
+
+async fn select_with_rentals(
+    loan_id: loan::Id,
+    db: &sqlx::PgPool,
+) -> Result<LoanWithRentals, common::ErrResponse> {
+    let loan = match loans::select_by_id(loan_id, db).await {
+        Ok(Some(l)) => l,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Loan not found",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let loan_rentals = match crate::queries::rentals::select_by_loan_id(loan.id, db).await {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    let rentals = rentals::with_text(&loan_rentals, db).await?;
+
+    let rental_ids: Vec<rental::Id> = loan_rentals.iter().map(|r| r.id).collect();
+    let deposits = match deposits::select_by_rental_ids(rental_ids, db).await {
+        Ok(d) => d,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let people = match users::select_by_ids(vec![loan.renter_id, loan.performed_by], db).await {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    let username_of = |id: user::Id| {
+        people
+            .iter()
+            .find(|u| u.id == id)
+            .map(|u| u.username.clone())
+            .unwrap_or_default()
+    };
+
+    Ok(LoanWithRentals {
+        id: loan.id,
+        receipt_number: loan.receipt_number,
+        renter_id: loan.renter_id,
+        renter_username: username_of(loan.renter_id),
+        performed_by: loan.performed_by,
+        performed_by_username: username_of(loan.performed_by),
+        channel: loan.channel,
+        created_at: loan.created_at,
+        rentals,
+        deposits,
+    })
+}
+
+pub async fn get_by_id(
+    claims: Claims,
+    Path(loan_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<LoanWithRentals>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    let loan = select_with_rentals(loan_id, &state.db).await?;
+    Ok(Json(loan))
+}
+
+pub async fn get_receipt(
+    claims: Claims,
+    Path(loan_id): Path<i32>,
+    Query(params): Query<ReceiptParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    let loan = select_with_rentals(loan_id, &state.db).await?;
+
+    let library_name = match library::select_information(&state.db).await {
+        Ok(info) => info.map(|i| i.name).unwrap_or_default(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let channel = match loans::select_channels(&state.db).await {
+        Ok(channels) => channels
+            .into_iter()
+            .find(|c| c.id == loan.channel)
+            .map(|c| c.name.replace('_', " "))
+            .unwrap_or_default(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let (content_type, body) = match params.format.unwrap_or_default() {
+        ReceiptFormat::Text => (
+            "text/plain; charset=utf-8",
+            receipt_text(&loan, &library_name, &channel),
+        ),
+        ReceiptFormat::Html => (
+            "text/html; charset=utf-8",
+            receipt_html(&loan, &library_name, &channel),
+        ),
+    };
+
+    Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+pub async fn check_in(
+    claims: Claims,
+    Path(loan_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<LoanCheckInData>,
+) -> Result<Json<LoanWithRentals>, common::ErrResponse> {
+    let loan = select_with_rentals(loan_id, &state.db).await?;
+
+    let rental_ids: Vec<rental::Id> = loan
+        .rentals
+        .iter()
+        .filter(|r| r.end_date.is_none())
+        .map(|r| r.id)
+        .collect();
+    if rental_ids.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Everything on the loan has already been checked in",
+        ));
+    }
+
+    let _ = rentals::check_in(
+        claims,
+        State(state.clone()),
+        Json(CheckInData {
+            tool_ids: vec![],
+            rental_ids,
+            scans: None,
+            reports: payload.reports,
+            deposits: payload.deposits,
+        }),
+    )
+    .await?;
+
+    let loan = select_with_rentals(loan_id, &state.db).await?;
+    Ok(Json(loan))
+}
+
+fn format_date(date: time::OffsetDateTime) -> String {
+    let date = date.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02} UTC",
+        date.year(),
+        date.month() as u8,
+        date.day(),
+        date.hour(),
+        date.minute(),
+    )
+}
+
+// amounts are stored in cents
+fn format_amount(amount: i32) -> String {
+    format!("{}.{:02}", amount / 100, amount % 100)
+}
+
+fn deposit_text(d: &deposit::Deposit) -> String {
+    let kept = d.kept_amount.unwrap_or_default();
+    if d.status == deposit::DepositStatus::Held as i32 {
+        format!("deposit of {} held", format_amount(d.amount))
+    } else if d.status == deposit::DepositStatus::Released as i32 {
+        format!("deposit of {} released", format_amount(d.amount))
+    } else {
+        format!(
+            "deposit of {}, {} kept: {}",
+            format_amount(d.amount),
+            format_amount(kept),
+            d.reason.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+// pooled tools go out a few units at a time
+fn quantity_text(quantity: rental::Quantity) -> String {
+    if quantity > 1 {
+        format!(" x{}", quantity)
+    } else {
+        String::new()
+    }
+}
+
+fn item_count(loan: &LoanWithRentals) -> i64 {
+    loan.rentals.iter().map(|r| i64::from(r.quantity)).sum()
+}
+
+fn receipt_text(loan: &LoanWithRentals, library_name: &str, channel: &str) -> String {
+    let mut lines = vec![
+        library_name.to_string(),
+        format!("Receipt {}", loan.receipt_number),
+        format!("Date: {}", format_date(loan.created_at)),
+        format!("Borrower: {}", loan.renter_username),
+        format!(
+            "Checked out by: {} ({})",
+            loan.performed_by_username, channel
+        ),
+        String::new(),
+    ];
+
+    for r in &loan.rentals {
+        lines.push(format!(
+            "{}  {}{}",
+            r.tool_real_id,
+            r.tool_short_description,
+            quantity_text(r.quantity)
+        ));
+        lines.push(format!("    from {}", r.store_name));
+        match r.end_date {
+            Some(end_date) => lines.push(format!("    returned {}", format_date(end_date))),
+            None => lines.push(format!("    due {}", format_date(r.due_date))),
+        }
+        if let Some(d) = loan.deposits.iter().find(|d| d.rental_id == r.id) {
+            lines.push(format!("    {}", deposit_text(d)));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push(format!("{} item(s)", item_count(loan)));
+    lines.join("\n") + "\n"
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn receipt_html(loan: &LoanWithRentals, library_name: &str, channel: &str) -> String {
+    let rows: String = loan
+        .rentals
+        .iter()
+        .map(|r| {
+            let mut status = match r.end_date {
+                Some(end_date) => format!("returned {}", format_date(end_date)),
+                None => format!("due {}", format_date(r.due_date)),
+            };
+            if let Some(d) = loan.deposits.iter().find(|d| d.rental_id == r.id) {
+                status = format!("{}<br>{}", status, escape_html(&deposit_text(d)));
+            }
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(&r.tool_real_id),
+                escape_html(&(r.tool_short_description.clone() + &quantity_text(r.quantity))),
+                escape_html(&r.store_name),
+                status,
+            )
+        })
+        .collect();
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Receipt {receipt}</title></head>\n<body>\n\
+        <h1>{library}</h1>\n<h2>Receipt {receipt}</h2>\n\
+        <p>Date: {date}<br>Borrower: {renter}<br>Checked out by: {performer} ({channel})</p>\n\
+        <table>\n<tr><th>Tool</th><th>Description</th><th>Store</th><th>Status</th></tr>\n{rows}\n</table>\n\
+        <p>{count} item(s)</p>\n</body>\n</html>\n",
+        receipt = escape_html(&loan.receipt_number),
+        library = escape_html(library_name),
+        date = format_date(loan.created_at),
+        renter = escape_html(&loan.renter_username),
+        performer = escape_html(&loan.performed_by_username),
+        channel = escape_html(channel),
+        rows = rows,
+        count = item_count(loan),
+    )
+}
@@ -0,0 +1,493 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::tool_status_change::StatusAction;
+use crate::db_structs::{
+    maintenance_schedule, service_log_entry, service_log_photo, store, tool, tool_photo,
+};
+use crate::handlers::photos;
+use crate::handlers::tool_holds::refresh_queues;
+use crate::handlers::tool_status_history::change_statuses;
+use crate::queries::maintenance::{self, DueMaintenance, EntryWithText};
+use crate::queries::tools;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewScheduleData {
+    pub tool_id: Option<maintenance_schedule::ToolId>,
+    pub category_id: Option<maintenance_schedule::CategoryId>,
+    pub name: maintenance_schedule::Name,
+    pub every_rentals: Option<maintenance_schedule::EveryRentals>,
+    pub every_days: Option<maintenance_schedule::EveryDays>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleParams {
+    pub tool_ids: Option<Vec<tool::Id>>,
+    pub category_ids: Option<Vec<maintenance_schedule::CategoryId>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulesResponse {
+    pub schedules: Vec<maintenance_schedule::MaintenanceSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewServiceEntryData {
+    pub schedule_id: Option<service_log_entry::ScheduleId>,
+    pub notes: Option<service_log_entry::Notes>,
+    pub photo_keys: Option<Vec<tool_photo::PhotoKey>>,
+    // usually to put a serviced tool back on the shelf
+    pub status: Option<tool::Status>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryWithPhotos {
+    #[serde(flatten)]
+    pub entry: EntryWithText,
+    pub photos: Vec<service_log_photo::ServiceLogPhoto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceLogResponse {
+    pub entries: Vec<EntryWithPhotos>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DueResponse {
+    pub due: Vec<DueMaintenance>,
+}
+
+// only tools that have at least one schedule due
+pub async fn select_due_tool_ids(
+    tool_ids: Vec<tool::Id>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<tool::Id>, common::ErrResponse> {
+    let now = time::OffsetDateTime::now_utc();
+    match maintenance::select_due(vec![], tool_ids, now, &mut *conn).await {
+        Ok(due) => {
+            let mut ids: Vec<tool::Id> = due.iter().map(|d| d.tool_id).collect();
+            ids.dedup();
+            Ok(ids)
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+// a schedule on a tool belongs to the tool's store, one on a category to the whole library
+async fn verify_schedule_manager(
+    claims: &Claims,
+    tool_id: Option<tool::Id>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), common::ErrResponse> {
+    let tool_id = match tool_id {
+        Some(id) => id,
+        None if claims.is_library_admin() => return Ok(()),
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::FORBIDDEN,
+                "ERR_AUTH",
+                "User is not a library admin",
+            ))
+        }
+    };
+
+    match tools::select_by_ids(vec![tool_id], db).await {
+        Ok(t) => match t.first() {
+            Some(t) if claims.is_tool_manager(t.store_id) => Ok(()),
+            Some(_) => Err(common::ErrResponse::new(
+                StatusCode::FORBIDDEN,
+                "ERR_AUTH",
+                "User is not a tool manager of this store",
+            )),
+            None => Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Tool not found",
+            )),
+        },
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn create_schedule(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewScheduleData>,
+) -> Result<Json<maintenance_schedule::MaintenanceSchedule>, common::ErrResponse> {
+    if payload.tool_id.is_some() == payload.category_id.is_some() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "A schedule is for either a tool or a category",
+        ));
+    }
+    if payload.every_rentals.is_none() && payload.every_days.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "A schedule needs a number of rentals or days",
+        ));
+    }
+    common::verify_payload_text_length(
+        &payload.name,
+        1,
+        common::MAX_MAINTENANCE_SCHEDULE_NAME_LENGTH,
+    )?;
+    common::none_or_verify_payload_integer_range(payload.every_rentals, 1, i32::MAX)?;
+    common::none_or_verify_payload_integer_range(
+        payload.every_days,
+        1,
+        common::MAX_MAINTENANCE_EVERY_DAYS,
+    )?;
+
+    verify_schedule_manager(&claims, payload.tool_id, &state.db).await?;
+
+    match maintenance::insert_schedule(
+        payload.tool_id,
+        payload.category_id,
+        payload.name,
+        payload.every_rentals,
+        payload.every_days,
+        &state.db,
+    )
+    .await
+    {
+        Ok(s) => Ok(Json(s)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn get_schedules(
+    claims: Claims,
+    Query(params): Query<ScheduleParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<SchedulesResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    match maintenance::select_schedules(
+        params.tool_ids.unwrap_or_default(),
+        params.category_ids.unwrap_or_default(),
+        &state.db,
+    )
+    .await
+    {
+        Ok(schedules) => Ok(Json(SchedulesResponse { schedules })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn delete_schedule(
+    claims: Claims,
+    Path(schedule_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<common::NoData>, common::ErrResponse> {
+    let schedule = match maintenance::select_schedule_by_id(schedule_id, &state.db).await {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Schedule not found",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    verify_schedule_manager(&claims, schedule.tool_id, &state.db).await?;
+
+    match maintenance::delete_schedule(schedule_id, &state.db).await {
+        Ok(_) => Ok(Json(common::NoData {})),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+async fn select_with_photos(
+    tool_id: tool::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<EntryWithPhotos>, common::ErrResponse> {
+    let entries = match maintenance::select_entries(tool_id, db).await {
+        Ok(e) => e,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut photos =
+        match maintenance::select_photos(entries.iter().map(|e| e.id).collect(), db).await {
+            Ok(p) => p,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let (own, rest) = photos.drain(..).partition(|p| p.entry_id == entry.id);
+            photos = rest;
+            EntryWithPhotos { entry, photos: own }
+        })
+        .collect())
+}
+
+pub async fn get_service_log(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ServiceLogResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    let entries = select_with_photos(tool_id, &state.db).await?;
+    Ok(Json(ServiceLogResponse { entries }))
+}
+
+pub async fn create_service_entry(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewServiceEntryData>,
+) -> Result<Json<EntryWithPhotos>, common::ErrResponse> {
+    common::none_or_verify_payload_text_length(
+        payload.notes.as_deref(),
+        1,
+        common::MAX_SERVICE_LOG_NOTES_LENGTH,
+    )?;
+    common::verify_payload_integer_range(
+        payload
+            .photo_keys
+            .as_ref()
+            .map_or(0, |p| p.len())
+            .try_into()
+            .unwrap_or(i32::MAX),
+        0,
+        common::MAX_SERVICE_LOG_PHOTOS_LENGTH,
+    )?;
+
+    let performed_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let tools = match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    match tools.first() {
+        Some(t) if !claims.is_tool_manager(t.store_id) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::FORBIDDEN,
+                "ERR_AUTH",
+                "User is not a tool manager of this store",
+            ))
+        }
+        Some(_) => {}
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Tool not found",
+            ))
+        }
+    }
+
+    if let Some(schedule_id) = payload.schedule_id {
+        match maintenance::select_schedules(vec![tool_id], vec![], &mut *tx).await {
+            Ok(s) if s.iter().any(|s| s.id == schedule_id) => {}
+            Ok(_) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "Schedule does not apply to the tool",
+                ))
+            }
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        }
+    }
+
+    let entry = match maintenance::insert_entry(
+        tool_id,
+        payload.schedule_id,
+        performed_by,
+        payload.notes,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(e) => e,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let photo_keys = payload.photo_keys.unwrap_or_default();
+    if !photo_keys.is_empty() {
+        let taken = photos::claim_unattached(photo_keys, &mut tx).await?;
+        if let Err(e) = maintenance::insert_photos(entry.id, taken, &mut *tx).await {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    }
+
+    if let Some(status) = payload.status {
+        let changed = change_statuses(
+            &tools,
+            status,
+            StatusAction::Edited,
+            Some("Serviced".to_string()),
+            Some(performed_by),
+            &mut tx,
+        )
+        .await?;
+
+        if !changed.is_empty() {
+            if let Err(e) = refresh_queues(changed, &mut tx).await {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
+    match select_with_photos(tool_id, &state.db)
+        .await?
+        .into_iter()
+        .find(|e| e.entry.id == entry.id)
+    {
+        Some(e) => Ok(Json(e)),
+        None => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Service log entry not found",
+        )),
+    }
+}
+
+pub async fn get_due_by_store(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<DueResponse>, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) && !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let now = time::OffsetDateTime::now_utc();
+    match maintenance::select_due(vec![store_id], vec![], now, &state.db).await {
+        Ok(due) => Ok(Json(DueResponse { due })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
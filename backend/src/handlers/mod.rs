@@ -1,11 +1,31 @@
 pub mod auth;
+pub mod condition_reports;
+pub mod deposits;
+pub mod fee_schedules;
 pub mod grievance_replies;
 pub mod grievances;
+pub mod labels;
+pub mod ledger_entries;
 pub mod library;
+pub mod loans;
+pub mod maintenance;
 pub mod permissions;
 pub mod photos;
+pub mod qr_codes;
+pub mod rental_extensions;
 pub mod rentals;
+pub mod repair_tickets;
+pub mod reservations;
+pub mod stocktakes;
+pub mod store_hours;
+pub mod store_policies;
 pub mod stores;
+pub mod tool_assets;
 pub mod tool_categories;
+pub mod tool_holds;
+pub mod tool_imports;
+pub mod tool_kits;
+pub mod tool_status_history;
+pub mod tool_transfers;
 pub mod tools;
 pub mod users;
@@ -1,4 +1,4 @@
-use crate::queries::tool_photos;
+use crate::queries::tool_photos::{self, TakenPhoto};
 use crate::AppState;
 use crate::{auth::claims::Claims, common};
 use axum::extract::{Path, State};
@@ -217,3 +217,25 @@ pub async fn get_thumbnail(Path(file_key): Path<String>) -> Result<Response, com
 
     Ok((headers, data).into_response())
 }
+
+// Takes uploads that aren't on a tool yet for a condition report, service entry or repair
+// ticket, which files them in its own table in the same transaction. Every key has to be there.
+pub async fn claim_unattached(
+    photo_keys: Vec<String>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<TakenPhoto>, common::ErrResponse> {
+    let expected = photo_keys.len();
+    match tool_photos::take_unattached(photo_keys, &mut *conn).await {
+        Ok(photos) if photos.len() != expected => Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Some photo keys do not exist or are already in use",
+        )),
+        Ok(photos) => Ok(photos),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
@@ -0,0 +1,177 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::qr_codes::{self, ScannedCode};
+use crate::queries::{stores, tools, users};
+use crate::AppState;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Svg,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeParams {
+    pub format: Option<ImageFormat>,
+}
+
+fn render(
+    code: ScannedCode,
+    name: &str,
+    format: ImageFormat,
+) -> Result<Response, common::ErrResponse> {
+    let payload = code.payload();
+    let make_render_error =
+        |e: &str| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_LOGIC", e);
+
+    match format {
+        ImageFormat::Png => {
+            let data = qr_codes::render_png(&payload).map_err(|e| make_render_error(&e))?;
+            let attachment_header = format!("attachment; filename=\"{}.png\"", name);
+            let headers = [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CONTENT_DISPOSITION, attachment_header.as_str()),
+            ];
+            Ok((headers, data).into_response())
+        }
+        ImageFormat::Svg => {
+            let data = qr_codes::render_svg(&payload).map_err(|e| make_render_error(&e))?;
+            let attachment_header = format!("attachment; filename=\"{}.svg\"", name);
+            let headers = [
+                (header::CONTENT_TYPE, "image/svg+xml"),
+                (header::CONTENT_DISPOSITION, attachment_header.as_str()),
+            ];
+            Ok((headers, data).into_response())
+        }
+    }
+}
+
+pub async fn get_tool_code(
+    Path(tool_id): Path<i32>,
+    Query(params): Query<CodeParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, common::ErrResponse> {
+    let tool = match tools::select_by_ids(vec![tool_id], &state.db).await {
+        Ok(mut t) => {
+            if t.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "No tool with the given ID exists",
+                ));
+            }
+            t.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    render(
+        ScannedCode::Tool {
+            store_id: tool.store_id,
+            real_id: tool.real_id,
+        },
+        &format!("tool-{}", tool.id),
+        params.format.unwrap_or_default(),
+    )
+}
+
+pub async fn get_store_code(
+    claims: Claims,
+    Path(store_id): Path<i32>,
+    Query(params): Query<CodeParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, common::ErrResponse> {
+    let can_see_code = claims.is_store_admin()
+        || claims.is_store_manager(store_id)
+        || claims.is_tool_manager(store_id);
+    if !can_see_code {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot see the store code",
+        ));
+    }
+
+    let store = match stores::select_by_ids(vec![store_id], &state.db).await {
+        Ok(mut s) => {
+            if s.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store not found",
+                ));
+            }
+            s.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    render(
+        ScannedCode::Store(store.code),
+        &format!("store-{}", store.id),
+        params.format.unwrap_or_default(),
+    )
+}
+
+pub async fn get_user_code(
+    claims: Claims,
+    Path(user_id): Path<i32>,
+    Query(params): Query<CodeParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, common::ErrResponse> {
+    let claims_user_id = claims.subject_as_user_id().unwrap_or_default();
+    if !claims.is_user_admin() && claims_user_id != user_id {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot see the user code",
+        ));
+    }
+
+    let user = match users::select_by_ids(vec![user_id], &state.db).await {
+        Ok(mut u) => {
+            if u.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "User not found",
+                ));
+            }
+            u.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    render(
+        ScannedCode::User(user.code),
+        &format!("user-{}", user.id),
+        params.format.unwrap_or_default(),
+    )
+}
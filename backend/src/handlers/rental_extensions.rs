@@ -0,0 +1,425 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{rental, rental_extension, user};
+use crate::handlers::tool_holds::refresh_queues;
+use crate::queries::{rental_extensions, rentals, tools};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestExtensionData {
+    pub due_date: rental::DueDate,
+    pub note: Option<rental_extension::Note>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecideExtensionData {
+    pub status: rental_extension::Status,
+    pub note: Option<rental_extension::DecisionNote>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionsResponse {
+    pub extensions: Vec<rental_extension::RentalExtension>,
+}
+
+const AUTO_DENIED_NOTE: &str = "The tool is on hold or reserved by someone else";
+
+// Denies a pending extension if anyone else is waiting for the tool or has it booked before the requested due date.
+// Returns the denied extension, or None if nothing is in the way.
+async fn deny_if_blocked(
+    extension: &rental_extension::RentalExtension,
+    rental: &rental::Rental,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Option<rental_extension::RentalExtension>, String> {
+    refresh_queues(vec![rental.tool_id], &mut *conn).await?;
+
+    let (holds, reservations) = rental_extensions::count_blockers(
+        rental.tool_id,
+        rental.renter_id,
+        rental.due_date,
+        extension.requested_due_date,
+        &mut *conn,
+    )
+    .await?;
+    if holds == 0 && reservations == 0 {
+        return Ok(None);
+    }
+
+    rental_extensions::decide(
+        extension.id,
+        rental_extension::ExtensionStatus::Denied as i32,
+        None,
+        Some(AUTO_DENIED_NOTE.to_string()),
+        &mut *conn,
+    )
+    .await
+}
+
+pub async fn request(
+    claims: Claims,
+    Path(rental_id): Path<rental::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RequestExtensionData>,
+) -> Result<Json<rental_extension::RentalExtension>, common::ErrResponse> {
+    let user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    common::none_or_verify_payload_text_length(
+        payload.note.as_deref(),
+        1,
+        common::MAX_RENTAL_EXTENSION_NOTE_LENGTH,
+    )?;
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let rental = match rentals::select_by_id(rental_id, &mut *tx).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Rental not found",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if rental.renter_id != user_id {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "Only the renter can ask for an extension",
+        ));
+    }
+
+    if rental.end_date.is_some() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Rental is already closed",
+        ));
+    }
+
+    if payload.due_date <= rental.due_date {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "New due date must be after the current due date",
+        ));
+    }
+
+    // holds and reservations lock the tool too, so nobody can queue up while this is being decided
+    if let Err(e) = tools::select_by_ids_for_update(vec![rental.tool_id], &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    let extension = match rental_extensions::insert(
+        rental.id,
+        user_id,
+        rental.due_date,
+        payload.due_date,
+        payload.note,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(ext) => ext,
+        Err(e) => {
+            if e.contains("idx_rental_extensions_pending_rental_id") {
+                return Err(common::ErrResponse::new(
+                    StatusCode::CONFLICT,
+                    "ERR_DUP",
+                    "Rental already has a pending extension request",
+                ));
+            }
+
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    };
+
+    let extension = match deny_if_blocked(&extension, &rental, &mut tx).await {
+        Ok(denied) => denied.unwrap_or(extension),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(extension)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+pub async fn get_by_rental(
+    claims: Claims,
+    Path(rental_id): Path<rental::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ExtensionsResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    match rental_extensions::select_by_rental_id(rental_id, &state.db).await {
+        Ok(extensions) => Ok(Json(ExtensionsResponse { extensions })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn update_status(
+    claims: Claims,
+    Path(extension_id): Path<rental_extension::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<DecideExtensionData>,
+) -> Result<Json<rental_extension::RentalExtension>, common::ErrResponse> {
+    let user_id: user::Id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    common::none_or_verify_payload_text_length(
+        payload.note.as_deref(),
+        1,
+        common::MAX_RENTAL_EXTENSION_NOTE_LENGTH,
+    )?;
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let extension = match rental_extensions::select_by_id(extension_id, &mut *tx).await {
+        Ok(Some(ext)) => ext,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Extension request not found",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let rental = match rentals::select_by_id(extension.rental_id, &mut *tx).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Rental not found",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let tool = match tools::select_by_ids_for_update(vec![rental.tool_id], &mut *tx).await {
+        Ok(mut t) => {
+            if t.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+            t.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let allowed = if payload.status == rental_extension::ExtensionStatus::Cancelled as i32 {
+        extension.requested_by == user_id
+    } else if payload.status == rental_extension::ExtensionStatus::Approved as i32
+        || payload.status == rental_extension::ExtensionStatus::Denied as i32
+    {
+        claims.is_tool_manager(tool.store_id)
+    } else {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Extension requests can only be approved, denied, or cancelled",
+        ));
+    };
+    if !allowed {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot make this decision",
+        ));
+    }
+
+    if extension.status != rental_extension::ExtensionStatus::Pending as i32 {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            "Extension request has already been decided",
+        ));
+    }
+
+    if payload.status == rental_extension::ExtensionStatus::Approved as i32 {
+        if rental.end_date.is_some() {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Rental is already closed",
+            ));
+        }
+
+        // the queue may have changed since the request was made
+        match deny_if_blocked(&extension, &rental, &mut tx).await {
+            Ok(Some(denied)) => {
+                return match tx.commit().await {
+                    Ok(_) => Ok(Json(denied)),
+                    Err(e) => Err(common::ErrResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "ERR_DB",
+                        &e.to_string(),
+                    )),
+                };
+            }
+            Ok(None) => {}
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        }
+
+        if let Err(e) = rentals::update(
+            rental.id,
+            None,
+            None,
+            Some(extension.requested_due_date),
+            &mut *tx,
+        )
+        .await
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    }
+
+    let extension = match rental_extensions::decide(
+        extension.id,
+        payload.status,
+        Some(user_id),
+        payload.note,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(Some(ext)) => ext,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "Extension request has already been decided",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(extension)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
@@ -1,7 +1,24 @@
 use crate::auth::claims::Claims;
 use crate::common;
-use crate::db_structs::{rental, store, tool, user};
-use crate::queries::{rentals, stores, tools, users};
+use crate::db_structs::tool_status_change::StatusAction;
+use crate::db_structs::{
+    loan, rental, rental_extension, rental_reminder, store, tool, tool_hold, tool_kit, user,
+};
+use crate::handlers::condition_reports::{self, ConditionReportData};
+use crate::handlers::deposits::{self, DepositSettlementData};
+use crate::handlers::tool_holds::refresh_queues;
+use crate::handlers::tool_kits::{self, IncompleteKit};
+use crate::handlers::tool_status_history::change_statuses;
+use crate::handlers::tools::available_units;
+use crate::handlers::{maintenance, store_hours, store_policies};
+use crate::opening_hours;
+use crate::qr_codes::ScannedCode;
+use crate::queries::tool_kits as tool_kit_queries;
+use crate::queries::{
+    fee_schedules, ledger_entries, loans, rental_extensions, rental_reminders, rentals,
+    reservations, stores, tool_holds, tools, users,
+};
+use crate::tool_lifecycle;
 use crate::AppState;
 use axum::{
     extract::{Json, Path, State},
@@ -14,15 +31,89 @@ use std::sync::Arc;
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CheckInData {
+    #[serde(default)]
     pub tool_ids: Vec<tool::Id>,
+    // a pooled tool can be out on several rentals at once, so say which ones are coming back
+    #[serde(default)]
+    pub rental_ids: Vec<rental::Id>,
+    pub scans: Option<Vec<String>>,
+    pub reports: Option<Vec<ConditionReportData>>,
+    pub deposits: Option<Vec<DepositSettlementData>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CheckOutData {
+    #[serde(default)]
     pub tool_ids: Vec<tool::Id>,
+    // every tool in each kit goes out with it
+    #[serde(default)]
+    pub kit_ids: Vec<tool_kit::Id>,
+    // units of pooled tools; any tool not listed takes one
+    #[serde(default)]
+    pub quantities: Vec<ToolQuantity>,
+    pub scans: Option<Vec<String>>,
+    pub user_code: Option<user::Code>,
+    pub store_code: Option<store::Code>,
+    pub due_date: Option<rental::DueDate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolQuantity {
+    pub tool_id: tool::Id,
+    pub quantity: rental::Quantity,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckInResponse {
+    pub incomplete_kits: Vec<IncompleteKit>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RealIdCheckInData {
+    pub store_id: store::Id,
+    pub real_ids: Vec<tool::RealId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RealIdCheckOutData {
+    pub store_id: store::Id,
+    pub real_ids: Vec<tool::RealId>,
     pub user_code: Option<user::Code>,
     pub store_code: Option<store::Code>,
+    pub due_date: Option<rental::DueDate>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum RealIdOutcome {
+    Processed,
+    NotFound,
+    Ambiguous,
+    WrongStatus,
+    // found and ready, but a check turned it away; the reason says why
+    Blocked,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RealIdResult {
+    pub real_id: tool::RealId,
+    pub tool_id: Option<tool::Id>,
+    pub status: Option<tool::Status>,
+    pub outcome: RealIdOutcome,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RealIdResponse {
+    pub results: Vec<RealIdResult>,
+    pub loan: Option<loan::Loan>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,6 +136,7 @@ pub struct FilterParams {
 pub struct SettableRentalData {
     pub end_date: Option<rental::EndDate>,
     pub no_end_date: Option<bool>,
+    pub due_date: Option<rental::DueDate>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -53,6 +145,12 @@ pub struct FilteredResponse {
     pub rentals: Vec<RentalWithText>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemindersResponse {
+    pub reminders: Vec<rental_reminder::RentalReminder>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RentalWithText {
@@ -66,28 +164,74 @@ pub struct RentalWithText {
     pub renter_username: user::Username,
     pub start_date: rental::StartDate,
     pub end_date: Option<rental::EndDate>,
+    pub due_date: rental::DueDate,
+    pub loan_id: Option<rental::LoanId>,
+    pub quantity: rental::Quantity,
 }
 
-pub async fn check_in(
-    claims: Claims,
-    State(state): State<Arc<AppState>>,
-    Json(payload): Json<CheckInData>,
-) -> Result<Json<common::NoData>, common::ErrResponse> {
-    if payload.tool_ids.is_empty() {
-        return Err(common::ErrResponse::new(
-            StatusCode::BAD_REQUEST,
-            "ERR_REQ",
-            "No tools to check in",
-        ));
-    } else if payload.tool_ids.len() > common::MAX_TOOL_RENTAL_CHECK_IN_COUNT {
+#[derive(Debug, Default)]
+struct ResolvedScans {
+    tool_ids: Vec<tool::Id>,
+    kit_ids: Vec<tool_kit::Id>,
+    user_code: Option<user::Code>,
+    store_code: Option<store::Code>,
+}
+
+// turn scanned qr payloads into the ids and codes the rest of check in / check out expects,
+// where a tool code that isn't a tool may be a kit
+async fn resolve_scans(
+    scans: Vec<String>,
+    db: &sqlx::PgPool,
+) -> Result<ResolvedScans, common::ErrResponse> {
+    if scans.len() > common::MAX_RENTAL_SCANS_LENGTH {
         return Err(common::ErrResponse::new(
             StatusCode::BAD_REQUEST,
             "ERR_REQ",
-            "Too many tools to check in",
+            "Too many scans",
         ));
     }
 
-    let tools = match tools::select_by_ids(payload.tool_ids.clone(), &state.db).await {
+    let mut resolved = ResolvedScans::default();
+    let mut store_ids: Vec<tool::StoreId> = Vec::new();
+    let mut real_ids: Vec<tool::RealId> = Vec::new();
+
+    for scan in &scans {
+        let code = match ScannedCode::parse(scan) {
+            Some(code) => code,
+            None => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    &format!("Unrecognized scan: {}", scan),
+                ))
+            }
+        };
+
+        let (slot, code) = match code {
+            ScannedCode::Tool { store_id, real_id } => {
+                store_ids.push(store_id);
+                real_ids.push(real_id);
+                continue;
+            }
+            ScannedCode::User(code) => (&mut resolved.user_code, code),
+            ScannedCode::Store(code) => (&mut resolved.store_code, code),
+        };
+        if slot.as_ref().is_some_and(|c| *c != code) {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "More than one user or store code was scanned",
+            ));
+        }
+        *slot = Some(code);
+    }
+
+    if real_ids.is_empty() {
+        return Ok(resolved);
+    }
+
+    let tools = match tools::select_by_store_real_ids(store_ids.clone(), real_ids.clone(), db).await
+    {
         Ok(tools) => tools,
         Err(e) => {
             return Err(common::ErrResponse::new(
@@ -98,70 +242,131 @@ pub async fn check_in(
         }
     };
 
-    if tools.len() != payload.tool_ids.len() {
-        return Err(common::ErrResponse::new(
-            StatusCode::NOT_FOUND,
-            "ERR_MIA",
-            "Some tools not found",
-        ));
+    let kits = match tool_kit_queries::select(
+        tool_kit_queries::SelectParams {
+            ids: vec![],
+            store_ids: store_ids.clone(),
+            real_ids: real_ids.clone(),
+            term: "".to_string(),
+            statuses: vec![],
+            category_ids: vec![],
+            offset: 0,
+            limit: i64::try_from(real_ids.len()).unwrap_or_default(),
+        },
+        db,
+    )
+    .await
+    {
+        Ok(kits) => kits,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    for (store_id, real_id) in store_ids.iter().zip(real_ids.iter()) {
+        let mut matches = tools
+            .iter()
+            .filter(|t| t.store_id == *store_id && t.real_id == *real_id);
+        let tool_id = match (matches.next(), matches.next()) {
+            (Some(t), None) => t.id,
+            (None, _) => {
+                match kits
+                    .iter()
+                    .find(|k| k.store_id == *store_id && k.real_id == *real_id)
+                {
+                    Some(k) => resolved.kit_ids.push(k.id),
+                    None => {
+                        return Err(common::ErrResponse::new(
+                            StatusCode::NOT_FOUND,
+                            "ERR_MIA",
+                            &format!("Scanned tool not found: {}", real_id),
+                        ))
+                    }
+                }
+                continue;
+            }
+            (Some(_), Some(_)) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::CONFLICT,
+                    "ERR_CONFLICT",
+                    &format!("More than one tool in the store has real id {}", real_id),
+                ))
+            }
+        };
+        resolved.tool_ids.push(tool_id);
     }
 
-    for tool in &tools {
-        if !claims.is_tool_manager(tool.store_id) {
+    Ok(resolved)
+}
+
+// stores can refuse to lend to anyone who owes more than their fee schedule allows
+async fn verify_balance(
+    renter_id: user::Id,
+    store_ids: Vec<store::Id>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), common::ErrResponse> {
+    let schedules = match fee_schedules::select_by_store_ids(store_ids, &mut *conn).await {
+        Ok(s) => s,
+        Err(e) => {
             return Err(common::ErrResponse::new(
-                StatusCode::FORBIDDEN,
-                "ERR_AUTH",
-                "User is not a tool manager of all the stores",
-            ));
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
         }
-    }
+    };
+    let max_balance = match schedules.iter().filter_map(|s| s.max_balance).min() {
+        Some(max) => max,
+        None => return Ok(()),
+    };
 
-    for tool in &tools {
-        if tool.status != tool::ToolStatus::Rented as i32 {
-            continue;
+    let balance = match ledger_entries::select_balance(renter_id, &mut *conn).await {
+        Ok(b) => b,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
         }
+    };
+    if balance > i64::from(max_balance) {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!(
+                "Balance of {} is over the limit of {} for borrowing",
+                balance, max_balance
+            ),
+        ));
+    }
 
-        match tools::update(
-            tool.id,
-            None,
-            None,
-            None,
-            None,
-            Some(tool::ToolStatus::Available as i32),
-            &state.db,
-        )
-        .await
-        {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
-            }
+    Ok(())
+}
+
+// scanned tools are added to any passed by id, without doubling up
+fn merge_tool_ids(tool_ids: &mut Vec<tool::Id>, scanned: Vec<tool::Id>) {
+    for id in scanned {
+        if !tool_ids.contains(&id) {
+            tool_ids.push(id);
         }
     }
+}
 
-    let rentals = match rentals::select(
-        rentals::SelectParams {
-            renter_ids: vec![],
-            tool_ids: payload.tool_ids.clone(),
-            store_ids: vec![],
-            start_date: common::DateBetween::none(),
-            end_date: common::DateBetween::none(),
-            open: true,
-            overdue: None,
-            order_by: rentals::OrderBy::StartDate,
-            order_asc: false,
-            offset: 0,
-            limit: payload.tool_ids.len() as i64,
-        },
-        &state.db,
-    )
-    .await
-    {
-        Ok(rentals) => rentals,
+// every open rental of a tool comes back with it, unless some were named, but a pooled tool out
+// to more than one renter has to say which
+async fn select_closing_rentals(
+    tools: &[tool::Tool],
+    rental_ids: &[rental::Id],
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<rental::Id>, common::ErrResponse> {
+    let tool_ids: Vec<tool::Id> = tools.iter().map(|t| t.id).collect();
+    let open = match rentals::select_open(tool_ids, vec![], &mut *conn).await {
+        Ok(r) => r,
         Err(e) => {
             return Err(common::ErrResponse::new(
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -171,61 +376,120 @@ pub async fn check_in(
         }
     };
 
-    let now = time::OffsetDateTime::now_utc();
-    for rental in &rentals {
-        match rentals::update(rental.id, None, Some(now), &state.db).await {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
-            }
+    let mut closing_ids: Vec<rental::Id> = vec![];
+    let mut ambiguous: Vec<&str> = vec![];
+    for tool in tools {
+        let tool_rentals: Vec<&rental::Rental> =
+            open.iter().filter(|r| r.tool_id == tool.id).collect();
+        let named: Vec<rental::Id> = tool_rentals
+            .iter()
+            .filter(|r| rental_ids.contains(&r.id))
+            .map(|r| r.id)
+            .collect();
+        if !named.is_empty() {
+            closing_ids.extend(named);
+        } else if tool_rentals.len() > 1 {
+            ambiguous.push(tool.real_id.as_str());
+        } else {
+            closing_ids.extend(tool_rentals.iter().map(|r| r.id));
         }
     }
 
-    Ok(Json(common::NoData {}))
+    if !ambiguous.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!(
+                "Tools out on more than one rental, check them in by rental: {}",
+                ambiguous.join(", ")
+            ),
+        ));
+    }
+
+    Ok(closing_ids)
 }
 
-pub async fn check_out(
+pub async fn check_in(
     claims: Claims,
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<CheckOutData>,
-) -> Result<Json<common::NoData>, common::ErrResponse> {
-    if payload.tool_ids.is_empty() {
-        return Err(common::ErrResponse::new(
-            StatusCode::BAD_REQUEST,
-            "ERR_REQ",
-            "No tools to check out",
-        ));
+    Json(mut payload): Json<CheckInData>,
+) -> Result<Json<CheckInResponse>, common::ErrResponse> {
+    if let Some(scans) = payload.scans.take() {
+        let resolved = resolve_scans(scans, &state.db).await?;
+        if resolved.user_code.is_some() || resolved.store_code.is_some() {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Only tool codes can be scanned at check in",
+            ));
+        }
+        // each piece is scanned on its way back in, so anything missing from a kit shows up
+        if !resolved.kit_ids.is_empty() {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Scan each tool in a kit at check in, not the kit",
+            ));
+        }
+        merge_tool_ids(&mut payload.tool_ids, resolved.tool_ids);
     }
 
-    if payload.user_code.is_none() && payload.store_code.is_none() {
+    if payload.tool_ids.is_empty() && payload.rental_ids.is_empty() {
         return Err(common::ErrResponse::new(
             StatusCode::BAD_REQUEST,
             "ERR_REQ",
-            "Either user code or store code must be provided",
+            "No tools to check in",
         ));
-    }
-
-    if payload.user_code.is_some() && payload.store_code.is_some() {
+    } else if payload.tool_ids.len() + payload.rental_ids.len()
+        > common::MAX_TOOL_RENTAL_CHECK_IN_COUNT
+    {
         return Err(common::ErrResponse::new(
             StatusCode::BAD_REQUEST,
             "ERR_REQ",
-            "Both user code and store code cannot be provided",
+            "Too many tools to check in",
         ));
     }
 
-    if payload.tool_ids.len() > common::MAX_TOOL_RENTAL_CHECK_OUT_COUNT {
-        return Err(common::ErrResponse::new(
-            StatusCode::BAD_REQUEST,
-            "ERR_REQ",
-            "Too many tools to check out",
-        ));
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    if !payload.rental_ids.is_empty() {
+        let named = match rentals::select_open(vec![], payload.rental_ids.clone(), &mut *tx).await {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+        if payload
+            .rental_ids
+            .iter()
+            .any(|id| !named.iter().any(|r| r.id == *id))
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Some rentals not found, or already checked in",
+            ));
+        }
+        merge_tool_ids(
+            &mut payload.tool_ids,
+            named.iter().map(|r| r.tool_id).collect(),
+        );
     }
 
-    let tools = match tools::select_by_ids(payload.tool_ids.clone(), &state.db).await {
+    let tools = match tools::select_by_ids_for_update(payload.tool_ids.clone(), &mut *tx).await {
         Ok(tools) => tools,
         Err(e) => {
             return Err(common::ErrResponse::new(
@@ -244,125 +508,1137 @@ pub async fn check_out(
         ));
     }
 
-    if payload.user_code.is_some() {
-        for tool in &tools {
-            if !claims.is_tool_manager(tool.store_id) {
-                return Err(common::ErrResponse::new(
-                    StatusCode::FORBIDDEN,
-                    "ERR_AUTH",
-                    "User is not a tool manager of all the stores",
-                ));
-            }
+    for tool in &tools {
+        if !claims.is_tool_manager(tool.store_id) {
+            return Err(common::ErrResponse::new(
+                StatusCode::FORBIDDEN,
+                "ERR_AUTH",
+                "User is not a tool manager of all the stores",
+            ));
         }
     }
 
-    if payload.store_code.is_some() {
-        let code = payload.store_code.unwrap();
-        let store = match stores::select_by_code(code, &state.db).await {
-            Ok(s) => {
-                if s.is_some() {
-                    s.unwrap()
-                } else {
-                    return Err(common::ErrResponse::new(
-                        StatusCode::NOT_FOUND,
-                        "ERR_MIA",
-                        "Store not found",
-                    ));
+    let closing_ids = select_closing_rentals(&tools, &payload.rental_ids, &mut tx).await?;
+
+    let reports = payload.reports.unwrap_or_default();
+    condition_reports::verify_reports(&reports, &tools)?;
+    let settlements = payload.deposits.unwrap_or_default();
+    deposits::verify_settlements(&settlements, &payload.tool_ids, &reports)?;
+
+    let reporter_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let now = time::OffsetDateTime::now_utc();
+    let incomplete_kits = return_tools(
+        &tools,
+        closing_ids,
+        reports,
+        settlements,
+        reporter_id,
+        now,
+        &mut tx,
+    )
+    .await?;
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(CheckInResponse { incomplete_kits })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+// Everything a return brings, shared by check in and closing a rental by its end date: the tools
+// go back (or off for repair), the rentals close as returned at the given time, and overdue fees,
+// condition reports, deposits and the hold queues follow.
+async fn return_tools(
+    tools: &[tool::Tool],
+    closing_ids: Vec<rental::Id>,
+    reports: Vec<ConditionReportData>,
+    settlements: Vec<DepositSettlementData>,
+    reporter_id: user::Id,
+    returned_at: rental::EndDate,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<IncompleteKit>, common::ErrResponse> {
+    let tool_ids: Vec<tool::Id> = tools.iter().map(|t| t.id).collect();
+    let due_for_maintenance =
+        maintenance::select_due_tool_ids(tool_ids.clone(), &mut *conn).await?;
+
+    // rented (or lost and now returned) tools go back to available unless a report says otherwise,
+    // or they are due for maintenance
+    let new_statuses: Vec<(tool::Id, tool::Status)> = tools
+        .iter()
+        .map(|t| {
+            let reported = reports
+                .iter()
+                .find(|r| r.tool_id == t.id)
+                .and_then(|r| r.status);
+            match reported {
+                Some(status) => (t.id, status),
+                None if t.status == tool::ToolStatus::Rented as i32
+                    || t.status == tool::ToolStatus::Lost as i32
+                    || t.status == tool::ToolStatus::Stolen as i32 =>
+                {
+                    (t.id, tool::ToolStatus::Available as i32)
                 }
+                None => (t.id, t.status),
             }
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
+        })
+        .map(|(id, status)| {
+            if status == tool::ToolStatus::Available as i32 && due_for_maintenance.contains(&id) {
+                (id, tool::ToolStatus::Maintenance as i32)
+            } else {
+                (id, status)
             }
-        };
+        })
+        .collect();
 
-        for tool in &tools {
-            if tool.store_id != store.id {
-                return Err(common::ErrResponse::new(
-                    StatusCode::BAD_REQUEST,
-                    "ERR_REQ",
-                    "Some tools do not belong to the store",
-                ));
-            }
+    for status in [
+        tool::ToolStatus::Available as i32,
+        tool::ToolStatus::Maintenance as i32,
+        tool::ToolStatus::Broken as i32,
+    ] {
+        for action in [
+            StatusAction::CheckedIn,
+            StatusAction::Found,
+            StatusAction::Edited,
+        ] {
+            let moving: Vec<tool::Tool> = tools
+                .iter()
+                .filter(|t| tool_lifecycle::check_in_action(t.status) == action)
+                .filter(|t| {
+                    new_statuses
+                        .iter()
+                        .any(|(id, s)| *id == t.id && *s == status)
+                })
+                .cloned()
+                .collect();
+
+            change_statuses(&moving, status, action, None, Some(reporter_id), &mut *conn).await?;
+        }
+    }
+
+    let closed = match rentals::close_open(closing_ids, returned_at, &mut *conn).await {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let closed_ids: Vec<rental::Id> = closed.iter().map(|r| r.id).collect();
+    if let Err(e) = ledger_entries::insert_overdue_fees(closed_ids, &mut *conn).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    let mut report_ids = Vec::with_capacity(reports.len());
+    for report in reports {
+        let rental_id = closed
+            .iter()
+            .find(|r| r.tool_id == report.tool_id)
+            .map(|r| r.id);
+        let tool_status = new_statuses
+            .iter()
+            .find(|(id, _)| *id == report.tool_id)
+            .map(|(_, s)| *s)
+            .unwrap_or(tool::ToolStatus::Available as i32);
+        let tool_id = report.tool_id;
+        let report_id = condition_reports::record_report(
+            report,
+            rental_id,
+            reporter_id,
+            tool_status,
+            &mut *conn,
+        )
+        .await?;
+        report_ids.push((tool_id, report_id));
+    }
+
+    deposits::settle_deposits(&closed, settlements, &report_ids, reporter_id, &mut *conn).await?;
+
+    let incomplete_kits = tool_kits::select_incomplete(tool_ids.clone(), &mut *conn).await?;
+
+    // returned tools go on hold for whoever is first in line
+    if let Err(e) = refresh_queues(tool_ids, &mut *conn).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    Ok(incomplete_kits)
+}
+
+// Each current reservation sets aside its units for its user, and each ready hold one unit. A
+// reservation outranks a hold, so holds only take units nobody has reserved, otherwise a ready hold
+// could lock the reserving user out. Returns why the renter can't have the quantity asked for, if so.
+fn claimed_by_others(
+    tool: &tool::Tool,
+    quantity: rental::Quantity,
+    available: i64,
+    reservations: &[reservations::ReservationWithText],
+    holds: &[tool_holds::HoldWithText],
+    renter_id: user::Id,
+) -> Option<String> {
+    let count = |n: usize| i64::try_from(n).unwrap_or(i64::MAX);
+    let reserved: Vec<&reservations::ReservationWithText> = reservations
+        .iter()
+        .filter(|r| r.tool_id == tool.id)
+        .collect();
+    let reserved_for_others: Vec<&reservations::ReservationWithText> = reserved
+        .iter()
+        .filter(|r| r.user_id != renter_id)
+        .copied()
+        .collect();
+    let units = |rs: &[&reservations::ReservationWithText]| -> i64 {
+        rs.iter().map(|r| i64::from(r.quantity)).sum()
+    };
+    let held_for_others: Vec<&str> = holds
+        .iter()
+        .filter(|h| h.tool_id == tool.id && h.user_id != renter_id)
+        .map(|h| h.username.as_str())
+        .collect();
+
+    let unreserved = (available - units(&reserved)).max(0);
+    let free =
+        available - units(&reserved_for_others) - count(held_for_others.len()).min(unreserved);
+    if i64::from(quantity) <= free {
+        return None;
+    }
+
+    Some(
+        match (reserved_for_others.first(), held_for_others.first()) {
+            _ if free > 0 => format!("{} (only {} left)", tool.real_id, free),
+            (Some(r), _) => format!("{} (reserved by {})", tool.real_id, r.username),
+            (None, Some(username)) => format!("{} (on hold for {})", tool.real_id, username),
+            (None, None) => tool.real_id.clone(),
+        },
+    )
+}
+
+// who a check out lends to and who performs it, from the codes given
+struct Borrower {
+    performed_by: user::Id,
+    renter_id: user::Id,
+    store_id: Option<store::Id>,
+    channel: loan::LoanChannel,
+}
+
+async fn resolve_borrower(
+    claims: &Claims,
+    user_code: Option<user::Code>,
+    store_code: Option<store::Code>,
+    db: &sqlx::PgPool,
+) -> Result<Borrower, common::ErrResponse> {
+    if user_code.is_none() && store_code.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Either user code or store code must be provided",
+        ));
+    }
+
+    // the channel follows from the codes: a tool manager lending with a user code is staff, a
+    // borrower with the store code is self checkout, and a kiosk signed in as a tool manager takes
+    // the store code along with the borrower's user code
+    let channel = match (user_code.is_some(), store_code.is_some()) {
+        (true, true) => loan::LoanChannel::Kiosk,
+        (false, true) => loan::LoanChannel::SelfCheckout,
+        _ => loan::LoanChannel::Staff,
+    };
+
+    let performed_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+    let mut renter_id = performed_by;
+    if let Some(code) = user_code {
+        let user = match users::select_by_code(code, db).await {
+            Ok(Some(u)) => u,
+            Ok(None) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "User not found",
+                ))
+            }
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+        renter_id = user.id;
+    }
+
+    let mut store_id: Option<store::Id> = None;
+    if let Some(code) = store_code {
+        let store = match stores::select_by_code(code, db).await {
+            Ok(Some(s)) => s,
+            Ok(None) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store not found",
+                ))
+            }
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+        store_id = Some(store.id);
+    }
+
+    Ok(Borrower {
+        performed_by,
+        renter_id,
+        store_id,
+        channel,
+    })
+}
+
+// What a whole check out stands or falls by: who may lend the locked tools, who picks the due
+// date, and whether the store is open for a store code. Returns the stores' schedules.
+async fn verify_lending(
+    claims: &Claims,
+    borrower: &Borrower,
+    tools: &[tool::Tool],
+    due_date: Option<rental::DueDate>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<(store::Id, opening_hours::Schedule)>, common::ErrResponse> {
+    if due_date.is_some_and(|d| d <= time::OffsetDateTime::now_utc()) {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Due date must be in the future",
+        ));
+    }
+
+    if borrower.store_id.is_none() || borrower.channel == loan::LoanChannel::Kiosk {
+        for tool in tools {
+            if !claims.is_tool_manager(tool.store_id) {
+                return Err(common::ErrResponse::new(
+                    StatusCode::FORBIDDEN,
+                    "ERR_AUTH",
+                    "User is not a tool manager of all the stores",
+                ));
+            }
+        }
+    }
+
+    if let Some(store_id) = borrower.store_id {
+        for tool in tools {
+            if tool.store_id != store_id {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "Some tools do not belong to the store",
+                ));
+            }
+        }
+    }
+
+    // borrowers get the store's rental hours, and only a tool manager picks another due date
+    if due_date.is_some() && tools.iter().any(|t| !claims.is_tool_manager(t.store_id)) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "Only a tool manager can set the due date",
+        ));
+    }
+
+    // the store code is for borrowing in person, so it only works while the store is open
+    let store_ids: Vec<store::Id> = tools.iter().map(|t| t.store_id).collect();
+    let schedules = store_hours::select_schedules(store_ids, &mut *conn).await?;
+    if let Some(store_id) = borrower.store_id {
+        let now = time::OffsetDateTime::now_utc();
+        let schedule = schedules
+            .iter()
+            .find(|(id, _)| *id == store_id)
+            .map(|(_, s)| s);
+        if let Some(schedule) = schedule.filter(|s| !s.is_open_at(now)) {
+            let details = match schedule.opens_at(now) {
+                Some(opens_at) => format!(
+                    "Store is closed, and opens at {}",
+                    opens_at
+                        .format(&time::format_description::well_known::Rfc3339)
+                        .unwrap_or_default()
+                ),
+                None => "Store is closed".to_string(),
+            };
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                &details,
+            ));
+        }
+    }
+
+    Ok(schedules)
+}
+
+// Why each locked tool can't go out in the quantity asked for, if so, lined up with tools: units
+// already out, then reservations and holds for someone else. The queues are refreshed first, so
+// a lapsed hold doesn't block anything.
+async fn select_blocked(
+    tools: &[tool::Tool],
+    quantities: &[rental::Quantity],
+    renter_id: user::Id,
+    now: time::OffsetDateTime,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<Option<String>>, common::ErrResponse> {
+    let tool_ids: Vec<tool::Id> = tools.iter().map(|t| t.id).collect();
+    if let Err(e) = refresh_queues(tool_ids.clone(), &mut *conn).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    let open_units = match rentals::select_open_units(tool_ids.clone(), &mut *conn).await {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let current_reservations =
+        match reservations::select_current(tool_ids.clone(), now, &mut *conn).await {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let holds = match tool_holds::select_on_hold(tool_ids, &mut *conn).await {
+        Ok(h) => h,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    Ok(tools
+        .iter()
+        .zip(quantities.iter())
+        .map(|(t, q)| match available_units(t, &open_units) {
+            0 => Some(t.real_id.clone()),
+            left if i64::from(*q) > left => Some(format!("{} (only {} left)", t.real_id, left)),
+            left => claimed_by_others(t, *q, left, &current_reservations, &holds, renter_id),
+        })
+        .collect())
+}
+
+// Lend the locked tools, once every check has passed: statuses, the loan and its rentals, due
+// dates rolled past closed hours, deposits, and the reservations and holds the renter had.
+async fn lend(
+    tools: &[tool::Tool],
+    quantities: Vec<rental::Quantity>,
+    borrower: &Borrower,
+    due_date: Option<rental::DueDate>,
+    schedules: &[(store::Id, opening_hours::Schedule)],
+    now: time::OffsetDateTime,
+    conn: &mut sqlx::PgConnection,
+) -> Result<loan::Loan, common::ErrResponse> {
+    let tool_ids: Vec<tool::Id> = tools.iter().map(|t| t.id).collect();
+    let open_units = match rentals::select_open_units(tool_ids.clone(), &mut *conn).await {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    // a pooled tool only counts as rented once its last unit is out
+    let emptied: Vec<tool::Tool> = tools
+        .iter()
+        .zip(quantities.iter())
+        .filter(|(t, q)| i64::from(**q) == available_units(t, &open_units))
+        .map(|(t, _)| t.clone())
+        .collect();
+    change_statuses(
+        &emptied,
+        tool::ToolStatus::Rented as i32,
+        StatusAction::CheckedOut,
+        None,
+        Some(borrower.performed_by),
+        &mut *conn,
+    )
+    .await?;
+
+    let renter_id = borrower.renter_id;
+    let loan = match loans::insert(
+        renter_id,
+        borrower.performed_by,
+        borrower.channel as i32,
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(l) => l,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let new_rentals = match rentals::insert_many(
+        tool_ids.clone(),
+        quantities,
+        renter_id,
+        now,
+        due_date,
+        Some(loan.id),
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    };
+
+    // nothing should fall due while its store is closed
+    if due_date.is_none() {
+        let mut rolled_ids = vec![];
+        let mut rolled_dates = vec![];
+        for r in &new_rentals {
+            let store_id = tools.iter().find(|t| t.id == r.tool_id).map(|t| t.store_id);
+            let schedule = schedules.iter().find(|(id, _)| Some(*id) == store_id);
+            if let Some((_, schedule)) = schedule {
+                let due_date = schedule.roll_forward(r.due_date);
+                if due_date != r.due_date {
+                    rolled_ids.push(r.id);
+                    rolled_dates.push(due_date);
+                }
+            }
+        }
+
+        if !rolled_ids.is_empty() {
+            if let Err(e) = rentals::update_due_dates(rolled_ids, rolled_dates, &mut *conn).await {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        }
+    }
+
+    // tools that ask for a deposit take it now, at whatever amount they ask for today
+    let rental_ids: Vec<rental::Id> = new_rentals.iter().map(|r| r.id).collect();
+    if let Err(e) = crate::queries::deposits::insert_for_rentals(rental_ids, &mut *conn).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) =
+        reservations::fulfill_current(tool_ids.clone(), renter_id, now, &mut *conn).await
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = tool_holds::update_statuses(
+        tool_ids,
+        vec![renter_id],
+        tool_hold::HoldStatus::Fulfilled as i32,
+        &mut *conn,
+    )
+    .await
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    Ok(loan)
+}
+
+pub async fn check_out(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(mut payload): Json<CheckOutData>,
+) -> Result<Json<loan::Loan>, common::ErrResponse> {
+    if let Some(scans) = payload.scans.take() {
+        let resolved = resolve_scans(scans, &state.db).await?;
+        merge_tool_ids(&mut payload.tool_ids, resolved.tool_ids);
+        merge_tool_ids(&mut payload.kit_ids, resolved.kit_ids);
+        for (typed, scanned) in [
+            (&mut payload.user_code, resolved.user_code),
+            (&mut payload.store_code, resolved.store_code),
+        ] {
+            if scanned.is_none() {
+                continue;
+            }
+            if typed.is_some() && *typed != scanned {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "Scanned code does not match the code provided",
+                ));
+            }
+            *typed = scanned;
+        }
+    }
+
+    if !payload.kit_ids.is_empty() {
+        let member_ids = tool_kits::select_member_ids(payload.kit_ids.clone(), &state.db).await?;
+        merge_tool_ids(&mut payload.tool_ids, member_ids);
+    }
+
+    for (i, q) in payload.quantities.iter().enumerate() {
+        common::verify_payload_integer_range(q.quantity, 1, common::MAX_TOOL_QUANTITY)?;
+        if payload.quantities[..i]
+            .iter()
+            .any(|p| p.tool_id == q.tool_id)
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "A tool can only be given one quantity",
+            ));
+        }
+    }
+    merge_tool_ids(
+        &mut payload.tool_ids,
+        payload.quantities.iter().map(|q| q.tool_id).collect(),
+    );
+
+    if payload.tool_ids.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "No tools to check out",
+        ));
+    }
+
+    if payload.tool_ids.len() > common::MAX_TOOL_RENTAL_CHECK_OUT_COUNT {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Too many tools to check out",
+        ));
+    }
+
+    let borrower = resolve_borrower(
+        &claims,
+        payload.user_code.take(),
+        payload.store_code.take(),
+        &state.db,
+    )
+    .await?;
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    // row locks are held until commit, so a concurrent check out of the same tools waits here
+    let tools = match tools::select_by_ids_for_update(payload.tool_ids.clone(), &mut *tx).await {
+        Ok(tools) => tools,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if tools.len() != payload.tool_ids.len() {
+        return Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Some tools not found",
+        ));
+    }
+
+    let schedules = verify_lending(&claims, &borrower, &tools, payload.due_date, &mut tx).await?;
+
+    // lined up with tools
+    let quantities: Vec<rental::Quantity> = tools
+        .iter()
+        .map(|t| {
+            payload
+                .quantities
+                .iter()
+                .find(|q| q.tool_id == t.id)
+                .map_or(1, |q| q.quantity)
+        })
+        .collect();
+
+    let now = time::OffsetDateTime::now_utc();
+    let blocked: Vec<String> =
+        select_blocked(&tools, &quantities, borrower.renter_id, now, &mut tx)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+    if !blocked.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!("Tools not available: {}", blocked.join(", ")),
+        ));
+    }
+
+    let store_ids: Vec<store::Id> = tools.iter().map(|t| t.store_id).collect();
+    verify_balance(borrower.renter_id, store_ids, &mut tx).await?;
+    store_policies::verify_check_out(
+        borrower.renter_id,
+        &tools,
+        &quantities,
+        payload.due_date,
+        &mut tx,
+    )
+    .await?;
+
+    let loan = lend(
+        &tools,
+        quantities,
+        &borrower,
+        payload.due_date,
+        &schedules,
+        now,
+        &mut tx,
+    )
+    .await?;
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(loan)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+// Look up a batch of real ids in one store and lock the tools found, then sort the ids by
+// whether they can go ahead. Callers run their own checks on the ready ones, in the same
+// transaction, and mark any they block.
+async fn resolve_real_ids(
+    store_id: store::Id,
+    real_ids: &[tool::RealId],
+    ready: impl Fn(&tool::Tool) -> bool,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(Vec<RealIdResult>, Vec<tool::Tool>), common::ErrResponse> {
+    if real_ids.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "No real ids provided",
+        ));
+    }
+
+    let found =
+        match tools::select_by_store_real_ids_for_update(store_id, real_ids.to_vec(), &mut *conn)
+            .await
+        {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let mut results: Vec<RealIdResult> = Vec::new();
+    let mut ready_tools: Vec<tool::Tool> = Vec::new();
+    for real_id in real_ids {
+        if results.iter().any(|r| r.real_id == *real_id) {
+            continue;
+        }
+        let mut matches = found.iter().filter(|t| t.real_id == *real_id);
+        let (tool_id, status, outcome) = match (matches.next(), matches.next()) {
+            (None, _) => (None, None, RealIdOutcome::NotFound),
+            (Some(_), Some(_)) => (None, None, RealIdOutcome::Ambiguous),
+            (Some(t), None) if !ready(t) => {
+                (Some(t.id), Some(t.status), RealIdOutcome::WrongStatus)
+            }
+            (Some(t), None) => {
+                ready_tools.push(t.clone());
+                (Some(t.id), Some(t.status), RealIdOutcome::Processed)
+            }
+        };
+        results.push(RealIdResult {
+            real_id: real_id.clone(),
+            tool_id,
+            status,
+            outcome,
+            reason: None,
+        });
+    }
+
+    Ok((results, ready_tools))
+}
+
+// mark the tools a check blocked, and keep the rest
+fn block_real_ids(
+    results: &mut [RealIdResult],
+    tools: Vec<tool::Tool>,
+    blocked: Vec<Option<String>>,
+) -> Vec<tool::Tool> {
+    let mut kept = Vec::with_capacity(tools.len());
+    for (tool, reason) in tools.into_iter().zip(blocked) {
+        match reason {
+            Some(reason) => {
+                if let Some(r) = results.iter_mut().find(|r| r.tool_id == Some(tool.id)) {
+                    r.outcome = RealIdOutcome::Blocked;
+                    r.reason = Some(reason);
+                }
+            }
+            None => kept.push(tool),
+        }
+    }
+    kept
+}
+
+pub async fn check_in_by_real_ids(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RealIdCheckInData>,
+) -> Result<Json<RealIdResponse>, common::ErrResponse> {
+    if payload.real_ids.len() > common::MAX_TOOL_RENTAL_CHECK_IN_COUNT {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Too many tools to check in",
+        ));
+    }
+
+    if !claims.is_tool_manager(payload.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let reporter_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    // lock every tool the ids name before reading their rentals, so the two can't drift apart
+    let (mut results, found) =
+        resolve_real_ids(payload.store_id, &payload.real_ids, |_| true, &mut tx).await?;
+    let open =
+        match rentals::select_open(found.iter().map(|t| t.id).collect(), vec![], &mut *tx).await {
+            Ok(r) => r,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    // a tool is out when it has an open rental, whatever its status says; a pooled tool out on
+    // more than one rental has to be checked in by rental
+    let mut ready: Vec<tool::Tool> = Vec::new();
+    let mut blocked: Vec<Option<String>> = Vec::new();
+    for tool in found {
+        match open.iter().filter(|r| r.tool_id == tool.id).count() {
+            0 => {
+                if let Some(r) = results.iter_mut().find(|r| r.tool_id == Some(tool.id)) {
+                    r.outcome = RealIdOutcome::WrongStatus;
+                }
+            }
+            1 => {
+                ready.push(tool);
+                blocked.push(None);
+            }
+            _ => {
+                blocked.push(Some(format!(
+                    "{} is out on more than one rental, check it in by rental",
+                    tool.real_id
+                )));
+                ready.push(tool);
+            }
+        }
+    }
+    let tools = block_real_ids(&mut results, ready, blocked);
+
+    if !tools.is_empty() {
+        let closing_ids: Vec<rental::Id> = open
+            .iter()
+            .filter(|r| tools.iter().any(|t| t.id == r.tool_id))
+            .map(|r| r.id)
+            .collect();
+        let now = time::OffsetDateTime::now_utc();
+        return_tools(
+            &tools,
+            closing_ids,
+            vec![],
+            vec![],
+            reporter_id,
+            now,
+            &mut tx,
+        )
+        .await?;
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(RealIdResponse {
+            results,
+            loan: None,
+        })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+pub async fn check_out_by_real_ids(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RealIdCheckOutData>,
+) -> Result<Json<RealIdResponse>, common::ErrResponse> {
+    if payload.real_ids.len() > common::MAX_TOOL_RENTAL_CHECK_OUT_COUNT {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Too many tools to check out",
+        ));
+    }
+
+    // self check out with a store code is checked against the store once the tools are locked
+    if payload.store_code.is_none() && !claims.is_tool_manager(payload.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let borrower =
+        resolve_borrower(&claims, payload.user_code, payload.store_code, &state.db).await?;
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let available = tool::ToolStatus::Available as i32;
+    let (mut results, tools) = resolve_real_ids(
+        payload.store_id,
+        &payload.real_ids,
+        |t| t.status == available,
+        &mut tx,
+    )
+    .await?;
+
+    // the same checks as any check out: the ones about the borrower or the store fail the batch,
+    // and a tool someone else has a claim on is left behind with the reason
+    let mut loan = None;
+    if !tools.is_empty() {
+        let schedules =
+            verify_lending(&claims, &borrower, &tools, payload.due_date, &mut tx).await?;
+
+        let now = time::OffsetDateTime::now_utc();
+        let quantities: Vec<rental::Quantity> = vec![1; tools.len()];
+        let blocked = select_blocked(&tools, &quantities, borrower.renter_id, now, &mut tx).await?;
+        let tools = block_real_ids(&mut results, tools, blocked);
+
+        if !tools.is_empty() {
+            let quantities: Vec<rental::Quantity> = vec![1; tools.len()];
+            let store_ids: Vec<store::Id> = tools.iter().map(|t| t.store_id).collect();
+            verify_balance(borrower.renter_id, store_ids, &mut tx).await?;
+            store_policies::verify_check_out(
+                borrower.renter_id,
+                &tools,
+                &quantities,
+                payload.due_date,
+                &mut tx,
+            )
+            .await?;
+
+            loan = Some(
+                lend(
+                    &tools,
+                    quantities,
+                    &borrower,
+                    payload.due_date,
+                    &schedules,
+                    now,
+                    &mut tx,
+                )
+                .await?,
+            );
+        }
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(RealIdResponse { results, loan })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+// fill in the names a client needs to show a list of rentals
+pub async fn with_text(
+    rentals: &[rental::Rental],
+    db: &sqlx::PgPool,
+) -> Result<Vec<RentalWithText>, common::ErrResponse> {
+    let users = match users::select_by_ids(rentals.iter().map(|r| r.renter_id).collect(), db).await
+    {
+        Ok(u) => u,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
         }
-    }
+    };
 
-    for tool in &tools {
-        match tools::update(
-            tool.id,
-            None,
-            None,
-            None,
-            None,
-            Some(tool::ToolStatus::Rented as i32),
-            &state.db,
-        )
-        .await
-        {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
-            }
+    let tools = match tools::select_by_ids(rentals.iter().map(|r| r.tool_id).collect(), db).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
         }
-    }
+    };
 
-    let mut renter_id = match claims.subject_as_user_id() {
-        Some(id) => id,
-        None => {
+    let stores = match stores::select_by_ids(tools.iter().map(|t| t.store_id).collect(), db).await {
+        Ok(s) => s,
+        Err(e) => {
             return Err(common::ErrResponse::new(
-                StatusCode::UNAUTHORIZED,
-                "ERR_AUTH",
-                "Invalid user id in claims",
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
             ))
         }
     };
-    if payload.user_code.is_some() {
-        let code = payload.user_code.unwrap();
-        let user = match users::select_by_code(code, &state.db).await {
-            Ok(u) => {
-                if u.is_some() {
-                    u.unwrap()
-                } else {
-                    return Err(common::ErrResponse::new(
-                        StatusCode::NOT_FOUND,
-                        "ERR_MIA",
-                        "User not found",
-                    ));
-                }
-            }
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
-            }
-        };
-        renter_id = user.id;
-    }
 
-    let now = time::OffsetDateTime::now_utc();
-    for tool in &tools {
-        match rentals::insert(tool.id, renter_id, now, None, &state.db).await {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
+    let rentals_with_text = rentals
+        .iter()
+        .map(|r| {
+            let user = users.iter().find(|u| u.id == r.renter_id).unwrap();
+            let tool = tools.iter().find(|t| t.id == r.tool_id).unwrap();
+            let store = stores.iter().find(|s| s.id == tool.store_id).unwrap();
+            RentalWithText {
+                id: r.id,
+                tool_id: r.tool_id,
+                tool_real_id: tool.real_id.clone(),
+                tool_short_description: tool.short_description.clone(),
+                store_id: tool.store_id,
+                store_name: store.name.clone(),
+                renter_id: r.renter_id,
+                renter_username: user.username.clone(),
+                start_date: r.start_date,
+                end_date: r.end_date,
+                due_date: r.due_date,
+                loan_id: r.loan_id,
+                quantity: r.quantity,
             }
-        }
-    }
+        })
+        .collect();
 
-    Ok(Json(common::NoData {}))
+    Ok(rentals_with_text)
 }
 
 pub async fn get_filtered(
@@ -408,63 +1684,7 @@ pub async fn get_filtered(
         }
     };
 
-    let users = match users::select_by_ids(rentals.iter().map(|r| r.renter_id).collect(), &state.db)
-        .await
-    {
-        Ok(u) => u,
-        Err(e) => {
-            return Err(common::ErrResponse::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "ERR_DB",
-                &e,
-            ))
-        }
-    };
-
-    let tools =
-        match tools::select_by_ids(rentals.iter().map(|r| r.tool_id).collect(), &state.db).await {
-            Ok(t) => t,
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
-            }
-        };
-
-    let stores =
-        match stores::select_by_ids(tools.iter().map(|t| t.store_id).collect(), &state.db).await {
-            Ok(s) => s,
-            Err(e) => {
-                return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
-            }
-        };
-
-    let rentals_with_text = rentals
-        .iter()
-        .map(|r| {
-            let user = users.iter().find(|u| u.id == r.renter_id).unwrap();
-            let tool = tools.iter().find(|t| t.id == r.tool_id).unwrap();
-            let store = stores.iter().find(|s| s.id == tool.store_id).unwrap();
-            RentalWithText {
-                id: r.id,
-                tool_id: r.tool_id,
-                tool_real_id: tool.real_id.clone(),
-                tool_short_description: tool.short_description.clone(),
-                store_id: tool.store_id,
-                store_name: store.name.clone(),
-                renter_id: r.renter_id,
-                renter_username: user.username.clone(),
-                start_date: r.start_date,
-                end_date: r.end_date,
-            }
-        })
-        .collect();
+    let rentals_with_text = with_text(&rentals, &state.db).await?;
 
     Ok(Json(FilteredResponse {
         rentals: rentals_with_text,
@@ -575,9 +1795,35 @@ pub async fn get_by_id(
         renter_username: user.username,
         start_date: rental.start_date,
         end_date: rental.end_date,
+        due_date: rental.due_date,
+        loan_id: rental.loan_id,
+        quantity: rental.quantity,
     }))
 }
 
+pub async fn get_reminders(
+    claims: Claims,
+    Path(rental_id): Path<rental::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RemindersResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    match rental_reminders::select_by_rental_id(rental_id, &state.db).await {
+        Ok(reminders) => Ok(Json(RemindersResponse { reminders })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
 pub async fn update(
     claims: Claims,
     Path(rental_id): Path<i32>,
@@ -600,7 +1846,18 @@ pub async fn update(
         ));
     }
 
-    let rental = match rentals::select_by_id(rental_id, &state.db).await {
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let rental = match rentals::select_by_id(rental_id, &mut *tx).await {
         Ok(r) => {
             if r.is_none() {
                 return Err(common::ErrResponse::new(
@@ -620,7 +1877,8 @@ pub async fn update(
         }
     };
 
-    let tool = match tools::select_by_ids(vec![rental.tool_id], &state.db).await {
+    // locked so reopening can't race a check out of the same units
+    let tool = match tools::select_by_ids_for_update(vec![rental.tool_id], &mut *tx).await {
         Ok(mut t) => {
             if t.is_empty() {
                 None
@@ -637,7 +1895,10 @@ pub async fn update(
         }
     };
 
-    if tool.is_some() && !claims.is_tool_manager(tool.unwrap().store_id) {
+    if tool
+        .as_ref()
+        .is_some_and(|t| !claims.is_tool_manager(t.store_id))
+    {
         return Err(common::ErrResponse::new(
             StatusCode::FORBIDDEN,
             "ERR_AUTH",
@@ -645,20 +1906,118 @@ pub async fn update(
         ));
     }
 
+    if let Some(due_date) = payload.due_date.filter(|d| *d != rental.due_date) {
+        record_due_date_change(&claims, &rental, due_date, &mut tx).await?;
+    }
+
     if payload.no_end_date.is_some() && payload.no_end_date.unwrap() {
-        match rentals::clear_fields(rental_id, true, &state.db).await {
-            Ok(_) => {}
-            Err(e) => {
+        // reopening lends the rental's units again, so it has to pass the same checks as a check out:
+        // the tool must be available, with enough units nobody else has reserved or holds
+        if let (Some(tool), Some(_)) = (tool.as_ref(), rental.end_date) {
+            let performed_by = match claims.subject_as_user_id() {
+                Some(id) => id,
+                None => {
+                    return Err(common::ErrResponse::new(
+                        StatusCode::UNAUTHORIZED,
+                        "ERR_AUTH",
+                        "Invalid user id in claims",
+                    ))
+                }
+            };
+
+            let now = time::OffsetDateTime::now_utc();
+            let blocked = select_blocked(
+                std::slice::from_ref(tool),
+                &[rental.quantity],
+                rental.renter_id,
+                now,
+                &mut tx,
+            )
+            .await?;
+            if let Some(reason) = blocked.into_iter().flatten().next() {
                 return Err(common::ErrResponse::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "ERR_DB",
-                    &e,
-                ))
+                    StatusCode::CONFLICT,
+                    "ERR_CONFLICT",
+                    &format!("Rental cannot be reopened: {}", reason),
+                ));
+            }
+
+            let open_units = match rentals::select_open_units(vec![tool.id], &mut *tx).await {
+                Ok(u) => u,
+                Err(e) => {
+                    return Err(common::ErrResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "ERR_DB",
+                        &e,
+                    ))
+                }
+            };
+
+            // a pooled tool only counts as rented once its last unit is out
+            if i64::from(rental.quantity) == available_units(tool, &open_units) {
+                change_statuses(
+                    std::slice::from_ref(tool),
+                    tool::ToolStatus::Rented as i32,
+                    StatusAction::CheckedOut,
+                    Some("Rental reopened".to_string()),
+                    Some(performed_by),
+                    &mut tx,
+                )
+                .await?;
+
+                if let Err(e) = refresh_queues(vec![tool.id], &mut tx).await {
+                    return Err(common::ErrResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "ERR_DB",
+                        &e,
+                    ));
+                }
             }
         }
+
+        if let Err(e) = rentals::clear_fields(rental_id, true, &mut *tx).await {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    }
+
+    // closing an open rental is a return like any check in, with its fees, deposits and queues
+    if let (Some(end_date), None, Some(tool)) = (payload.end_date, rental.end_date, tool.as_ref()) {
+        if end_date < rental.start_date || end_date > time::OffsetDateTime::now_utc() {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "End date must be between the start of the rental and now",
+            ));
+        }
+
+        let reporter_id = match claims.subject_as_user_id() {
+            Some(id) => id,
+            None => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::UNAUTHORIZED,
+                    "ERR_AUTH",
+                    "Invalid user id in claims",
+                ))
+            }
+        };
+
+        return_tools(
+            std::slice::from_ref(tool),
+            vec![rental_id],
+            vec![],
+            vec![],
+            reporter_id,
+            end_date,
+            &mut tx,
+        )
+        .await?;
     }
 
-    match rentals::update(rental_id, None, payload.end_date, &state.db).await {
+    let rental = match rentals::update(rental_id, None, payload.end_date, None, &mut *tx).await {
         Ok(r) => {
             if r.is_none() {
                 return Err(common::ErrResponse::new(
@@ -667,12 +2026,96 @@ pub async fn update(
                     "Rental not found",
                 ));
             }
-            Ok(Json(r.unwrap()))
+            r.unwrap()
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
         }
+    };
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(rental)),
         Err(e) => Err(common::ErrResponse::new(
             StatusCode::INTERNAL_SERVER_ERROR,
             "ERR_DB",
-            &e,
+            &e.to_string(),
         )),
     }
 }
+
+// a tool manager moving the due date is kept in the same history as renter requests
+async fn record_due_date_change(
+    claims: &Claims,
+    rental: &rental::Rental,
+    due_date: rental::DueDate,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), common::ErrResponse> {
+    let manager_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    if let Err(e) = rentals::update(rental.id, None, None, Some(due_date), &mut *conn).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    let extension = match rental_extensions::insert(
+        rental.id,
+        manager_id,
+        rental.due_date,
+        due_date,
+        None,
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(ext) => ext,
+        Err(e) => {
+            if e.contains("idx_rental_extensions_pending_rental_id") {
+                return Err(common::ErrResponse::new(
+                    StatusCode::CONFLICT,
+                    "ERR_CONFLICT",
+                    "Rental has a pending extension request, decide on that instead",
+                ));
+            }
+
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    };
+
+    if let Err(e) = rental_extensions::decide(
+        extension.id,
+        rental_extension::ExtensionStatus::Approved as i32,
+        Some(manager_id),
+        None,
+        &mut *conn,
+    )
+    .await
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,520 @@
+use crate::auth::claims::{self, Claims};
+use crate::common;
+use crate::db_structs::repair_ticket::{self, RepairTicketStatus};
+use crate::db_structs::tool_status_change::StatusAction;
+use crate::db_structs::{permission, repair_ticket_photo, store, tool, tool_photo};
+use crate::handlers::photos;
+use crate::handlers::tool_holds::refresh_queues;
+use crate::handlers::tool_status_history::change_statuses;
+use crate::queries::repair_tickets::{self, TicketWithText};
+use crate::queries::{permissions, tools};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTicketData {
+    pub description: repair_ticket::Description,
+    pub photo_keys: Option<Vec<tool_photo::PhotoKey>>,
+    // take the tool off the shelf straight away, either maintenance or broken
+    pub tool_status: Option<tool::Status>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateTicketData {
+    pub status: Option<repair_ticket::Status>,
+    pub priority: Option<repair_ticket::Priority>,
+    pub assigned_to: Option<repair_ticket::AssignedTo>,
+    pub no_assignee: Option<bool>,
+    pub resolution: Option<repair_ticket::Resolution>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TicketFilterParams {
+    pub statuses: Option<Vec<repair_ticket::Status>>,
+    pub page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TicketWithPhotos {
+    #[serde(flatten)]
+    pub ticket: TicketWithText,
+    pub photos: Vec<repair_ticket_photo::RepairTicketPhoto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TicketsResponse {
+    pub tickets: Vec<TicketWithPhotos>,
+}
+
+fn is_closed(status: repair_ticket::Status) -> bool {
+    status == RepairTicketStatus::Resolved as i32 || status == RepairTicketStatus::Dismissed as i32
+}
+
+async fn select_with_photos(
+    params: repair_tickets::SelectParams,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<TicketWithPhotos>, common::ErrResponse> {
+    let tickets = match repair_tickets::select(params, db).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut photos =
+        match repair_tickets::select_photos(tickets.iter().map(|t| t.id).collect(), db).await {
+            Ok(p) => p,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    Ok(tickets
+        .into_iter()
+        .map(|ticket| {
+            let (own, rest) = photos.drain(..).partition(|p| p.ticket_id == ticket.id);
+            photos = rest;
+            TicketWithPhotos {
+                ticket,
+                photos: own,
+            }
+        })
+        .collect())
+}
+
+async fn select_one(
+    id: repair_ticket::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<TicketWithPhotos, common::ErrResponse> {
+    match select_with_photos(
+        repair_tickets::SelectParams {
+            ids: vec![id],
+            tool_ids: vec![],
+            store_ids: vec![],
+            reporter_ids: vec![],
+            statuses: vec![],
+            offset: 0,
+            limit: 1,
+        },
+        db,
+    )
+    .await?
+    .pop()
+    {
+        Some(t) => Ok(t),
+        None => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Repair ticket not found",
+        )),
+    }
+}
+
+pub async fn create_new(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewTicketData>,
+) -> Result<Json<TicketWithPhotos>, common::ErrResponse> {
+    common::verify_payload_text_length(
+        &payload.description,
+        1,
+        common::MAX_REPAIR_TICKET_DESCRIPTION_LENGTH,
+    )?;
+    common::verify_payload_integer_range(
+        payload
+            .photo_keys
+            .as_ref()
+            .map_or(0, |p| p.len())
+            .try_into()
+            .unwrap_or(i32::MAX),
+        0,
+        common::MAX_REPAIR_TICKET_PHOTOS_LENGTH,
+    )?;
+
+    if let Some(status) = payload.tool_status {
+        if status != tool::ToolStatus::Maintenance as i32
+            && status != tool::ToolStatus::Broken as i32
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Tool status can only be set to maintenance or broken",
+            ));
+        }
+    }
+
+    let reporter_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let tools = match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if tools.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Tool not found",
+        ));
+    }
+
+    if payload.tool_status.is_some() && !claims.is_tool_manager(tools[0].store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "Only a tool manager can change the tool status",
+        ));
+    }
+
+    let ticket =
+        match repair_tickets::insert(tool_id, reporter_id, payload.description, &mut *tx).await {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let photo_keys = payload.photo_keys.unwrap_or_default();
+    if !photo_keys.is_empty() {
+        let taken = photos::claim_unattached(photo_keys, &mut tx).await?;
+        if let Err(e) = repair_tickets::insert_photos(ticket.id, taken, &mut *tx).await {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    }
+
+    // a rented tool keeps its status while it's out, and the check in can pull it for repair
+    let on_shelf: Vec<tool::Tool> = tools
+        .into_iter()
+        .filter(|t| t.status != tool::ToolStatus::Rented as i32)
+        .collect();
+    if let Some(status) = payload.tool_status {
+        let changed = change_statuses(
+            &on_shelf,
+            status,
+            StatusAction::TicketOpened,
+            Some(format!("Repair ticket #{}", ticket.id)),
+            Some(reporter_id),
+            &mut tx,
+        )
+        .await?;
+
+        if !changed.is_empty() {
+            if let Err(e) = refresh_queues(changed, &mut tx).await {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
+    Ok(Json(select_one(ticket.id, &state.db).await?))
+}
+
+pub async fn get_by_tool(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TicketFilterParams>,
+) -> Result<Json<TicketsResponse>, common::ErrResponse> {
+    let user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    let store_id = match tools::select_by_ids(vec![tool_id], &state.db).await {
+        Ok(t) if t.is_empty() => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Tool not found",
+            ))
+        }
+        Ok(t) => t[0].store_id,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    // the same rule as a single ticket: a tool manager sees them all, anyone else just their own
+    let reporter_ids = if claims.is_tool_manager(store_id) {
+        vec![]
+    } else {
+        vec![user_id]
+    };
+
+    let (offset, limit) = common::calculate_offset_limit(params.page.unwrap_or_default());
+    let tickets = select_with_photos(
+        repair_tickets::SelectParams {
+            ids: vec![],
+            tool_ids: vec![tool_id],
+            store_ids: vec![],
+            reporter_ids,
+            statuses: params.statuses.unwrap_or_default(),
+            offset,
+            limit,
+        },
+        &state.db,
+    )
+    .await?;
+
+    Ok(Json(TicketsResponse { tickets }))
+}
+
+// the triage queue, open and triaged tickets unless asked otherwise
+pub async fn get_by_store(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TicketFilterParams>,
+) -> Result<Json<TicketsResponse>, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) && !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let statuses = match params.statuses {
+        Some(s) if !s.is_empty() => s,
+        _ => vec![
+            RepairTicketStatus::Open as i32,
+            RepairTicketStatus::Triaged as i32,
+        ],
+    };
+
+    let (offset, limit) = common::calculate_offset_limit(params.page.unwrap_or_default());
+    let tickets = select_with_photos(
+        repair_tickets::SelectParams {
+            ids: vec![],
+            tool_ids: vec![],
+            store_ids: vec![store_id],
+            reporter_ids: vec![],
+            statuses,
+            offset,
+            limit,
+        },
+        &state.db,
+    )
+    .await?;
+
+    Ok(Json(TicketsResponse { tickets }))
+}
+
+pub async fn get_by_id(
+    claims: Claims,
+    Path(ticket_id): Path<repair_ticket::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TicketWithPhotos>, common::ErrResponse> {
+    let ticket = select_one(ticket_id, &state.db).await?;
+
+    if claims.subject_as_user_id() != Some(ticket.ticket.reporter_id)
+        && !claims.is_tool_manager(ticket.ticket.store_id)
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is neither the reporter nor a tool manager of the store",
+        ));
+    }
+
+    Ok(Json(ticket))
+}
+
+pub async fn update(
+    claims: Claims,
+    Path(ticket_id): Path<repair_ticket::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateTicketData>,
+) -> Result<Json<TicketWithPhotos>, common::ErrResponse> {
+    common::none_or_verify_payload_integer_range(
+        payload.status,
+        RepairTicketStatus::Open as i32,
+        RepairTicketStatus::Dismissed as i32,
+    )?;
+    common::none_or_verify_payload_integer_range(
+        payload.priority,
+        repair_ticket::RepairTicketPriority::Low as i32,
+        repair_ticket::RepairTicketPriority::Urgent as i32,
+    )?;
+    common::none_or_verify_payload_text_length(
+        payload.resolution.as_deref(),
+        1,
+        common::MAX_REPAIR_TICKET_RESOLUTION_LENGTH,
+    )?;
+
+    let user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let ticket = select_one(ticket_id, &state.db).await?.ticket;
+    let store_id = ticket.store_id;
+    if !claims.is_tool_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of this store",
+        ));
+    }
+
+    // only an active tool manager of the same store can be handed a ticket
+    if let Some(assigned_to) = payload.assigned_to {
+        match permissions::select(
+            permissions::SelectParams {
+                ids: vec![],
+                user_ids: vec![assigned_to],
+                role_ids: vec![claims::Roles::ToolManager as i32],
+                store_ids: vec![store_id],
+                statuses: vec![permission::PermissionStatus::Active as i32],
+            },
+            &state.db,
+        )
+        .await
+        {
+            Ok(p) if p.is_empty() => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "Assignee is not a tool manager of this store",
+                ))
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        }
+    }
+
+    let now = time::OffsetDateTime::now_utc();
+    let status = payload.status.unwrap_or(ticket.status);
+    let (closed_at, closed_by) = if !is_closed(status) {
+        (None, None)
+    } else if is_closed(ticket.status) {
+        (ticket.closed_at, ticket.closed_by)
+    } else {
+        (Some(now), Some(user_id))
+    };
+    let assigned_to = if payload.no_assignee.unwrap_or(false) {
+        None
+    } else {
+        payload.assigned_to.or(ticket.assigned_to)
+    };
+
+    match repair_tickets::update(
+        repair_tickets::UpdateData {
+            id: ticket_id,
+            status,
+            priority: payload.priority.or(ticket.priority),
+            assigned_to,
+            resolution: payload.resolution.or(ticket.resolution),
+            updated_at: now,
+            closed_at,
+            closed_by,
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Repair ticket not found",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    }
+
+    Ok(Json(select_one(ticket_id, &state.db).await?))
+}
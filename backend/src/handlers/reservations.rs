@@ -0,0 +1,363 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{reservation, tool};
+use crate::queries::reservations::{self, ReservationWithText};
+use crate::queries::tools;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateReservationData {
+    pub tool_id: reservation::ToolId,
+    pub user_id: Option<reservation::UserId>,
+    pub start_date: reservation::StartDate,
+    pub end_date: reservation::EndDate,
+    pub quantity: Option<reservation::Quantity>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterParams {
+    pub tool_ids: Option<Vec<reservation::ToolId>>,
+    pub user_ids: Option<Vec<reservation::UserId>>,
+    pub store_ids: Option<Vec<tool::StoreId>>,
+    pub statuses: Option<Vec<reservation::Status>>,
+    pub overlapping: Option<common::DateBetween>,
+    pub page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReservationsResponse {
+    pub reservations: Vec<ReservationWithText>,
+}
+
+pub async fn create_new(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateReservationData>,
+) -> Result<Json<reservation::Reservation>, common::ErrResponse> {
+    let claims_user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+    let user_id = payload.user_id.unwrap_or(claims_user_id);
+    let quantity = payload.quantity.unwrap_or(1);
+    common::verify_payload_integer_range(quantity, 1, common::MAX_TOOL_QUANTITY)?;
+
+    let now = time::OffsetDateTime::now_utc();
+    if payload.end_date <= payload.start_date {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Reservation must end after it starts",
+        ));
+    }
+
+    if payload.end_date <= now {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Reservation must end in the future",
+        ));
+    }
+
+    if payload.start_date > now + time::Duration::days(common::MAX_RESERVATION_DAYS_AHEAD) {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Reservation starts too far in the future",
+        ));
+    }
+
+    if payload.end_date - payload.start_date > time::Duration::hours(common::MAX_RESERVATION_HOURS)
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Reservation is too long",
+        ));
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    // locking the tool serializes overlap checks, so two bookings for the same slot can't both pass
+    let tool = match tools::select_by_ids_for_update(vec![payload.tool_id], &mut *tx).await {
+        Ok(mut t) => {
+            if t.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+            t.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if user_id != claims_user_id && !claims.is_tool_manager(tool.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of this store",
+        ));
+    }
+
+    if tool.status == tool::ToolStatus::Lost as i32
+        || tool.status == tool::ToolStatus::Stolen as i32
+        || tool.status == tool::ToolStatus::Retired as i32
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Tool cannot be reserved",
+        ));
+    }
+
+    if quantity > tool.quantity {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            &format!("Tool only has {} units", tool.quantity),
+        ));
+    }
+
+    let reserved = match reservations::select_overlapping_units(
+        payload.tool_id,
+        payload.start_date,
+        payload.end_date,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(units) => units,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if reserved + i64::from(quantity) > i64::from(tool.quantity) {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            "Tool is already reserved for some of that time",
+        ));
+    }
+
+    let rented = match reservations::select_overlapping_rental_units(
+        payload.tool_id,
+        payload.start_date,
+        payload.end_date,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(units) => units,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if reserved + rented + i64::from(quantity) > i64::from(tool.quantity) {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            "Tool is rented out for some of that time",
+        ));
+    }
+
+    let reservation = match reservations::insert(
+        payload.tool_id,
+        user_id,
+        payload.start_date,
+        payload.end_date,
+        reservation::ReservationStatus::Active as i32,
+        quantity,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(reservation)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+pub async fn cancel(
+    claims: Claims,
+    Path(reservation_id): Path<reservation::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<reservation::Reservation>, common::ErrResponse> {
+    let user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    let existing = match reservations::select_by_id(reservation_id, &state.db).await {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Could not find any reservation with that id",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if existing.user_id != user_id && !claims.is_tool_manager(existing.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot cancel this reservation",
+        ));
+    }
+
+    if existing.status != reservation::ReservationStatus::Active as i32 {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Only active reservations can be cancelled",
+        ));
+    }
+
+    match reservations::update_status(
+        reservation_id,
+        reservation::ReservationStatus::Cancelled as i32,
+        &state.db,
+    )
+    .await
+    {
+        Ok(Some(r)) => Ok(Json(r)),
+        Ok(None) => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Could not find any reservation with that id",
+        )),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn get_filtered(
+    claims: Claims,
+    Query(params): Query<FilterParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReservationsResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    let (offset, limit) = common::calculate_offset_limit(params.page.unwrap_or_default());
+    reservations::select(
+        reservations::SelectParams {
+            tool_ids: params.tool_ids.unwrap_or_default(),
+            user_ids: params.user_ids.unwrap_or_default(),
+            store_ids: params.store_ids.unwrap_or_default(),
+            statuses: params.statuses.unwrap_or_default(),
+            overlapping: params.overlapping.unwrap_or_default(),
+            offset,
+            limit,
+        },
+        &state.db,
+    )
+    .await
+    .map(|reservations| Json(ReservationsResponse { reservations }))
+    .map_err(|e| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e))
+}
+
+pub async fn get_by_id(
+    claims: Claims,
+    Path(reservation_id): Path<reservation::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReservationWithText>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    match reservations::select_by_id(reservation_id, &state.db).await {
+        Ok(Some(r)) => Ok(Json(r)),
+        Ok(None) => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Could not find any reservation with that id",
+        )),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
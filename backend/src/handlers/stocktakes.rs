@@ -0,0 +1,737 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::stocktake::{self, StocktakeStatus};
+use crate::db_structs::tool_status_change::{self, StatusAction};
+use crate::db_structs::{store, tool};
+use crate::handlers::tool_holds::refresh_queues;
+use crate::handlers::tool_status_history::change_statuses;
+use crate::queries::{stocktakes, stores, tools};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewStocktakeData {
+    pub note: Option<stocktake::Note>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanData {
+    pub real_ids: Vec<tool::RealId>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndStocktakeData {
+    pub status: stocktake::Status,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkMissingData {
+    // every tool the stocktake found missing when left out
+    pub tool_ids: Option<Vec<tool::Id>>,
+    pub status: tool::Status,
+    pub reason: Option<tool_status_change::Reason>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StocktakeFilterParams {
+    pub statuses: Option<Vec<stocktake::Status>>,
+    pub page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ScanOutcome {
+    Recorded,
+    AlreadyScanned,
+    NotFound,
+    Ambiguous,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanResult {
+    pub real_id: tool::RealId,
+    pub tool_id: Option<tool::Id>,
+    pub status: Option<tool::Status>,
+    pub outcome: ScanOutcome,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanResponse {
+    pub results: Vec<ScanResult>,
+}
+
+// While the stocktake is open the missing and unexpected tools are worked out afresh each
+// time; once it's closed they're what was found at closing, with the tools as they are now.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StocktakeWithReport {
+    #[serde(flatten)]
+    pub stocktake: stocktake::Stocktake,
+    pub scanned: i64,
+    pub missing: Vec<tool::Tool>,
+    pub unexpected: Vec<tool::Tool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StocktakesResponse {
+    pub stocktakes: Vec<stocktake::Stocktake>,
+}
+
+async fn with_report(
+    stocktake: stocktake::Stocktake,
+    conn: &mut sqlx::PgConnection,
+) -> Result<StocktakeWithReport, common::ErrResponse> {
+    let scanned = match stocktakes::count_scans(stocktake.id, &mut *conn).await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let discrepancies = if stocktake.status == StocktakeStatus::Open as i32 {
+        stocktakes::select_live_discrepancies(stocktake.id, stocktake.store_id, &mut *conn).await
+    } else {
+        stocktakes::select_discrepancies(stocktake.id, &mut *conn).await
+    };
+    let discrepancies = match discrepancies {
+        Ok(d) => d,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let found = match tools::select_by_ids(
+        discrepancies.iter().map(|d| d.tool_id).collect(),
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    let tools_where = |scanned: bool| {
+        discrepancies
+            .iter()
+            .filter(|d| d.scanned == scanned)
+            .filter_map(|d| found.iter().find(|t| t.id == d.tool_id))
+            .cloned()
+            .collect::<Vec<tool::Tool>>()
+    };
+
+    Ok(StocktakeWithReport {
+        missing: tools_where(false),
+        unexpected: tools_where(true),
+        scanned,
+        stocktake,
+    })
+}
+
+async fn lock_one(
+    id: stocktake::Id,
+    conn: &mut sqlx::PgConnection,
+) -> Result<stocktake::Stocktake, common::ErrResponse> {
+    match stocktakes::select_for_update(id, &mut *conn).await {
+        Ok(Some(s)) => Ok(s),
+        Ok(None) => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Stocktake not found",
+        )),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn create_new(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewStocktakeData>,
+) -> Result<Json<StocktakeWithReport>, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let opened_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    common::none_or_verify_payload_text_length(
+        payload.note.as_deref(),
+        1,
+        common::MAX_STOCKTAKE_NOTE_LENGTH,
+    )?;
+
+    match stores::select_by_ids(vec![store_id], &state.db).await {
+        Ok(s) => {
+            if !s
+                .iter()
+                .any(|s| s.status == store::StoreStatus::Active as i32)
+            {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store not found, or not active",
+                ));
+            }
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    }
+
+    let stocktake = match stocktakes::insert(store_id, opened_by, payload.note, &state.db).await {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "The store already has an open stocktake",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut conn = match state.db.acquire().await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+    Ok(Json(with_report(stocktake, &mut conn).await?))
+}
+
+// newest first
+pub async fn get_by_store(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    Query(params): Query<StocktakeFilterParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StocktakesResponse>, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let (offset, limit) = common::calculate_offset_limit(params.page.unwrap_or_default());
+    match stocktakes::select(
+        stocktakes::SelectParams {
+            ids: vec![],
+            store_ids: vec![store_id],
+            statuses: params.statuses.unwrap_or_default(),
+            offset,
+            limit,
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(stocktakes) => Ok(Json(StocktakesResponse { stocktakes })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn get_by_id(
+    claims: Claims,
+    Path(stocktake_id): Path<stocktake::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StocktakeWithReport>, common::ErrResponse> {
+    let stocktake = match stocktakes::select(
+        stocktakes::SelectParams {
+            ids: vec![stocktake_id],
+            store_ids: vec![],
+            statuses: vec![],
+            offset: 0,
+            limit: 1,
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(mut s) => match s.pop() {
+            Some(s) => s,
+            None => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Stocktake not found",
+                ))
+            }
+        },
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if !claims.is_tool_manager(stocktake.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let mut conn = match state.db.acquire().await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+    Ok(Json(with_report(stocktake, &mut conn).await?))
+}
+
+// Records real ids from the shelf, any number of times over the life of the stocktake. Any
+// tool of the store counts, whatever its status; one that isn't on the shelf shows up as
+// unexpected when the stocktake closes.
+pub async fn scan(
+    claims: Claims,
+    Path(stocktake_id): Path<stocktake::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ScanData>,
+) -> Result<Json<ScanResponse>, common::ErrResponse> {
+    let scanned_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    if payload.real_ids.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "No real ids provided",
+        ));
+    }
+    if payload.real_ids.len() > common::MAX_STOCKTAKE_SCANS_LENGTH {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Too many real ids in one go",
+        ));
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let stocktake = lock_one(stocktake_id, &mut tx).await?;
+    if !claims.is_tool_manager(stocktake.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+    if stocktake.status != StocktakeStatus::Open as i32 {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            "Stocktake is no longer open",
+        ));
+    }
+
+    let found = match tools::select_by_store_real_ids(
+        vec![stocktake.store_id; payload.real_ids.len()],
+        payload.real_ids.clone(),
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut results: Vec<ScanResult> = Vec::new();
+    for real_id in &payload.real_ids {
+        if results.iter().any(|r| r.real_id == *real_id) {
+            continue;
+        }
+        let mut matches = found.iter().filter(|t| t.real_id == *real_id);
+        let (tool_id, status, outcome) = match (matches.next(), matches.next()) {
+            (None, _) => (None, None, ScanOutcome::NotFound),
+            (Some(_), Some(_)) => (None, None, ScanOutcome::Ambiguous),
+            (Some(t), None) => (Some(t.id), Some(t.status), ScanOutcome::Recorded),
+        };
+        results.push(ScanResult {
+            real_id: real_id.clone(),
+            tool_id,
+            status,
+            outcome,
+        });
+    }
+
+    let tool_ids: Vec<tool::Id> = results
+        .iter()
+        .filter(|r| r.outcome == ScanOutcome::Recorded)
+        .filter_map(|r| r.tool_id)
+        .collect();
+    let recorded =
+        match stocktakes::insert_scans(stocktake.id, tool_ids, scanned_by, &mut *tx).await {
+            Ok(s) => s.into_iter().map(|s| s.tool_id).collect::<Vec<tool::Id>>(),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+    for result in results.iter_mut() {
+        if result.outcome == ScanOutcome::Recorded
+            && !result.tool_id.is_some_and(|id| recorded.contains(&id))
+        {
+            result.outcome = ScanOutcome::AlreadyScanned;
+        }
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(ScanResponse { results })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+// Closing keeps what the scans add up to: available tools nobody scanned, and scanned tools
+// that weren't on the shelf. Cancelling just ends it, and keeps nothing.
+pub async fn update_status(
+    claims: Claims,
+    Path(stocktake_id): Path<stocktake::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<EndStocktakeData>,
+) -> Result<Json<StocktakeWithReport>, common::ErrResponse> {
+    let closed_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    if payload.status != StocktakeStatus::Closed as i32
+        && payload.status != StocktakeStatus::Cancelled as i32
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Stocktakes can only be closed or cancelled",
+        ));
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let stocktake = lock_one(stocktake_id, &mut tx).await?;
+    if !claims.is_tool_manager(stocktake.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let stocktake = match stocktakes::end(stocktake.id, payload.status, closed_by, &mut *tx).await {
+        Ok(Some(s)) => s,
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "Stocktake is no longer open",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if payload.status == StocktakeStatus::Closed as i32 {
+        if let Err(e) =
+            stocktakes::insert_discrepancies(stocktake.id, stocktake.store_id, &mut *tx).await
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    }
+
+    let report = with_report(stocktake, &mut tx).await?;
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(report)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+// Marks tools a closed stocktake found missing as lost, or as unknown while someone has
+// another look. A tool that has gone out or changed status since closing is refused.
+pub async fn mark_missing(
+    claims: Claims,
+    Path(stocktake_id): Path<stocktake::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<MarkMissingData>,
+) -> Result<Json<StocktakeWithReport>, common::ErrResponse> {
+    let changed_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    if payload.status != tool::ToolStatus::Lost as i32
+        && payload.status != tool::ToolStatus::Unknown as i32
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Missing tools can only be marked lost or unknown",
+        ));
+    }
+    common::none_or_verify_payload_text_length(
+        payload.reason.as_deref(),
+        1,
+        common::MAX_TOOL_STATUS_REASON_LENGTH,
+    )?;
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let stocktake = lock_one(stocktake_id, &mut tx).await?;
+    if !claims.is_tool_manager(stocktake.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+    if stocktake.status != StocktakeStatus::Closed as i32 {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            "Only a closed stocktake has missing tools",
+        ));
+    }
+
+    let missing: Vec<tool::Id> =
+        match stocktakes::select_discrepancies(stocktake.id, &mut *tx).await {
+            Ok(d) => d.iter().filter(|d| !d.scanned).map(|d| d.tool_id).collect(),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let tool_ids = match payload.tool_ids {
+        Some(mut ids) => {
+            ids.sort_unstable();
+            ids.dedup();
+            if ids.iter().any(|id| !missing.contains(id)) {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    "Some tools were not found missing by the stocktake",
+                ));
+            }
+            ids
+        }
+        None => missing,
+    };
+    if tool_ids.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "No missing tools to mark",
+        ));
+    }
+
+    let found = match tools::select_by_ids_for_update(tool_ids, &mut *tx).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    // moved to another store since
+    let moved: Vec<&str> = found
+        .iter()
+        .filter(|t| t.store_id != stocktake.store_id)
+        .map(|t| t.real_id.as_str())
+        .collect();
+    if !moved.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!("Tools no longer in the store: {}", moved.join(", ")),
+        ));
+    }
+
+    let changed = change_statuses(
+        &found,
+        payload.status,
+        StatusAction::Stocktake,
+        Some(
+            payload
+                .reason
+                .unwrap_or_else(|| format!("Missing in stocktake #{}", stocktake.id)),
+        ),
+        Some(changed_by),
+        &mut tx,
+    )
+    .await?;
+
+    if !changed.is_empty() {
+        if let Err(e) = refresh_queues(changed.clone(), &mut tx).await {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    }
+
+    let report = with_report(stocktake, &mut tx).await?;
+
+    match tx.commit().await {
+        Ok(_) => {
+            for tool in report.missing.iter().filter(|t| changed.contains(&t.id)) {
+                let encoded = serde_json::to_vec(tool).unwrap_or_default();
+                state.comm.send_message("tools", &encoded).await.ok();
+            }
+            Ok(Json(report))
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
@@ -0,0 +1,362 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{store, store_holiday, store_hours};
+use crate::opening_hours::{self, Schedule};
+use crate::queries::{store_hours as hours_queries, stores};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpeningHoursData {
+    pub weekday: store_hours::Weekday,
+    pub opens_at: store_hours::OpensAt,
+    pub closes_at: store_hours::ClosesAt,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettableHoursData {
+    pub timezone: Option<store::Timezone>,
+    pub hours: Vec<OpeningHoursData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewHolidayData {
+    pub starts_on: store_holiday::StartsOn,
+    pub ends_on: store_holiday::EndsOn,
+    pub name: Option<store_holiday::Name>,
+}
+
+// open_now and opens_at are left out for a store that has not set any hours
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenStatus {
+    pub open_now: Option<bool>,
+    pub opens_at: Option<time::OffsetDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreHoursResponse {
+    pub store_id: store::Id,
+    pub timezone: store::Timezone,
+    pub hours: Vec<store_hours::StoreHours>,
+    pub holidays: Vec<store_holiday::StoreHoliday>,
+    #[serde(flatten)]
+    pub status: OpenStatus,
+}
+
+pub fn open_status(schedule: &Schedule, at: time::OffsetDateTime) -> OpenStatus {
+    if !schedule.has_hours() {
+        return OpenStatus::default();
+    }
+
+    OpenStatus {
+        open_now: Some(schedule.is_open_at(at)),
+        opens_at: schedule.opens_at(at),
+    }
+}
+
+// one schedule per store asked for, in no particular order; unknown stores are left out
+pub async fn select_schedules(
+    store_ids: Vec<store::Id>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<(store::Id, Schedule)>, common::ErrResponse> {
+    let make_db_error =
+        |e: String| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e);
+
+    let found = stores::select_by_ids(store_ids.clone(), &mut *conn)
+        .await
+        .map_err(make_db_error)?;
+    let hours = hours_queries::select_by_store_ids(store_ids.clone(), &mut *conn)
+        .await
+        .map_err(make_db_error)?;
+    // a day of slack, since the store's local date can lag behind utc
+    let yesterday = time::OffsetDateTime::now_utc()
+        .date()
+        .previous_day()
+        .unwrap_or(time::Date::MIN);
+    let holidays = hours_queries::select_holidays(store_ids, yesterday, &mut *conn)
+        .await
+        .map_err(make_db_error)?;
+
+    Ok(found
+        .into_iter()
+        .map(|s| {
+            let schedule = Schedule::new(
+                &s.timezone,
+                hours
+                    .iter()
+                    .filter(|h| h.store_id == s.id)
+                    .cloned()
+                    .collect(),
+                holidays
+                    .iter()
+                    .filter(|h| h.store_id == s.id)
+                    .cloned()
+                    .collect(),
+            );
+            (s.id, schedule)
+        })
+        .collect())
+}
+
+pub async fn get_by_store(
+    Path(store_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StoreHoursResponse>, common::ErrResponse> {
+    let mut conn = match state.db.acquire().await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let store = match stores::select_by_ids(vec![store_id], &mut *conn).await {
+        Ok(mut s) => {
+            if s.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store not found",
+                ));
+            }
+            s.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let status = match select_schedules(vec![store_id], &mut conn).await?.pop() {
+        Some((_, schedule)) => open_status(&schedule, time::OffsetDateTime::now_utc()),
+        None => OpenStatus::default(),
+    };
+
+    let hours = match hours_queries::select_by_store_ids(vec![store_id], &mut *conn).await {
+        Ok(h) => h,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let today = time::OffsetDateTime::now_utc().date();
+    let holidays = match hours_queries::select_holidays(vec![store_id], today, &mut *conn).await {
+        Ok(h) => h,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    Ok(Json(StoreHoursResponse {
+        store_id,
+        timezone: store.timezone,
+        hours,
+        holidays,
+        status,
+    }))
+}
+
+pub async fn update(
+    claims: Claims,
+    Path(store_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SettableHoursData>,
+) -> Result<Json<StoreHoursResponse>, common::ErrResponse> {
+    if !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a store rep",
+        ));
+    }
+
+    if payload
+        .timezone
+        .as_deref()
+        .is_some_and(|tz| opening_hours::find_timezone(tz).is_none())
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Unknown timezone",
+        ));
+    }
+    common::verify_payload_integer_range(
+        payload.hours.len().try_into().unwrap_or(i32::MAX),
+        0,
+        common::MAX_STORE_HOURS_LENGTH,
+    )?;
+    for h in &payload.hours {
+        common::verify_payload_integer_range(h.weekday, 1, 7)?;
+        // closing earlier than opening means closing the next day, as with midnight
+        if h.opens_at == h.closes_at {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Opening hours must close at a different time than they open",
+            ));
+        }
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    if let Some(timezone) = payload.timezone {
+        match stores::update_timezone(store_id, timezone, &mut *tx).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store not found",
+                ))
+            }
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        }
+    }
+
+    if let Err(e) = hours_queries::delete_by_store_id(store_id, &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = hours_queries::insert_many(
+        store_id,
+        payload.hours.iter().map(|h| h.weekday).collect(),
+        payload.hours.iter().map(|h| h.opens_at).collect(),
+        payload.hours.iter().map(|h| h.closes_at).collect(),
+        &mut *tx,
+    )
+    .await
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
+    get_by_store(Path(store_id), State(state)).await
+}
+
+pub async fn create_holiday(
+    claims: Claims,
+    Path(store_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewHolidayData>,
+) -> Result<Json<store_holiday::StoreHoliday>, common::ErrResponse> {
+    if !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a store rep",
+        ));
+    }
+
+    if payload.starts_on > payload.ends_on {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Holiday must end on or after the day it starts",
+        ));
+    }
+    common::none_or_verify_payload_text_length(
+        payload.name.as_deref(),
+        1,
+        common::MAX_STORE_HOLIDAY_NAME_LENGTH,
+    )?;
+
+    match hours_queries::insert_holiday(
+        store_id,
+        payload.starts_on,
+        payload.ends_on,
+        payload.name,
+        &state.db,
+    )
+    .await
+    {
+        Ok(h) => Ok(Json(h)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn delete_holiday(
+    claims: Claims,
+    Path((store_id, holiday_id)): Path<(i32, i32)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<common::NoData>, common::ErrResponse> {
+    if !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a store rep",
+        ));
+    }
+
+    match hours_queries::delete_holiday(holiday_id, store_id, &state.db).await {
+        Ok(0) => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Holiday not found",
+        )),
+        Ok(_) => Ok(Json(common::NoData {})),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
@@ -0,0 +1,268 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::store_policy::{self, PolicyRule, PolicyViolation, StorePolicy};
+use crate::db_structs::{rental, store, tool, user};
+use crate::queries::{rentals, store_policies, users};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettableStorePolicy {
+    pub max_open_rentals: Option<store_policy::MaxOpenRentals>,
+    pub max_tools_per_check_out: Option<store_policy::MaxToolsPerCheckOut>,
+    pub min_account_age_days: Option<store_policy::MinAccountAgeDays>,
+    pub block_overdue: Option<store_policy::BlockOverdue>,
+    pub max_rental_hours: Option<store_policy::MaxRentalHours>,
+}
+
+// what a check out looks like from the point of view of a single store
+pub struct CheckOutSummary {
+    pub store_id: store::Id,
+    pub tool_count: i64,
+    pub open_count: i64,
+    pub overdue_count: i64,
+    pub account_age_days: i64,
+    pub requested_hours: Option<i64>,
+}
+
+pub fn evaluate(policy: &StorePolicy, summary: &CheckOutSummary) -> Vec<PolicyViolation> {
+    let mut violations = vec![];
+    let mut violate = |rule: PolicyRule, limit: i64, actual: i64, message: String| {
+        violations.push(PolicyViolation {
+            store_id: summary.store_id,
+            rule,
+            limit,
+            actual,
+            message,
+        })
+    };
+
+    if let Some(max) = policy.max_tools_per_check_out.map(i64::from) {
+        if summary.tool_count > max {
+            violate(
+                PolicyRule::MaxToolsPerCheckOut,
+                max,
+                summary.tool_count,
+                format!("At most {} tools can be checked out at once", max),
+            );
+        }
+    }
+
+    if let Some(max) = policy.max_open_rentals.map(i64::from) {
+        let after = summary.open_count + summary.tool_count;
+        if after > max {
+            violate(
+                PolicyRule::MaxOpenRentals,
+                max,
+                after,
+                format!("At most {} tools can be out at the same time", max),
+            );
+        }
+    }
+
+    if let Some(min) = policy.min_account_age_days.map(i64::from) {
+        if summary.account_age_days < min {
+            violate(
+                PolicyRule::MinAccountAge,
+                min,
+                summary.account_age_days,
+                format!("Account must be at least {} days old", min),
+            );
+        }
+    }
+
+    if policy.block_overdue && summary.overdue_count > 0 {
+        violate(
+            PolicyRule::NoOverdueRentals,
+            0,
+            summary.overdue_count,
+            "Overdue tools must be returned first".to_string(),
+        );
+    }
+
+    if let (Some(max), Some(requested)) = (
+        policy.max_rental_hours.map(i64::from),
+        summary.requested_hours,
+    ) {
+        if requested > max {
+            violate(
+                PolicyRule::MaxRentalHours,
+                max,
+                requested,
+                format!("Tools can be borrowed for at most {} hours", max),
+            );
+        }
+    }
+
+    violations
+}
+
+// open rentals are counted per store, but an overdue tool from anywhere counts against the renter;
+// quantities line up with tools, so a pooled tool counts once per unit
+pub async fn verify_check_out(
+    renter_id: user::Id,
+    tools: &[tool::Tool],
+    quantities: &[rental::Quantity],
+    due_date: Option<rental::DueDate>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), common::ErrResponse> {
+    let mut store_ids: Vec<store::Id> = tools.iter().map(|t| t.store_id).collect();
+    store_ids.sort_unstable();
+    store_ids.dedup();
+
+    let policies = match store_policies::select_by_store_ids(store_ids, &mut *conn).await {
+        Ok(p) => p,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if policies.is_empty() {
+        return Ok(());
+    }
+
+    let now = time::OffsetDateTime::now_utc();
+    let open_counts = match rentals::select_open_counts(renter_id, now, &mut *conn).await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    let overdue_count = open_counts.iter().map(|c| c.overdue_count).sum();
+
+    let account_age_days = match users::select_by_ids(vec![renter_id], &mut *conn).await {
+        Ok(u) => u
+            .first()
+            .map(|u| (now - u.created_at).whole_days())
+            .unwrap_or_default(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    // rounded up, so a due date a few minutes past the limit still counts
+    let requested_hours = due_date.map(|d| ((d - now).whole_minutes() + 59) / 60);
+
+    let violations: Vec<PolicyViolation> = policies
+        .iter()
+        .flat_map(|policy| {
+            evaluate(
+                policy,
+                &CheckOutSummary {
+                    store_id: policy.store_id,
+                    tool_count: tools
+                        .iter()
+                        .zip(quantities.iter())
+                        .filter(|(t, _)| t.store_id == policy.store_id)
+                        .map(|(_, q)| i64::from(*q))
+                        .sum(),
+                    open_count: open_counts
+                        .iter()
+                        .find(|c| c.store_id == policy.store_id)
+                        .map_or(0, |c| c.open_count),
+                    overdue_count,
+                    account_age_days,
+                    requested_hours,
+                },
+            )
+        })
+        .collect();
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    let details = violations
+        .iter()
+        .map(|v| v.message.as_str())
+        .collect::<Vec<&str>>()
+        .join("; ");
+    Err(
+        common::ErrResponse::new(StatusCode::CONFLICT, "ERR_CONFLICT", &details)
+            .with_violations(violations),
+    )
+}
+
+pub async fn get_by_store(
+    Path(store_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<StorePolicy>, common::ErrResponse> {
+    match store_policies::select_by_store_ids(vec![store_id], &state.db).await {
+        Ok(mut p) => {
+            if p.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Store has no borrowing policy",
+                ));
+            }
+            Ok(Json(p.remove(0)))
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn update(
+    claims: Claims,
+    Path(store_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SettableStorePolicy>,
+) -> Result<Json<StorePolicy>, common::ErrResponse> {
+    if !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a store rep",
+        ));
+    }
+
+    common::none_or_verify_payload_integer_range(payload.max_open_rentals, 1, i32::MAX)?;
+    common::none_or_verify_payload_integer_range(payload.max_tools_per_check_out, 1, i32::MAX)?;
+    common::none_or_verify_payload_integer_range(
+        payload.min_account_age_days,
+        0,
+        common::MAX_POLICY_ACCOUNT_AGE_DAYS,
+    )?;
+    common::none_or_verify_payload_integer_range(payload.max_rental_hours, 1, i32::MAX)?;
+
+    match store_policies::upsert(
+        StorePolicy {
+            store_id,
+            max_open_rentals: payload.max_open_rentals,
+            max_tools_per_check_out: payload.max_tools_per_check_out,
+            min_account_age_days: payload.min_account_age_days,
+            block_overdue: payload.block_overdue.unwrap_or_default(),
+            max_rental_hours: payload.max_rental_hours,
+            updated_at: time::OffsetDateTime::now_utc(),
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(p) => Ok(Json(p)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
@@ -1,7 +1,10 @@
 use crate::auth::claims;
 use crate::common;
-use crate::db_structs::{permission, tool};
-use crate::queries::{permissions, stores, tools};
+use crate::db_structs::tool_status_change::StatusAction;
+use crate::db_structs::{permission, store_policy, tool};
+use crate::handlers::store_hours::{self, OpenStatus};
+use crate::handlers::tool_status_history::change_statuses;
+use crate::queries::{permissions, store_policies, stores, tools};
 use crate::AppState;
 use crate::{auth::claims::Claims, db_structs::store};
 use axum::{
@@ -43,10 +46,27 @@ pub struct FilterParams {
     pub page: Option<i64>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreWithPolicy {
+    #[serde(flatten)]
+    pub store: store::Store,
+    pub policy: Option<store_policy::StorePolicy>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoreWithOpenStatus {
+    #[serde(flatten)]
+    pub store: store::Store,
+    #[serde(flatten)]
+    pub status: OpenStatus,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FilteredResponse {
-    pub stores: Vec<store::Store>,
+    pub stores: Vec<StoreWithOpenStatus>,
 }
 
 pub async fn create_new(
@@ -272,18 +292,26 @@ pub async fn update_status(
         }
     };
 
+    // the search index filters stores by status too
+    let encoded = serde_json::to_vec(&updated_store).unwrap_or_default();
+    state.comm.send_message("stores", &encoded).await.ok();
+
     if payload.status == store::StoreStatus::Active as i32 {
         return Ok(Json(updated_store));
     }
 
     let tools = match tools::select(
         tools::SelectParams {
+            ids: vec![],
             term: "".to_string(),
             statuses: vec![tool::ToolStatus::Available as i32],
             store_ids: vec![store_id],
             category_ids: vec![],
             match_all_categories: false,
             real_ids: vec![],
+            asset_store_ids: vec![],
+            min_available: 0,
+            order_by: tools::OrderBy::Id,
             offset: 0,
             limit: 1000,
         },
@@ -301,19 +329,36 @@ pub async fn update_status(
         }
     };
 
-    for tool in &tools {
-        match tools::update(
-            tool.id,
-            None,
-            None,
-            None,
-            None,
-            Some(tool::ToolStatus::Unknown as i32),
-            &state.db,
-        )
-        .await
+    let changed_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let tools =
+        match tools::select_by_ids_for_update(tools.iter().map(|t| t.id).collect(), &mut *tx).await
         {
-            Ok(_) => (),
+            // one could have gone out since the search above
+            Ok(t) => t
+                .into_iter()
+                .filter(|t| t.status == tool::ToolStatus::Available as i32)
+                .collect::<Vec<tool::Tool>>(),
             Err(e) => {
                 return Err(common::ErrResponse::new(
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -321,7 +366,24 @@ pub async fn update_status(
                     &e,
                 ))
             }
-        }
+        };
+
+    change_statuses(
+        &tools,
+        tool::ToolStatus::Unknown as i32,
+        StatusAction::StoreClosed,
+        None,
+        Some(changed_by),
+        &mut tx,
+    )
+    .await?;
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
     }
 
     Ok(Json(updated_store))
@@ -331,7 +393,7 @@ pub async fn get_by_id(
     claims: Claims,
     Path(store_id): Path<i32>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<store::Store>, common::ErrResponse> {
+) -> Result<Json<StoreWithPolicy>, common::ErrResponse> {
     let mut store = match stores::select_by_ids(vec![store_id], &state.db).await {
         Ok(mut s) => {
             if s.is_empty() {
@@ -366,7 +428,18 @@ pub async fn get_by_id(
         store.code = String::new();
     }
 
-    Ok(Json(store))
+    let policy = match store_policies::select_by_store_ids(vec![store_id], &state.db).await {
+        Ok(mut p) => p.pop(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    Ok(Json(StoreWithPolicy { store, policy }))
 }
 
 pub async fn get_filtered(
@@ -378,40 +451,53 @@ pub async fn get_filtered(
     let can_see_contact_info = !claims.is_none();
     let can_see_code = claims.is_store_admin();
 
-    if !can_see_contact_info {
-        stores::select_no_contact(
-            stores::SelectParams {
-                ids: vec![],
-                statuses: params.statuses.unwrap_or_default(),
-                term: params.term.unwrap_or_default(),
-                user_ids: params.user_ids.unwrap_or_default(),
-                offset: offset,
-                limit: limit,
-            },
-            &state.db,
-        )
-        .await
-        .map(|stores| Json(FilteredResponse { stores }))
-        .map_err(|e| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e))
-    } else {
-        stores::select(
+    let found = state
+        .search
+        .search_stores(
             stores::SelectParams {
                 ids: vec![],
                 statuses: params.statuses.unwrap_or_default(),
                 term: params.term.unwrap_or_default(),
                 user_ids: params.user_ids.unwrap_or_default(),
-                offset: offset,
-                limit: limit,
+                offset,
+                limit,
             },
-            &state.db,
+            can_see_contact_info,
         )
         .await
         .map(|mut stores| {
             if !can_see_code {
                 stores.iter_mut().for_each(|s| s.code = String::new());
             }
-            Json(FilteredResponse { stores })
+            stores
         })
-        .map_err(|e| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e))
-    }
+        .map_err(|e| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e))?;
+
+    let mut conn = match state.db.acquire().await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+    let store_ids: Vec<store::Id> = found.iter().map(|s| s.id).collect();
+    let schedules = store_hours::select_schedules(store_ids, &mut conn).await?;
+
+    let now = time::OffsetDateTime::now_utc();
+    let stores = found
+        .into_iter()
+        .map(|store| {
+            let status = schedules
+                .iter()
+                .find(|(id, _)| *id == store.id)
+                .map(|(_, schedule)| store_hours::open_status(schedule, now))
+                .unwrap_or_default();
+            StoreWithOpenStatus { store, status }
+        })
+        .collect();
+
+    Ok(Json(FilteredResponse { stores }))
 }
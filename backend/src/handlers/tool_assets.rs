@@ -0,0 +1,324 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::tool_asset::{self, ToolAcquisitionSource, ToolAsset};
+use crate::db_structs::{store, tool};
+use crate::handlers::tool_imports::TransferFormat;
+use crate::queries::tool_assets::{self, RegisterRow};
+use crate::queries::{tools, users};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// replaces whatever was there before, so a field left out is cleared
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettableToolAsset {
+    pub brand: Option<tool_asset::Brand>,
+    pub model: Option<tool_asset::Model>,
+    pub serial_number: Option<tool_asset::SerialNumber>,
+    pub acquired_on: Option<tool_asset::AcquiredOn>,
+    pub acquisition_source: Option<tool_asset::AcquisitionSource>,
+    // only for a donated tool
+    pub donated_by: Option<tool_asset::DonatedBy>,
+    pub replacement_value: Option<tool_asset::ReplacementValue>,
+    pub warranty_expires_on: Option<tool_asset::WarrantyExpiresOn>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterParams {
+    pub format: Option<TransferFormat>,
+}
+
+// Tools without a replacement value count towards tools and units but not the value, and are
+// counted again in unvalued so a report can say how complete it is.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusTotal {
+    pub status: tool::Status,
+    pub tools: i64,
+    pub units: i64,
+    pub unvalued: i64,
+    pub value: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetRegister {
+    pub store_id: store::Id,
+    pub tools: Vec<RegisterRow>,
+    pub totals: Vec<StatusTotal>,
+    pub total_value: i64,
+}
+
+async fn select_tool(
+    tool_id: tool::Id,
+    db: &sqlx::PgPool,
+) -> Result<tool::Tool, common::ErrResponse> {
+    match tools::select_by_ids(vec![tool_id], db).await {
+        Ok(mut t) if !t.is_empty() => Ok(t.remove(0)),
+        Ok(_) => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Tool not found",
+        )),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+// a pooled tool is worth its replacement value once per unit
+fn totals_by_status(rows: &[RegisterRow]) -> Vec<StatusTotal> {
+    let mut totals: Vec<StatusTotal> = vec![];
+    for row in rows {
+        let index = match totals.iter().position(|t| t.status == row.status) {
+            Some(i) => i,
+            None => {
+                totals.push(StatusTotal {
+                    status: row.status,
+                    tools: 0,
+                    units: 0,
+                    unvalued: 0,
+                    value: 0,
+                });
+                totals.len() - 1
+            }
+        };
+        let total = &mut totals[index];
+        total.tools += 1;
+        total.units += i64::from(row.quantity);
+        match row.replacement_value {
+            Some(value) => total.value += i64::from(value) * i64::from(row.quantity),
+            None => total.unvalued += 1,
+        }
+    }
+    totals.sort_by_key(|t| t.status);
+    totals
+}
+
+pub async fn get_by_tool(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ToolAsset>, common::ErrResponse> {
+    let tool = select_tool(tool_id, &state.db).await?;
+    if !claims.is_tool_manager(tool.store_id) && !claims.is_store_manager(tool.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    match tool_assets::select_by_tool_ids(vec![tool_id], &state.db).await {
+        Ok(mut a) => {
+            if a.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool has no asset details",
+                ));
+            }
+            Ok(Json(a.remove(0)))
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn update(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SettableToolAsset>,
+) -> Result<Json<ToolAsset>, common::ErrResponse> {
+    let tool = select_tool(tool_id, &state.db).await?;
+    if !claims.is_tool_manager(tool.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    for text in [&payload.brand, &payload.model, &payload.serial_number] {
+        common::none_or_verify_payload_text_length(
+            text.as_deref(),
+            1,
+            common::MAX_TOOL_ASSET_FIELD_LENGTH,
+        )?;
+    }
+    common::none_or_verify_payload_integer_range(
+        payload.replacement_value,
+        0,
+        common::MAX_LEDGER_AMOUNT,
+    )?;
+
+    if let Some(source) = payload.acquisition_source {
+        if source != ToolAcquisitionSource::Purchased as i32
+            && source != ToolAcquisitionSource::Donated as i32
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Unknown acquisition source",
+            ));
+        }
+    }
+
+    if let (Some(acquired_on), Some(expires_on)) =
+        (payload.acquired_on, payload.warranty_expires_on)
+    {
+        if expires_on < acquired_on {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Warranty cannot expire before the tool was acquired",
+            ));
+        }
+    }
+
+    if let Some(donated_by) = payload.donated_by {
+        if payload.acquisition_source != Some(ToolAcquisitionSource::Donated as i32) {
+            return Err(common::ErrResponse::new(
+                StatusCode::BAD_REQUEST,
+                "ERR_REQ",
+                "Only a donated tool has a donor",
+            ));
+        }
+
+        match users::select_by_ids(vec![donated_by], &state.db).await {
+            Ok(u) if u.is_empty() => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Donor not found",
+                ))
+            }
+            Ok(_) => {}
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        }
+    }
+
+    match tool_assets::upsert(
+        ToolAsset {
+            tool_id,
+            brand: payload.brand,
+            model: payload.model,
+            serial_number: payload.serial_number,
+            acquired_on: payload.acquired_on,
+            acquisition_source: payload.acquisition_source,
+            donated_by: payload.donated_by,
+            replacement_value: payload.replacement_value,
+            warranty_expires_on: payload.warranty_expires_on,
+            updated_at: time::OffsetDateTime::now_utc(),
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(a) => {
+            // brand, model and serial number are searched with the tool
+            let encoded = serde_json::to_vec(&tool).unwrap_or_default();
+            state.comm.send_message("tools", &encoded).await.ok();
+            Ok(Json(a))
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+// Every tool in the store with its asset details, and the replacement value totalled by status
+// so losses (lost and stolen tools) can be reported. A csv has the tools only.
+pub async fn get_register(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    Query(params): Query<RegisterParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) && !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let rows = match tool_assets::select_register(store_id, &state.db).await {
+        Ok(r) => r,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match params.format.unwrap_or_default() {
+        TransferFormat::Json => {
+            let totals = totals_by_status(&rows);
+            let total_value = totals.iter().map(|t| t.value).sum();
+            Ok(Json(AssetRegister {
+                store_id,
+                tools: rows,
+                totals,
+                total_value,
+            })
+            .into_response())
+        }
+        TransferFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for row in rows {
+                if let Err(e) = writer.serialize(row) {
+                    return Err(common::ErrResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "ERR_LOGIC",
+                        &e.to_string(),
+                    ));
+                }
+            }
+            let data = match writer.into_inner() {
+                Ok(data) => data,
+                Err(e) => {
+                    return Err(common::ErrResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "ERR_LOGIC",
+                        &e.to_string(),
+                    ))
+                }
+            };
+
+            let attachment_header = format!(
+                "attachment; filename=\"store-{}-asset-register.csv\"",
+                store_id
+            );
+            let headers = [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (header::CONTENT_DISPOSITION, attachment_header.as_str()),
+            ];
+            Ok((headers, data).into_response())
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{tool, tool_hold};
+use crate::queries::tool_holds::{self, HoldWithText};
+use crate::queries::tools;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HoldsResponse {
+    pub holds: Vec<HoldWithText>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorderData {
+    pub hold_ids: Vec<tool_hold::Id>,
+}
+
+// Expires lapsed pickup windows, then makes sure the first person in line holds every available tool.
+// Call after anything that changes a tool's status or the order of its queue.
+pub async fn refresh_queues(
+    tool_ids: Vec<tool::Id>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), String> {
+    let now = time::OffsetDateTime::now_utc();
+    tool_holds::expire_lapsed(tool_ids.clone(), now, &mut *conn).await?;
+    tool_holds::demote_stale(tool_ids.clone(), &mut *conn).await?;
+    tool_holds::promote_first(tool_ids, now, common::HOLD_PICKUP_WINDOW_HOURS, &mut *conn).await?;
+    Ok(())
+}
+
+fn active_statuses() -> Vec<tool_hold::Status> {
+    vec![
+        tool_hold::HoldStatus::Waiting as i32,
+        tool_hold::HoldStatus::Ready as i32,
+    ]
+}
+
+pub async fn join(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<tool_hold::ToolHold>, common::ErrResponse> {
+    let user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    // locking the tool serializes queue positions
+    let tool = match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
+        Ok(mut t) => {
+            if t.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+            t.remove(0)
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if let Err(e) = refresh_queues(vec![tool_id], &mut tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    let on_hold = match tool_holds::select_on_hold(vec![tool_id], &mut *tx).await {
+        Ok(h) => !h.is_empty(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let can_queue = tool.status == tool::ToolStatus::Rented as i32
+        || tool.status == tool::ToolStatus::Maintenance as i32
+        || (tool.status == tool::ToolStatus::Available as i32 && on_hold);
+    if !can_queue {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Only rented, in maintenance, or on hold tools can be held",
+        ));
+    }
+
+    let hold = match tool_holds::insert(tool_id, user_id, &mut *tx).await {
+        Ok(h) => h,
+        Err(e) => {
+            if e.contains("idx_tool_holds_active_tool_user") {
+                return Err(common::ErrResponse::new(
+                    StatusCode::CONFLICT,
+                    "ERR_DUP",
+                    "User is already in the queue for this tool",
+                ));
+            }
+
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(hold)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+pub async fn leave(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<common::NoData>, common::ErrResponse> {
+    let user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
+        Ok(t) => {
+            if t.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match tool_holds::update_statuses(
+        vec![tool_id],
+        vec![user_id],
+        tool_hold::HoldStatus::Cancelled as i32,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(h) => {
+            if h.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "User is not in the queue for this tool",
+                ));
+            }
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    }
+
+    if let Err(e) = refresh_queues(vec![tool_id], &mut tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(common::NoData {})),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+// read only; the queues are refreshed by whatever changes them, so a lapsed pickup window shows
+// until the next change to that tool
+pub async fn get_by_tool(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<HoldsResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    tool_holds::select(
+        tool_holds::SelectParams {
+            tool_ids: vec![tool_id],
+            user_ids: vec![],
+            statuses: active_statuses(),
+        },
+        &state.db,
+    )
+    .await
+    .map(|holds| Json(HoldsResponse { holds }))
+    .map_err(|e| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e))
+}
+
+// a user sees all of their own holds, and a tool manager only the ones at their stores
+pub async fn get_by_user(
+    claims: Claims,
+    Path(user_id): Path<tool_hold::UserId>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<HoldsResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    let sees_all = claims.subject_as_user_id() == Some(user_id) || claims.is_user_admin();
+    if !sees_all && !claims.is_any_tool_manager() {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot see the holds of another user",
+        ));
+    }
+
+    match tool_holds::select(
+        tool_holds::SelectParams {
+            tool_ids: vec![],
+            user_ids: vec![user_id],
+            statuses: active_statuses(),
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(holds) => Ok(Json(HoldsResponse {
+            holds: holds
+                .into_iter()
+                .filter(|h| sees_all || claims.is_tool_manager(h.store_id))
+                .collect(),
+        })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+pub async fn reorder(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ReorderData>,
+) -> Result<Json<HoldsResponse>, common::ErrResponse> {
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
+        Ok(t) => {
+            if t.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+
+            if !claims.is_tool_manager(t[0].store_id) {
+                return Err(common::ErrResponse::new(
+                    StatusCode::FORBIDDEN,
+                    "ERR_AUTH",
+                    "User is not a tool manager of this store",
+                ));
+            }
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let existing = match tool_holds::select(
+        tool_holds::SelectParams {
+            tool_ids: vec![tool_id],
+            user_ids: vec![],
+            statuses: active_statuses(),
+        },
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(h) => h,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut sorted_ids = payload.hold_ids.clone();
+    sorted_ids.sort();
+    sorted_ids.dedup();
+    let mut existing_ids: Vec<tool_hold::Id> = existing.iter().map(|h| h.id).collect();
+    existing_ids.sort();
+    if sorted_ids.len() != payload.hold_ids.len() || sorted_ids != existing_ids {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Hold ids must list every hold in the queue exactly once",
+        ));
+    }
+
+    let positions = (1..=payload.hold_ids.len() as i32).collect();
+    if let Err(e) = tool_holds::update_positions(payload.hold_ids, positions, &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = refresh_queues(vec![tool_id], &mut tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    let holds = match tool_holds::select(
+        tool_holds::SelectParams {
+            tool_ids: vec![tool_id],
+            user_ids: vec![],
+            statuses: active_statuses(),
+        },
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(h) => h,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(HoldsResponse { holds })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+pub async fn clear(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<common::NoData>, common::ErrResponse> {
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
+        Ok(t) => {
+            if t.is_empty() {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+
+            if !claims.is_tool_manager(t[0].store_id) {
+                return Err(common::ErrResponse::new(
+                    StatusCode::FORBIDDEN,
+                    "ERR_AUTH",
+                    "User is not a tool manager of this store",
+                ));
+            }
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if let Err(e) = tool_holds::update_statuses(
+        vec![tool_id],
+        vec![],
+        tool_hold::HoldStatus::Cancelled as i32,
+        &mut *tx,
+    )
+    .await
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(common::NoData {})),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
@@ -0,0 +1,680 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::tool_classification::ToolClassification;
+use crate::db_structs::{store, tool, tool_category, tool_kit};
+use crate::queries::{
+    stores, tool_categories, tool_classifications, tool_kits, tool_status_history, tools,
+};
+use crate::tool_lifecycle;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportParams {
+    pub format: Option<TransferFormat>,
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportParams {
+    pub format: Option<TransferFormat>,
+}
+
+// One tool, as it is imported and exported. Categories are names (or synonyms) rather than
+// ids so a sheet can be filled in by hand. Photos are left out, add them to each tool after.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolRow {
+    pub real_id: Option<tool::RealId>,
+    pub short_description: tool::ShortDescription,
+    pub long_description: Option<tool::LongDescription>,
+    pub rental_hours: tool::RentalHours,
+    pub deposit: Option<tool::Deposit>,
+    pub quantity: Option<tool::Quantity>,
+    pub location_room: Option<tool::LocationRoom>,
+    pub location_shelf: Option<tool::LocationShelf>,
+    pub location_bin: Option<tool::LocationBin>,
+    pub status: Option<tool::Status>,
+    pub categories: Vec<String>,
+}
+
+// a csv cell can't hold a list, so categories are joined with semicolons
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CsvToolRow {
+    real_id: Option<tool::RealId>,
+    short_description: tool::ShortDescription,
+    long_description: Option<tool::LongDescription>,
+    rental_hours: tool::RentalHours,
+    deposit: Option<tool::Deposit>,
+    // sheets from before pooled tools have no quantity column
+    #[serde(default)]
+    quantity: Option<tool::Quantity>,
+    // nor location columns, from before shelf locations
+    #[serde(default)]
+    location_room: Option<tool::LocationRoom>,
+    #[serde(default)]
+    location_shelf: Option<tool::LocationShelf>,
+    #[serde(default)]
+    location_bin: Option<tool::LocationBin>,
+    status: Option<tool::Status>,
+    categories: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowError {
+    // counted from one, not including the csv header
+    pub row: usize,
+    pub field: Option<String>,
+    pub details: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResponse {
+    pub dry_run: bool,
+    pub rows: usize,
+    pub errors: Vec<RowError>,
+    pub tools: Vec<tool::Tool>,
+}
+
+impl From<CsvToolRow> for ToolRow {
+    fn from(row: CsvToolRow) -> Self {
+        ToolRow {
+            real_id: row.real_id,
+            short_description: row.short_description,
+            long_description: row.long_description,
+            rental_hours: row.rental_hours,
+            deposit: row.deposit,
+            quantity: row.quantity,
+            location_room: row.location_room,
+            location_shelf: row.location_shelf,
+            location_bin: row.location_bin,
+            status: row.status,
+            categories: row
+                .categories
+                .split(';')
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl From<ToolRow> for CsvToolRow {
+    fn from(row: ToolRow) -> Self {
+        CsvToolRow {
+            real_id: row.real_id,
+            short_description: row.short_description,
+            long_description: row.long_description,
+            rental_hours: row.rental_hours,
+            deposit: row.deposit,
+            quantity: row.quantity,
+            location_room: row.location_room,
+            location_shelf: row.location_shelf,
+            location_bin: row.location_bin,
+            status: row.status,
+            categories: row.categories.join("; "),
+        }
+    }
+}
+
+// rows that don't fit the columns at all are reported as errors, like any other bad row
+fn parse_csv(body: &str) -> (Vec<(usize, ToolRow)>, Vec<RowError>) {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+
+    let mut rows = vec![];
+    let mut errors = vec![];
+    for (i, record) in reader.deserialize::<CsvToolRow>().enumerate() {
+        match record {
+            Ok(r) => rows.push((i + 1, r.into())),
+            Err(e) => errors.push(RowError {
+                row: i + 1,
+                field: None,
+                details: e.to_string(),
+            }),
+        }
+    }
+
+    (rows, errors)
+}
+
+// the body has to be a list, but a row in it that isn't a tool is reported like a bad csv row
+fn parse_json_rows(values: Vec<serde_json::Value>) -> (Vec<(usize, ToolRow)>, Vec<RowError>) {
+    let mut rows = vec![];
+    let mut errors = vec![];
+    for (i, value) in values.into_iter().enumerate() {
+        match serde_json::from_value::<ToolRow>(value) {
+            Ok(r) => rows.push((i + 1, r)),
+            Err(e) => errors.push(RowError {
+                row: i + 1,
+                field: None,
+                details: e.to_string(),
+            }),
+        }
+    }
+
+    (rows, errors)
+}
+
+fn write_csv(rows: Vec<ToolRow>) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for row in rows {
+        writer
+            .serialize(CsvToolRow::from(row))
+            .map_err(|e| e.to_string())?;
+    }
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+// A name match wins over a synonym match. Anything that still matches more than one category
+// is refused, rather than guessing.
+fn resolve_category(
+    name: &str,
+    categories: &[tool_category::ToolCategory],
+) -> Result<tool_category::Id, String> {
+    let lower = name.to_lowercase();
+    let by_name: Vec<tool_category::Id> = categories
+        .iter()
+        .filter(|c| c.name.to_lowercase() == lower)
+        .map(|c| c.id)
+        .collect();
+    let found = if by_name.is_empty() {
+        categories
+            .iter()
+            .filter(|c| c.synonyms.iter().any(|s| s.to_lowercase() == lower))
+            .map(|c| c.id)
+            .collect()
+    } else {
+        by_name
+    };
+
+    match found.as_slice() {
+        [id] => Ok(*id),
+        [] => Err(format!("No category is called {}", name)),
+        _ => Err(format!("More than one category is called {}", name)),
+    }
+}
+
+async fn select_active_store(
+    store_id: store::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<(), common::ErrResponse> {
+    let store = match stores::select_by_ids(vec![store_id], db).await {
+        Ok(mut s) if !s.is_empty() => s.remove(0),
+        Ok(_) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Store not found",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if store.status != store::StoreStatus::Active as i32 {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Store is not active",
+        ));
+    }
+
+    Ok(())
+}
+
+// Every tool is checked before anything is written. With any problems, or on a dry run,
+// nothing is imported and the problems are returned row by row. Otherwise all of the tools
+// go in together, or none do.
+pub async fn import(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    Query(params): Query<ImportParams>,
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> Result<Json<ImportResponse>, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of this store",
+        ));
+    }
+
+    let created_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let (rows, mut errors) = match params.format.unwrap_or_default() {
+        TransferFormat::Csv => parse_csv(&body),
+        TransferFormat::Json => match serde_json::from_str::<Vec<serde_json::Value>>(&body) {
+            Ok(values) => parse_json_rows(values),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "ERR_REQ",
+                    &e.to_string(),
+                ))
+            }
+        },
+    };
+
+    let row_count = rows.len() + errors.len();
+    if row_count == 0 || row_count > common::MAX_TOOL_IMPORT_ROWS {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            &format!(
+                "Import must have between 1 and {} tools",
+                common::MAX_TOOL_IMPORT_ROWS
+            ),
+        ));
+    }
+
+    select_active_store(store_id, &state.db).await?;
+
+    let statuses = match tools::select_statuses(&state.db).await {
+        Ok(s) => s,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let category_names: Vec<String> = rows
+        .iter()
+        .flat_map(|(_, r)| r.categories.iter().cloned())
+        .collect();
+    let categories = match tool_categories::select_by_names(category_names, &state.db).await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let real_ids: Vec<tool::RealId> = rows.iter().filter_map(|(_, r)| r.real_id.clone()).collect();
+    let taken: HashSet<tool::RealId> =
+        match tools::select_by_store_real_ids(vec![store_id; real_ids.len()], real_ids, &state.db)
+            .await
+        {
+            Ok(t) => t.into_iter().map(|t| t.real_id).collect(),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let kit_real_ids: HashSet<tool_kit::RealId> = match tool_kits::select(
+        tool_kits::SelectParams {
+            ids: vec![],
+            store_ids: vec![store_id],
+            real_ids: rows.iter().filter_map(|(_, r)| r.real_id.clone()).collect(),
+            term: "".to_string(),
+            statuses: vec![],
+            category_ids: vec![],
+            offset: 0,
+            limit: common::MAX_TOOL_IMPORT_ROWS as i64,
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(k) => k.into_iter().map(|k| k.real_id).collect(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut seen: HashMap<tool::RealId, usize> = HashMap::new();
+    let mut pending: Vec<(ToolRow, Vec<tool_category::Id>)> = vec![];
+    for (row, tool_row) in rows {
+        let row_errors_before = errors.len();
+        let mut push = |field: &str, details: String| {
+            errors.push(RowError {
+                row,
+                field: Some(field.to_string()),
+                details,
+            })
+        };
+
+        let checks = [
+            (
+                "realId",
+                common::none_or_verify_payload_text_length(
+                    tool_row.real_id.as_deref(),
+                    1,
+                    common::MAX_TOOL_REAL_ID_LENGTH,
+                ),
+            ),
+            (
+                "shortDescription",
+                common::verify_payload_text_length(
+                    &tool_row.short_description,
+                    1,
+                    common::MAX_TOOL_SHORT_DESCRIPTION_LENGTH,
+                ),
+            ),
+            (
+                "longDescription",
+                common::none_or_verify_payload_text_length(
+                    tool_row.long_description.as_deref(),
+                    1,
+                    common::MAX_TOOL_LONG_DESCRIPTION_LENGTH,
+                ),
+            ),
+            (
+                "rentalHours",
+                common::verify_payload_integer_range(tool_row.rental_hours, 1, i32::MAX),
+            ),
+            (
+                "deposit",
+                common::none_or_verify_payload_integer_range(
+                    tool_row.deposit,
+                    0,
+                    common::MAX_LEDGER_AMOUNT,
+                ),
+            ),
+            (
+                "quantity",
+                common::none_or_verify_payload_integer_range(
+                    tool_row.quantity,
+                    1,
+                    common::MAX_TOOL_QUANTITY,
+                ),
+            ),
+            (
+                "locationRoom",
+                common::none_or_verify_payload_text_length(
+                    tool_row.location_room.as_deref(),
+                    1,
+                    common::MAX_TOOL_LOCATION_LENGTH,
+                ),
+            ),
+            (
+                "locationShelf",
+                common::none_or_verify_payload_text_length(
+                    tool_row.location_shelf.as_deref(),
+                    1,
+                    common::MAX_TOOL_LOCATION_LENGTH,
+                ),
+            ),
+            (
+                "locationBin",
+                common::none_or_verify_payload_text_length(
+                    tool_row.location_bin.as_deref(),
+                    1,
+                    common::MAX_TOOL_LOCATION_LENGTH,
+                ),
+            ),
+            (
+                "categories",
+                common::verify_payload_integer_range(
+                    tool_row.categories.len().try_into().unwrap_or(i32::MAX),
+                    1,
+                    common::MAX_TOOL_CATEGORIES_LENGTH,
+                ),
+            ),
+        ];
+        for (field, check) in checks {
+            if let Err(e) = check {
+                push(field, e.details);
+            }
+        }
+
+        if let Some(status) = tool_row.status {
+            if !statuses.iter().any(|s| s.id == status) {
+                push("status", format!("No tool status has id {}", status));
+            } else if !tool_lifecycle::is_initial_status(status) {
+                push("status", "A new tool cannot start out rented".to_string());
+            }
+        }
+
+        if let Some(real_id) = &tool_row.real_id {
+            if taken.contains(real_id) {
+                push("realId", format!("Store already has a tool {}", real_id));
+            } else if kit_real_ids.contains(real_id) {
+                push("realId", format!("Store already has a kit {}", real_id));
+            } else if let Some(first) = seen.get(real_id) {
+                push("realId", format!("Same as row {}", first));
+            } else {
+                seen.insert(real_id.clone(), row);
+            }
+        }
+
+        let mut category_ids = vec![];
+        for name in &tool_row.categories {
+            match resolve_category(name, &categories) {
+                Ok(id) if !category_ids.contains(&id) => category_ids.push(id),
+                Ok(_) => {}
+                Err(e) => push("categories", e),
+            }
+        }
+
+        if errors.len() == row_errors_before {
+            pending.push((tool_row, category_ids));
+        }
+    }
+
+    errors.sort_by_key(|e| e.row);
+
+    let dry_run = params.dry_run.unwrap_or(false);
+    if dry_run || !errors.is_empty() {
+        return Ok(Json(ImportResponse {
+            dry_run,
+            rows: row_count,
+            errors,
+            tools: vec![],
+        }));
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let mut created: Vec<tool::Tool> = vec![];
+    let mut classifications: Vec<ToolClassification> = vec![];
+    for (tool_row, category_ids) in pending {
+        let tool = match tools::insert(
+            tools::NewTool {
+                real_id: tool_row.real_id.unwrap_or(common::rnd_code_str("t-")),
+                store_id,
+                rental_hours: tool_row.rental_hours,
+                short_description: tool_row.short_description,
+                long_description: tool_row.long_description,
+                status: tool_row
+                    .status
+                    .unwrap_or(tool::ToolStatus::Available as i32),
+                deposit: tool_row.deposit.filter(|d| *d > 0),
+                quantity: tool_row.quantity.unwrap_or(1),
+                location_room: tool_row.location_room,
+                location_shelf: tool_row.location_shelf,
+                location_bin: tool_row.location_bin,
+            },
+            &mut *tx,
+        )
+        .await
+        {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+        if let Err(e) =
+            tool_status_history::insert_created(tool.id, tool.status, created_by, &mut *tx).await
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+
+        classifications.extend(
+            category_ids
+                .into_iter()
+                .map(|category_id| ToolClassification {
+                    tool_id: tool.id,
+                    category_id,
+                }),
+        );
+        created.push(tool);
+    }
+
+    if let Err(e) = tool_classifications::insert(classifications, &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
+    for tool in &created {
+        let encoded = serde_json::to_vec(tool).unwrap_or_default();
+        state.comm.send_message("tools", &encoded).await.ok();
+    }
+
+    Ok(Json(ImportResponse {
+        dry_run,
+        rows: row_count,
+        errors,
+        tools: created,
+    }))
+}
+
+pub async fn export(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    Query(params): Query<ExportParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) && !claims.is_store_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let rows: Vec<ToolRow> = match tools::select_for_export(store_id, &state.db).await {
+        Ok(t) => t
+            .into_iter()
+            .map(|t| ToolRow {
+                real_id: Some(t.real_id),
+                short_description: t.short_description,
+                long_description: t.long_description,
+                rental_hours: t.rental_hours,
+                deposit: t.deposit,
+                quantity: Some(t.quantity),
+                location_room: t.location_room,
+                location_shelf: t.location_shelf,
+                location_bin: t.location_bin,
+                // a tool that's out goes back in as available, since nothing new starts out rented
+                status: if tool_lifecycle::is_initial_status(t.status) {
+                    Some(t.status)
+                } else {
+                    Some(tool::ToolStatus::Available as i32)
+                },
+                categories: t.categories,
+            })
+            .collect(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match params.format.unwrap_or_default() {
+        TransferFormat::Json => Ok(Json(rows).into_response()),
+        TransferFormat::Csv => {
+            let data = match write_csv(rows) {
+                Ok(data) => data,
+                Err(e) => {
+                    return Err(common::ErrResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "ERR_LOGIC",
+                        &e,
+                    ))
+                }
+            };
+
+            let attachment_header =
+                format!("attachment; filename=\"store-{}-tools.csv\"", store_id);
+            let headers = [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (header::CONTENT_DISPOSITION, attachment_header.as_str()),
+            ];
+            Ok((headers, data).into_response())
+        }
+    }
+}
@@ -0,0 +1,715 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::{store, tool, tool_kit};
+use crate::handlers::tools::available_units;
+use crate::queries::{rentals, reservations, tool_holds, tool_kits, tools};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewKitData {
+    pub store_id: store::Id,
+    pub real_id: Option<tool_kit::RealId>,
+    pub short_description: tool_kit::ShortDescription,
+    pub long_description: Option<tool_kit::LongDescription>,
+    pub tool_ids: Vec<tool::Id>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateKitData {
+    pub real_id: Option<tool_kit::RealId>,
+    pub short_description: Option<tool_kit::ShortDescription>,
+    pub long_description: Option<tool_kit::LongDescription>,
+    pub tool_ids: Option<Vec<tool::Id>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KitMemberInfo {
+    pub tool_id: tool::Id,
+    pub real_id: tool::RealId,
+    pub short_description: tool::ShortDescription,
+    pub status: tool::Status,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KitWithMembers {
+    #[serde(flatten)]
+    pub kit: tool_kit::ToolKit,
+    pub available: bool,
+    pub members: Vec<KitMemberInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncompleteKit {
+    pub kit_id: tool_kit::Id,
+    pub real_id: tool_kit::RealId,
+    pub missing: Vec<KitMemberInfo>,
+}
+
+fn member_info(t: &tool::Tool) -> KitMemberInfo {
+    KitMemberInfo {
+        tool_id: t.id,
+        real_id: t.real_id.clone(),
+        short_description: t.short_description.clone(),
+        status: t.status,
+    }
+}
+
+pub async fn with_members(
+    kits: Vec<tool_kit::ToolKit>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<KitWithMembers>, common::ErrResponse> {
+    if kits.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let members =
+        match tool_kits::select_members(kits.iter().map(|k| k.id).collect(), vec![], db).await {
+            Ok(m) => m,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let tools = match tools::select_by_ids(members.iter().map(|m| m.tool_id).collect(), db).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let free = select_free_tool_ids(&tools, db).await?;
+
+    Ok(kits
+        .into_iter()
+        .map(|kit| {
+            let member_tools: Vec<&tool::Tool> = members
+                .iter()
+                .filter(|m| m.kit_id == kit.id)
+                .filter_map(|m| tools.iter().find(|t| t.id == m.tool_id))
+                .collect();
+            KitWithMembers {
+                available: !member_tools.is_empty()
+                    && member_tools.iter().all(|t| free.contains(&t.id)),
+                kit,
+                members: member_tools.into_iter().map(member_info).collect(),
+            }
+        })
+        .collect())
+}
+
+// tools with a unit left over once the ones out, held for pickup and reserved are counted
+async fn select_free_tool_ids(
+    tools: &[tool::Tool],
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<tool::Id>, common::ErrResponse> {
+    let make_db_error =
+        |e: String| common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e);
+
+    let tool_ids: Vec<tool::Id> = tools.iter().map(|t| t.id).collect();
+    let open_units = rentals::select_open_units(tool_ids.clone(), db)
+        .await
+        .map_err(make_db_error)?;
+    let held = tool_holds::select_on_hold(tool_ids.clone(), db)
+        .await
+        .map_err(make_db_error)?;
+    let reserved = reservations::select_current(tool_ids, time::OffsetDateTime::now_utc(), db)
+        .await
+        .map_err(make_db_error)?;
+
+    Ok(tools
+        .iter()
+        .filter(|t| {
+            let taken = held.iter().filter(|h| h.tool_id == t.id).count()
+                + reserved.iter().filter(|r| r.tool_id == t.id).count();
+            available_units(t, &open_units) > i64::try_from(taken).unwrap_or(i64::MAX)
+        })
+        .map(|t| t.id)
+        .collect())
+}
+
+// every tool in the kits, for checking them all out together
+pub async fn select_member_ids(
+    kit_ids: Vec<tool_kit::Id>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<tool::Id>, common::ErrResponse> {
+    let members = match tool_kits::select_members(kit_ids.clone(), vec![], db).await {
+        Ok(m) => m,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if kit_ids
+        .iter()
+        .any(|id| !members.iter().any(|m| m.kit_id == *id))
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Some kits not found, or empty",
+        ));
+    }
+
+    Ok(members.into_iter().map(|m| m.tool_id).collect())
+}
+
+// Kits that had some of their tools come back, but not all of them. Whatever is still rented,
+// lost or stolen is missing.
+pub async fn select_incomplete(
+    returned_ids: Vec<tool::Id>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<IncompleteKit>, common::ErrResponse> {
+    let members = match tool_kits::select_members(vec![], returned_ids.clone(), &mut *conn).await {
+        Ok(m) => m,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if members.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut kit_ids: Vec<tool_kit::Id> = members.iter().map(|m| m.kit_id).collect();
+    kit_ids.dedup();
+    let kits = match tool_kits::select(
+        tool_kits::SelectParams {
+            ids: kit_ids,
+            store_ids: vec![],
+            real_ids: vec![],
+            term: "".to_string(),
+            statuses: vec![],
+            category_ids: vec![],
+            offset: 0,
+            limit: 1000,
+        },
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(k) => k,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let tools =
+        match tools::select_by_ids(members.iter().map(|m| m.tool_id).collect(), &mut *conn).await {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let still_out = |t: &&tool::Tool| {
+        !returned_ids.contains(&t.id)
+            && (t.status == tool::ToolStatus::Rented as i32
+                || t.status == tool::ToolStatus::Lost as i32
+                || t.status == tool::ToolStatus::Stolen as i32)
+    };
+
+    Ok(kits
+        .into_iter()
+        .map(|kit| {
+            let missing = members
+                .iter()
+                .filter(|m| m.kit_id == kit.id)
+                .filter_map(|m| tools.iter().find(|t| t.id == m.tool_id))
+                .filter(still_out)
+                .map(member_info)
+                .collect();
+            IncompleteKit {
+                kit_id: kit.id,
+                real_id: kit.real_id,
+                missing,
+            }
+        })
+        .filter(|k| !k.missing.is_empty())
+        .collect())
+}
+
+// Tools have to be in the kit's store and not already in some other kit. The kit's real id
+// can't be taken by any tool or other kit in the store either, since scans go by real id.
+async fn verify_kit(
+    kit_id: Option<tool_kit::Id>,
+    store_id: store::Id,
+    real_id: Option<&tool_kit::RealId>,
+    tool_ids: Option<&Vec<tool::Id>>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), common::ErrResponse> {
+    if let Some(real_id) = real_id {
+        let taken_by_tool = match tools::select_by_store_real_ids(
+            vec![store_id],
+            vec![real_id.clone()],
+            &mut *conn,
+        )
+        .await
+        {
+            Ok(t) => !t.is_empty(),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+        let taken_by_kit = match tool_kits::select(
+            tool_kits::SelectParams {
+                ids: vec![],
+                store_ids: vec![store_id],
+                real_ids: vec![real_id.clone()],
+                term: "".to_string(),
+                statuses: vec![],
+                category_ids: vec![],
+                offset: 0,
+                limit: 2,
+            },
+            &mut *conn,
+        )
+        .await
+        {
+            Ok(k) => k.iter().any(|k| Some(k.id) != kit_id),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+        if taken_by_tool || taken_by_kit {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_DUP",
+                "Real id is already in use in this store",
+            ));
+        }
+    }
+
+    let tool_ids = match tool_ids {
+        Some(ids) => ids,
+        None => return Ok(()),
+    };
+
+    common::verify_payload_integer_range(
+        tool_ids.len().try_into().unwrap_or(i32::MAX),
+        1,
+        common::MAX_TOOL_KIT_MEMBERS_LENGTH,
+    )?;
+
+    let tools = match tools::select_by_ids_for_update(tool_ids.clone(), &mut *conn).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if tools.len() != tool_ids.len() {
+        return Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Some tools not found",
+        ));
+    }
+    if tools.iter().any(|t| t.store_id != store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Some tools do not belong to the store",
+        ));
+    }
+
+    let in_other_kits: Vec<&str> =
+        match tool_kits::select_members(vec![], tool_ids.clone(), &mut *conn).await {
+            Ok(m) => tools
+                .iter()
+                .filter(|t| {
+                    m.iter()
+                        .any(|m| m.tool_id == t.id && Some(m.kit_id) != kit_id)
+                })
+                .map(|t| t.real_id.as_str())
+                .collect(),
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+    if !in_other_kits.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!("Tools already in another kit: {}", in_other_kits.join(", ")),
+        ));
+    }
+
+    Ok(())
+}
+
+// two kits given the same real id at once get past verify_kit, but not the unique index
+fn kit_write_error(e: String) -> common::ErrResponse {
+    if e.contains("idx_tool_kits_store_id_real_id") {
+        return common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_DUP",
+            "Real id is already in use in this store",
+        );
+    }
+    common::ErrResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "ERR_DB", &e)
+}
+
+// Scans go by real id, so a tool can't take one that a kit in the same store already has.
+// Kits check against tools in verify_kit.
+pub async fn verify_real_id_free_of_kits(
+    store_id: store::Id,
+    real_ids: Vec<tool::RealId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<(), common::ErrResponse> {
+    if real_ids.is_empty() {
+        return Ok(());
+    }
+
+    match tool_kits::select(
+        tool_kits::SelectParams {
+            ids: vec![],
+            store_ids: vec![store_id],
+            real_ids,
+            term: "".to_string(),
+            statuses: vec![],
+            category_ids: vec![],
+            offset: 0,
+            limit: common::MAX_TOOL_IMPORT_ROWS as i64,
+        },
+        db,
+    )
+    .await
+    {
+        Ok(k) if k.is_empty() => Ok(()),
+        Ok(k) => Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_DUP",
+            &format!(
+                "Real id is already in use by a kit in this store: {}",
+                k.iter()
+                    .map(|k| k.real_id.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ),
+        )),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+async fn select_one(
+    kit_id: tool_kit::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<KitWithMembers, common::ErrResponse> {
+    let kits = match tool_kits::select(
+        tool_kits::SelectParams {
+            ids: vec![kit_id],
+            store_ids: vec![],
+            real_ids: vec![],
+            term: "".to_string(),
+            statuses: vec![],
+            category_ids: vec![],
+            offset: 0,
+            limit: 1,
+        },
+        db,
+    )
+    .await
+    {
+        Ok(k) => k,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match with_members(kits, db).await?.pop() {
+        Some(k) => Ok(k),
+        None => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Kit not found",
+        )),
+    }
+}
+
+pub async fn create_new(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewKitData>,
+) -> Result<Json<KitWithMembers>, common::ErrResponse> {
+    if !claims.is_tool_manager(payload.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of this store",
+        ));
+    }
+
+    common::none_or_verify_payload_text_length(
+        payload.real_id.as_deref(),
+        1,
+        common::MAX_TOOL_REAL_ID_LENGTH,
+    )?;
+    common::verify_payload_text_length(
+        &payload.short_description,
+        1,
+        common::MAX_TOOL_SHORT_DESCRIPTION_LENGTH,
+    )?;
+    common::none_or_verify_payload_text_length(
+        payload.long_description.as_deref(),
+        1,
+        common::MAX_TOOL_LONG_DESCRIPTION_LENGTH,
+    )?;
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let real_id = payload.real_id.unwrap_or(common::rnd_code_str("k-"));
+    verify_kit(
+        None,
+        payload.store_id,
+        Some(&real_id),
+        Some(&payload.tool_ids),
+        &mut tx,
+    )
+    .await?;
+
+    let kit = match tool_kits::insert(
+        real_id,
+        payload.store_id,
+        payload.short_description,
+        payload.long_description,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(k) => k,
+        Err(e) => return Err(kit_write_error(e)),
+    };
+
+    if let Err(e) = tool_kits::insert_members(kit.id, payload.tool_ids, &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
+    Ok(Json(select_one(kit.id, &state.db).await?))
+}
+
+pub async fn get_by_id(
+    Path(kit_id): Path<tool_kit::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<KitWithMembers>, common::ErrResponse> {
+    Ok(Json(select_one(kit_id, &state.db).await?))
+}
+
+pub async fn update(
+    claims: Claims,
+    Path(kit_id): Path<tool_kit::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateKitData>,
+) -> Result<Json<KitWithMembers>, common::ErrResponse> {
+    common::none_or_verify_payload_text_length(
+        payload.real_id.as_deref(),
+        1,
+        common::MAX_TOOL_REAL_ID_LENGTH,
+    )?;
+    common::none_or_verify_payload_text_length(
+        payload.short_description.as_deref(),
+        1,
+        common::MAX_TOOL_SHORT_DESCRIPTION_LENGTH,
+    )?;
+    common::none_or_verify_payload_text_length(
+        payload.long_description.as_deref(),
+        1,
+        common::MAX_TOOL_LONG_DESCRIPTION_LENGTH,
+    )?;
+
+    let kit = select_one(kit_id, &state.db).await?.kit;
+    if !claims.is_tool_manager(kit.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of this store",
+        ));
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    verify_kit(
+        Some(kit_id),
+        kit.store_id,
+        payload.real_id.as_ref().filter(|r| **r != kit.real_id),
+        payload.tool_ids.as_ref(),
+        &mut tx,
+    )
+    .await?;
+
+    if let Err(e) = tool_kits::update(
+        kit_id,
+        payload.real_id,
+        payload.short_description,
+        payload.long_description,
+        &mut *tx,
+    )
+    .await
+    {
+        return Err(kit_write_error(e));
+    }
+
+    if let Some(tool_ids) = payload.tool_ids {
+        if let Err(e) = tool_kits::delete_members(kit_id, &mut *tx).await {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+        if let Err(e) = tool_kits::insert_members(kit_id, tool_ids, &mut *tx).await {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
+    Ok(Json(select_one(kit_id, &state.db).await?))
+}
+
+// the tools stay, only the grouping goes
+pub async fn delete(
+    claims: Claims,
+    Path(kit_id): Path<tool_kit::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<common::NoData>, common::ErrResponse> {
+    let kit = select_one(kit_id, &state.db).await?.kit;
+    if !claims.is_tool_manager(kit.store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of this store",
+        ));
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    if let Err(e) = tool_kits::delete_members(kit_id, &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+    if let Err(e) = tool_kits::delete(kit_id, &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(common::NoData {})),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
@@ -0,0 +1,250 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::tool_status_change::{self, StatusAction};
+use crate::db_structs::{tool, user};
+use crate::handlers::tool_holds::refresh_queues;
+use crate::handlers::tools::ToolWithText;
+use crate::queries::tool_status_history::{self, ChangeWithText};
+use crate::queries::{rentals, tools};
+use crate::tool_lifecycle;
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FoundData {
+    pub status: Option<tool::Status>,
+    pub reason: Option<tool_status_change::Reason>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineResponse {
+    pub changes: Vec<ChangeWithText>,
+}
+
+// Moves every tool to the given status, refusing the whole lot if any of them isn't allowed
+// to get there that way, and writes the history. Tools already in that status are left out.
+// Returns the ids of the tools that changed.
+pub async fn change_statuses(
+    tools: &[tool::Tool],
+    to_status: tool::Status,
+    action: StatusAction,
+    reason: Option<tool_status_change::Reason>,
+    changed_by: Option<user::Id>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<tool::Id>, common::ErrResponse> {
+    let moving: Vec<&tool::Tool> = tools.iter().filter(|t| t.status != to_status).collect();
+    if moving.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let refused: Vec<&str> = moving
+        .iter()
+        .filter(|t| !tool_lifecycle::can_transition(t.status, to_status, action))
+        .map(|t| t.real_id.as_str())
+        .collect();
+    if !refused.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!("Tool status cannot change this way: {}", refused.join(", ")),
+        ));
+    }
+
+    let tool_ids: Vec<tool::Id> = moving.iter().map(|t| t.id).collect();
+    if let Err(e) = tools::update_statuses(tool_ids.clone(), to_status, &mut *conn).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = tool_status_history::insert_many(
+        tool_ids.clone(),
+        moving.iter().map(|t| t.status).collect(),
+        to_status,
+        action as i32,
+        reason,
+        changed_by,
+        &mut *conn,
+    )
+    .await
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    Ok(tool_ids)
+}
+
+pub async fn get_by_tool(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TimelineResponse>, common::ErrResponse> {
+    if claims.is_none() {
+        return Err(common::ErrResponse::new(
+            StatusCode::UNAUTHORIZED,
+            "ERR_AUTH",
+            "User is not logged in",
+        ));
+    }
+
+    match tool_status_history::select_by_tool_id(tool_id, &state.db).await {
+        Ok(changes) => Ok(Json(TimelineResponse { changes })),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+// a lost or stolen tool turning up again
+pub async fn mark_found(
+    claims: Claims,
+    Path(tool_id): Path<tool::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<FoundData>,
+) -> Result<Json<ToolWithText>, common::ErrResponse> {
+    common::none_or_verify_payload_text_length(
+        payload.reason.as_deref(),
+        1,
+        common::MAX_TOOL_STATUS_REASON_LENGTH,
+    )?;
+
+    // a tool that is still out comes back through check in instead, which closes the rental
+    match rentals::select(
+        rentals::SelectParams {
+            renter_ids: vec![],
+            tool_ids: vec![tool_id],
+            store_ids: vec![],
+            start_date: common::DateBetween::default(),
+            end_date: common::DateBetween::default(),
+            open: true,
+            overdue: None,
+            order_by: rentals::OrderBy::StartDate,
+            order_asc: true,
+            offset: 0,
+            limit: 1,
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(r) if !r.is_empty() => {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "Tool is still out on a rental, check it in instead",
+            ))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    }
+
+    let changed_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let tools = match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match tools.first() {
+        Some(t) if !claims.is_tool_manager(t.store_id) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::FORBIDDEN,
+                "ERR_AUTH",
+                "User is not a tool manager of this store",
+            ))
+        }
+        Some(t)
+            if t.status != tool::ToolStatus::Lost as i32
+                && t.status != tool::ToolStatus::Stolen as i32 =>
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "Only a lost or stolen tool can be found",
+            ))
+        }
+        Some(_) => {}
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::NOT_FOUND,
+                "ERR_MIA",
+                "Tool not found",
+            ))
+        }
+    }
+
+    change_statuses(
+        &tools,
+        payload.status.unwrap_or(tool::ToolStatus::Available as i32),
+        StatusAction::Found,
+        payload.reason,
+        Some(changed_by),
+        &mut tx,
+    )
+    .await?;
+
+    if let Err(e) = refresh_queues(vec![tool_id], &mut tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
+    crate::handlers::tools::get_by_id(Path(tool_id), State(state)).await
+}
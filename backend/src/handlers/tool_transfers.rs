@@ -0,0 +1,759 @@
+use crate::auth::claims::Claims;
+use crate::common;
+use crate::db_structs::tool_status_change::StatusAction;
+use crate::db_structs::tool_transfer::{self, TransferStatus};
+use crate::db_structs::{store, tool, tool_hold};
+use crate::queries::{
+    rentals, reservations, stores, tool_holds, tool_kits, tool_status_history, tool_transfers,
+    tools,
+};
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTransferData {
+    pub from_store_id: store::Id,
+    pub to_store_id: store::Id,
+    pub tool_ids: Vec<tool::Id>,
+    pub note: Option<tool_transfer::Note>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecideTransferData {
+    pub status: tool_transfer::Status,
+    pub note: Option<tool_transfer::DecisionNote>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferFilterParams {
+    pub statuses: Option<Vec<tool_transfer::Status>>,
+    pub page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferWithTools {
+    #[serde(flatten)]
+    pub transfer: tool_transfer::ToolTransfer,
+    pub tools: Vec<tool::Tool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransfersResponse {
+    pub transfers: Vec<TransferWithTools>,
+}
+
+async fn with_tools(
+    transfers: Vec<tool_transfer::ToolTransfer>,
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<TransferWithTools>, common::ErrResponse> {
+    let items =
+        match tool_transfers::select_items(transfers.iter().map(|t| t.id).collect(), &mut *conn)
+            .await
+        {
+            Ok(i) => i,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    let tools =
+        match tools::select_by_ids(items.iter().map(|i| i.tool_id).collect(), &mut *conn).await {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+    Ok(transfers
+        .into_iter()
+        .map(|transfer| {
+            let tools = items
+                .iter()
+                .filter(|i| i.transfer_id == transfer.id)
+                .filter_map(|i| tools.iter().find(|t| t.id == i.tool_id))
+                .cloned()
+                .collect();
+            TransferWithTools { transfer, tools }
+        })
+        .collect())
+}
+
+async fn select_one(
+    id: tool_transfer::Id,
+    conn: &mut sqlx::PgConnection,
+) -> Result<TransferWithTools, common::ErrResponse> {
+    let transfers = match tool_transfers::select(
+        tool_transfers::SelectParams {
+            ids: vec![id],
+            store_ids: vec![],
+            statuses: vec![],
+            offset: 0,
+            limit: 1,
+        },
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match with_tools(transfers, conn).await?.pop() {
+        Some(t) => Ok(t),
+        None => Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Transfer not found",
+        )),
+    }
+}
+
+// A tool can only move while it's on the shelf of the store it's leaving: nothing out on a
+// rental, not part of a kit (kits stay within one store), and not already promised elsewhere.
+async fn verify_movable(
+    transfer_id: Option<tool_transfer::Id>,
+    from_store_id: store::Id,
+    tools: &[tool::Tool],
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), common::ErrResponse> {
+    if tools.iter().any(|t| t.store_id != from_store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Some tools do not belong to the store",
+        ));
+    }
+
+    let tool_ids: Vec<tool::Id> = tools.iter().map(|t| t.id).collect();
+    let real_ids_of = |ids: Vec<tool::Id>| {
+        tools
+            .iter()
+            .filter(|t| ids.contains(&t.id))
+            .map(|t| t.real_id.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    };
+
+    let rented = match rentals::select_open_units(tool_ids.clone(), &mut *conn).await {
+        Ok(u) => u.into_iter().map(|u| u.tool_id).collect::<Vec<tool::Id>>(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if !rented.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!("Tools out on open rentals: {}", real_ids_of(rented)),
+        ));
+    }
+
+    let in_kits = match tool_kits::select_members(vec![], tool_ids.clone(), &mut *conn).await {
+        Ok(m) => m
+            .into_iter()
+            .map(|m| m.tool_id)
+            .filter(|id| tool_ids.contains(id))
+            .collect::<Vec<tool::Id>>(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if !in_kits.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!("Tools in a kit: {}", real_ids_of(in_kits)),
+        ));
+    }
+
+    let pending = match tool_transfers::select_pending_items(tool_ids, &mut *conn).await {
+        Ok(i) => i
+            .into_iter()
+            .filter(|i| Some(i.transfer_id) != transfer_id)
+            .map(|i| i.tool_id)
+            .collect::<Vec<tool::Id>>(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if !pending.is_empty() {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            &format!(
+                "Tools already in another transfer: {}",
+                real_ids_of(pending)
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+// scans go by real id, so the destination can't already have a tool or kit with the same one
+async fn verify_real_ids_free(
+    to_store_id: store::Id,
+    tools: &[tool::Tool],
+    conn: &mut sqlx::PgConnection,
+) -> Result<(), common::ErrResponse> {
+    let real_ids: Vec<tool::RealId> = tools.iter().map(|t| t.real_id.clone()).collect();
+
+    let mut taken: Vec<tool::RealId> = match tools::select_by_store_real_ids(
+        vec![to_store_id; real_ids.len()],
+        real_ids.clone(),
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(t) => t.into_iter().map(|t| t.real_id).collect(),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    match tool_kits::select(
+        tool_kits::SelectParams {
+            ids: vec![],
+            store_ids: vec![to_store_id],
+            real_ids: real_ids.clone(),
+            term: "".to_string(),
+            statuses: vec![],
+            category_ids: vec![],
+            offset: 0,
+            limit: i64::try_from(real_ids.len()).unwrap_or_default(),
+        },
+        &mut *conn,
+    )
+    .await
+    {
+        Ok(k) => taken.extend(k.into_iter().map(|k| k.real_id)),
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    }
+
+    if !taken.is_empty() {
+        taken.sort();
+        taken.dedup();
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_DUP",
+            &format!(
+                "Real ids already in use in the destination store: {}",
+                taken.join(", ")
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn create_new(
+    claims: Claims,
+    State(state): State<Arc<AppState>>,
+    Json(mut payload): Json<NewTransferData>,
+) -> Result<Json<TransferWithTools>, common::ErrResponse> {
+    if !claims.is_tool_manager(payload.from_store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let proposed_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
+    payload.tool_ids.sort_unstable();
+    payload.tool_ids.dedup();
+    common::verify_payload_integer_range(
+        payload.tool_ids.len().try_into().unwrap_or(i32::MAX),
+        1,
+        common::MAX_TOOL_TRANSFER_TOOLS_LENGTH,
+    )?;
+    common::none_or_verify_payload_text_length(
+        payload.note.as_deref(),
+        1,
+        common::MAX_TOOL_TRANSFER_NOTE_LENGTH,
+    )?;
+
+    if payload.from_store_id == payload.to_store_id {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Tools can only be transferred to a different store",
+        ));
+    }
+
+    match stores::select_by_ids(vec![payload.to_store_id], &state.db).await {
+        Ok(s) => {
+            if !s
+                .iter()
+                .any(|s| s.status == store::StoreStatus::Active as i32)
+            {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Destination store not found, or not active",
+                ));
+            }
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    }
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let tools = match tools::select_by_ids_for_update(payload.tool_ids.clone(), &mut *tx).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+    if tools.len() != payload.tool_ids.len() {
+        return Err(common::ErrResponse::new(
+            StatusCode::NOT_FOUND,
+            "ERR_MIA",
+            "Some tools not found",
+        ));
+    }
+
+    verify_movable(None, payload.from_store_id, &tools, &mut tx).await?;
+    verify_real_ids_free(payload.to_store_id, &tools, &mut tx).await?;
+
+    let transfer = match tool_transfers::insert(
+        payload.from_store_id,
+        payload.to_store_id,
+        proposed_by,
+        payload.note,
+        &mut *tx,
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    if let Err(e) = tool_transfers::insert_items(transfer.id, payload.tool_ids, &mut *tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    let transfer = select_one(transfer.id, &mut tx).await?;
+
+    match tx.commit().await {
+        Ok(_) => Ok(Json(transfer)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
+
+// transfers out of the store and into it, newest first
+pub async fn get_by_store(
+    claims: Claims,
+    Path(store_id): Path<store::Id>,
+    Query(params): Query<TransferFilterParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TransfersResponse>, common::ErrResponse> {
+    if !claims.is_tool_manager(store_id) {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of the store",
+        ));
+    }
+
+    let (offset, limit) = common::calculate_offset_limit(params.page.unwrap_or_default());
+    let transfers = match tool_transfers::select(
+        tool_transfers::SelectParams {
+            ids: vec![],
+            store_ids: vec![store_id],
+            statuses: params.statuses.unwrap_or_default(),
+            offset,
+            limit,
+        },
+        &state.db,
+    )
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    };
+
+    let mut conn = match state.db.acquire().await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+    let transfers = with_tools(transfers, &mut conn).await?;
+
+    Ok(Json(TransfersResponse { transfers }))
+}
+
+pub async fn get_by_id(
+    claims: Claims,
+    Path(transfer_id): Path<tool_transfer::Id>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<TransferWithTools>, common::ErrResponse> {
+    let mut conn = match state.db.acquire().await {
+        Ok(c) => c,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+    let transfer = select_one(transfer_id, &mut conn).await?;
+
+    if !claims.is_tool_manager(transfer.transfer.from_store_id)
+        && !claims.is_tool_manager(transfer.transfer.to_store_id)
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User is not a tool manager of either store",
+        ));
+    }
+
+    Ok(Json(transfer))
+}
+
+// The destination store accepts or declines, the source store can cancel. Accepting checks
+// everything over again, since the tools may have gone out or been put in a kit meanwhile.
+pub async fn update_status(
+    claims: Claims,
+    Path(transfer_id): Path<tool_transfer::Id>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<DecideTransferData>,
+) -> Result<Json<TransferWithTools>, common::ErrResponse> {
+    let user_id = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "User is not logged in",
+            ))
+        }
+    };
+
+    common::none_or_verify_payload_text_length(
+        payload.note.as_deref(),
+        1,
+        common::MAX_TOOL_TRANSFER_NOTE_LENGTH,
+    )?;
+
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ))
+        }
+    };
+
+    let current = select_one(transfer_id, &mut tx).await?;
+    let transfer = &current.transfer;
+
+    let allowed = if payload.status == TransferStatus::Cancelled as i32 {
+        claims.is_tool_manager(transfer.from_store_id)
+    } else if payload.status == TransferStatus::Accepted as i32
+        || payload.status == TransferStatus::Declined as i32
+    {
+        claims.is_tool_manager(transfer.to_store_id)
+    } else {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "Transfers can only be accepted, declined, or cancelled",
+        ));
+    };
+    if !allowed {
+        return Err(common::ErrResponse::new(
+            StatusCode::FORBIDDEN,
+            "ERR_AUTH",
+            "User cannot make this decision",
+        ));
+    }
+
+    if transfer.status != TransferStatus::Pending as i32 {
+        return Err(common::ErrResponse::new(
+            StatusCode::CONFLICT,
+            "ERR_CONFLICT",
+            "Transfer has already been decided",
+        ));
+    }
+
+    if payload.status == TransferStatus::Accepted as i32 {
+        let tool_ids: Vec<tool::Id> = current.tools.iter().map(|t| t.id).collect();
+        let tools = match tools::select_by_ids_for_update(tool_ids.clone(), &mut *tx).await {
+            Ok(t) => t,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+
+        let stores = match stores::select_by_ids(
+            vec![transfer.from_store_id, transfer.to_store_id],
+            &mut *tx,
+        )
+        .await
+        {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ))
+            }
+        };
+        let store_name = |id: store::Id| {
+            stores
+                .iter()
+                .find(|s| s.id == id)
+                .map(|s| s.name.clone())
+                .unwrap_or_default()
+        };
+        if !stores
+            .iter()
+            .any(|s| s.id == transfer.to_store_id && s.status == store::StoreStatus::Active as i32)
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "Destination store is not active",
+            ));
+        }
+
+        verify_movable(Some(transfer.id), transfer.from_store_id, &tools, &mut tx).await?;
+        verify_real_ids_free(transfer.to_store_id, &tools, &mut tx).await?;
+
+        if let Err(e) =
+            tools::update_store_ids(tool_ids.clone(), transfer.to_store_id, &mut *tx).await
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+
+        // holds, reservations and the shelf belong to the store the tools left
+        if let Err(e) = tool_holds::update_statuses(
+            tool_ids.clone(),
+            vec![],
+            tool_hold::HoldStatus::Cancelled as i32,
+            &mut *tx,
+        )
+        .await
+        {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+
+        if let Err(e) = reservations::cancel_active(tool_ids.clone(), &mut *tx).await {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+
+        for tool_id in tool_ids {
+            if let Err(e) = tools::update_location(
+                tool_id,
+                Some(String::new()),
+                Some(String::new()),
+                Some(String::new()),
+                &mut *tx,
+            )
+            .await
+            {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        }
+
+        // the status stays as it was, the entry is there to mark the move
+        let reason = format!(
+            "Moved from {} to {} (transfer #{})",
+            store_name(transfer.from_store_id),
+            store_name(transfer.to_store_id),
+            transfer.id
+        );
+        let mut statuses: Vec<tool::Status> = tools.iter().map(|t| t.status).collect();
+        statuses.sort_unstable();
+        statuses.dedup();
+        for status in statuses {
+            let moved: Vec<tool::Id> = tools
+                .iter()
+                .filter(|t| t.status == status)
+                .map(|t| t.id)
+                .collect();
+            if let Err(e) = tool_status_history::insert_many(
+                moved.clone(),
+                vec![status; moved.len()],
+                status,
+                StatusAction::Transferred as i32,
+                Some(reason.clone()),
+                Some(user_id),
+                &mut *tx,
+            )
+            .await
+            {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        }
+    }
+
+    match tool_transfers::decide(transfer.id, payload.status, user_id, payload.note, &mut *tx).await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "Transfer has already been decided",
+            ))
+        }
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ))
+        }
+    }
+
+    let updated = select_one(transfer_id, &mut tx).await?;
+
+    match tx.commit().await {
+        Ok(_) => {
+            if payload.status == TransferStatus::Accepted as i32 {
+                for tool in &updated.tools {
+                    let encoded = serde_json::to_vec(tool).unwrap_or_default();
+                    state.comm.send_message("tools", &encoded).await.ok();
+                }
+            }
+            Ok(Json(updated))
+        }
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        )),
+    }
+}
@@ -1,8 +1,19 @@
 use crate::auth::claims::Claims;
 use crate::common;
 use crate::db_structs::tool_classification::ToolClassification;
-use crate::db_structs::{store, tool, tool_category, tool_classification, tool_photo};
-use crate::queries::{stores, tool_categories, tool_classifications, tool_photos, tools};
+use crate::db_structs::tool_status_change::StatusAction;
+use crate::db_structs::{
+    store, tool, tool_category, tool_classification, tool_hold, tool_photo, tool_status_change,
+    user,
+};
+use crate::handlers::tool_holds::refresh_queues;
+use crate::handlers::tool_kits::{self as kit_handlers, KitWithMembers};
+use crate::handlers::tool_status_history::change_statuses;
+use crate::queries::{
+    rentals, repair_tickets, stores, tool_categories, tool_classifications, tool_holds, tool_kits,
+    tool_photos, tool_status_history, tools,
+};
+use crate::tool_lifecycle;
 use crate::AppState;
 use axum::{
     extract::{Json, Path, State},
@@ -23,6 +34,12 @@ pub struct NewToolData {
     pub long_description: Option<tool::LongDescription>,
     pub photo_keys: Vec<tool_photo::PhotoKey>,
     pub status: Option<tool::Status>,
+    pub deposit: Option<tool::Deposit>,
+    // more than one makes a pooled tool, lent out a few units at a time
+    pub quantity: Option<tool::Quantity>,
+    pub location_room: Option<tool::LocationRoom>,
+    pub location_shelf: Option<tool::LocationShelf>,
+    pub location_bin: Option<tool::LocationBin>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,6 +52,14 @@ pub struct UpdateToolData {
     pub long_description: Option<tool::LongDescription>,
     pub photo_keys: Option<Vec<tool_photo::PhotoKey>>,
     pub status: Option<tool::Status>,
+    pub status_reason: Option<tool_status_change::Reason>,
+    // zero removes the deposit
+    pub deposit: Option<tool::Deposit>,
+    pub quantity: Option<tool::Quantity>,
+    // an empty string clears that part of the location
+    pub location_room: Option<tool::LocationRoom>,
+    pub location_shelf: Option<tool::LocationShelf>,
+    pub location_bin: Option<tool::LocationBin>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,6 +70,9 @@ pub struct FilterParams {
     pub statuses: Option<Vec<tool::Status>>,
     pub categories: Option<Vec<tool_category::Id>>,
     pub match_all_categories: Option<bool>,
+    pub min_available: Option<tool::Quantity>,
+    // by relevance when there's a search term, otherwise by id
+    pub order_by: Option<tools::OrderBy>,
     pub page: Option<i64>,
 }
 
@@ -63,6 +91,14 @@ pub struct ToolPhotoInfo {
     pub photo_key: tool_photo::PhotoKey,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolHoldInfo {
+    pub user_id: tool_hold::UserId,
+    pub username: user::Username,
+    pub expires_at: Option<tool_hold::ExpiresAt>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolWithText {
@@ -75,7 +111,15 @@ pub struct ToolWithText {
     pub long_description: Option<tool::LongDescription>,
     pub pictures: Vec<ToolPhotoInfo>,
     pub status: tool::Status,
+    pub deposit: Option<tool::Deposit>,
+    pub quantity: tool::Quantity,
+    pub available_quantity: i64,
+    pub location_room: Option<tool::LocationRoom>,
+    pub location_shelf: Option<tool::LocationShelf>,
+    pub location_bin: Option<tool::LocationBin>,
+    pub on_hold_for: Option<ToolHoldInfo>,
     pub categories: Vec<tool_category::ToolCategory>,
+    pub open_repair_tickets: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -89,17 +133,97 @@ pub struct ToolWithClassifications {
     pub long_description: Option<tool::LongDescription>,
     pub pictures: Vec<ToolPhotoInfo>,
     pub status: tool::Status,
+    pub deposit: Option<tool::Deposit>,
+    pub quantity: tool::Quantity,
+    pub available_quantity: i64,
+    pub location_room: Option<tool::LocationRoom>,
+    pub location_shelf: Option<tool::LocationShelf>,
+    pub location_bin: Option<tool::LocationBin>,
+    pub on_hold_for: Option<ToolHoldInfo>,
     pub classifications: Vec<tool_classification::CategoryId>,
+    // how well the tool matched the search term, if there was one
+    pub score: Option<tools::Score>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolSearchResponse {
     pub tools: Vec<ToolWithClassifications>,
+    pub kits: Vec<KitWithMembers>,
     pub stores: Vec<store::Store>,
     pub categories: Vec<tool_category::ToolCategory>,
 }
 
+async fn select_hold_info(
+    tool_ids: Vec<tool::Id>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<(tool::Id, ToolHoldInfo)>, common::ErrResponse> {
+    if tool_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    match tool_holds::select_on_hold(tool_ids, db).await {
+        Ok(holds) => Ok(holds
+            .into_iter()
+            .map(|h| {
+                (
+                    h.tool_id,
+                    ToolHoldInfo {
+                        user_id: h.user_id,
+                        username: h.username,
+                        expires_at: h.expires_at,
+                    },
+                )
+            })
+            .collect()),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+async fn select_open_units(
+    tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<rentals::OpenUnits>, common::ErrResponse> {
+    match rentals::select_open_units(tool_ids, db).await {
+        Ok(units) => Ok(units),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
+// a tool that isn't available has nothing to lend, however many units it has
+pub fn available_units(tool: &tool::Tool, open_units: &[rentals::OpenUnits]) -> i64 {
+    if tool.status != tool::ToolStatus::Available as i32 {
+        return 0;
+    }
+    let out = open_units
+        .iter()
+        .find(|u| u.tool_id == tool.id)
+        .map_or(0, |u| u.units);
+    (i64::from(tool.quantity) - out).max(0)
+}
+
+async fn select_open_ticket_count(
+    tool_id: tool::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<i64, common::ErrResponse> {
+    match repair_tickets::select_open_counts(vec![tool_id], db).await {
+        Ok(counts) => Ok(counts.first().map_or(0, |c| c.count)),
+        Err(e) => Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        )),
+    }
+}
+
 pub async fn create_new(
     claims: Claims,
     State(state): State<Arc<AppState>>,
@@ -113,6 +237,17 @@ pub async fn create_new(
         ));
     }
 
+    let created_by = match claims.subject_as_user_id() {
+        Some(id) => id,
+        None => {
+            return Err(common::ErrResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "ERR_AUTH",
+                "Invalid user id in claims",
+            ))
+        }
+    };
+
     common::none_or_verify_payload_text_length(
         payload.real_id.as_deref(),
         1,
@@ -131,6 +266,29 @@ pub async fn create_new(
         common::MAX_TOOL_LONG_DESCRIPTION_LENGTH,
     )?;
     common::none_or_verify_payload_integer_range(payload.status, 1, i32::MAX)?;
+    if payload
+        .status
+        .is_some_and(|s| !tool_lifecycle::is_initial_status(s))
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ERR_REQ",
+            "A new tool cannot start out rented",
+        ));
+    }
+    common::none_or_verify_payload_integer_range(payload.deposit, 0, common::MAX_LEDGER_AMOUNT)?;
+    common::none_or_verify_payload_integer_range(payload.quantity, 1, common::MAX_TOOL_QUANTITY)?;
+    for part in [
+        &payload.location_room,
+        &payload.location_shelf,
+        &payload.location_bin,
+    ] {
+        common::none_or_verify_payload_text_length(
+            part.as_deref(),
+            1,
+            common::MAX_TOOL_LOCATION_LENGTH,
+        )?;
+    }
     common::verify_payload_integer_range(
         payload.category_ids.len().try_into().unwrap_or_default(),
         1,
@@ -182,13 +340,27 @@ pub async fn create_new(
         ));
     }
 
-    let tool = match tools::insert(
-        payload.real_id.unwrap_or(common::rnd_code_str("t-")),
+    kit_handlers::verify_real_id_free_of_kits(
         payload.store_id,
-        payload.rental_hours,
-        payload.short_description,
-        payload.long_description,
-        payload.status.unwrap_or(tool::ToolStatus::Available as i32),
+        payload.real_id.iter().cloned().collect(),
+        &state.db,
+    )
+    .await?;
+
+    let tool = match tools::insert(
+        tools::NewTool {
+            real_id: payload.real_id.unwrap_or(common::rnd_code_str("t-")),
+            store_id: payload.store_id,
+            rental_hours: payload.rental_hours,
+            short_description: payload.short_description,
+            long_description: payload.long_description,
+            status: payload.status.unwrap_or(tool::ToolStatus::Available as i32),
+            deposit: payload.deposit.filter(|d| *d > 0),
+            quantity: payload.quantity.unwrap_or(1),
+            location_room: payload.location_room,
+            location_shelf: payload.location_shelf,
+            location_bin: payload.location_bin,
+        },
         &state.db,
     )
     .await
@@ -203,6 +375,16 @@ pub async fn create_new(
         }
     };
 
+    if let Err(e) =
+        tool_status_history::insert_created(tool.id, tool.status, created_by, &state.db).await
+    {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
     let new_classifications: Vec<ToolClassification> = payload
         .category_ids
         .iter()
@@ -302,6 +484,7 @@ pub async fn create_new(
 
     let encoded = serde_json::to_vec(&tool).unwrap_or_default();
     state.comm.send_message("tools", &encoded).await.ok();
+    let available_quantity = available_units(&tool, &[]);
     Ok(Json(ToolWithText {
         id: tool.id,
         real_id: tool.real_id,
@@ -312,7 +495,15 @@ pub async fn create_new(
         long_description: tool.long_description,
         pictures,
         status: tool.status,
+        deposit: tool.deposit,
+        quantity: tool.quantity,
+        available_quantity,
+        location_room: tool.location_room,
+        location_shelf: tool.location_shelf,
+        location_bin: tool.location_bin,
+        on_hold_for: None,
         categories,
+        open_repair_tickets: 0,
     }))
 }
 
@@ -322,8 +513,20 @@ pub async fn update(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<UpdateToolData>,
 ) -> Result<Json<ToolWithText>, common::ErrResponse> {
-    // all the sql statements in this handler should be inside a transaction, but I'm ignoring that for now
-    match tools::select_by_ids(vec![tool_id], &state.db).await {
+    // the tool row and its queue change together; the category and photo statements below should
+    // be inside the transaction too, but I'm ignoring that for now
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e.to_string(),
+            ));
+        }
+    };
+
+    let current = match tools::select_by_ids_for_update(vec![tool_id], &mut *tx).await {
         Ok(tools) => {
             if tools.is_empty() {
                 return Err(common::ErrResponse::new(
@@ -340,6 +543,7 @@ pub async fn update(
                     "User is not a tool manager of this store",
                 ));
             }
+            tools
         }
         Err(e) => {
             return Err(common::ErrResponse::new(
@@ -348,7 +552,7 @@ pub async fn update(
                 &e,
             ));
         }
-    }
+    };
 
     common::none_or_verify_payload_text_length(
         payload.real_id.as_deref(),
@@ -367,6 +571,24 @@ pub async fn update(
         common::MAX_TOOL_LONG_DESCRIPTION_LENGTH,
     )?;
     common::none_or_verify_payload_integer_range(payload.status, 1, i32::MAX)?;
+    common::none_or_verify_payload_text_length(
+        payload.status_reason.as_deref(),
+        1,
+        common::MAX_TOOL_STATUS_REASON_LENGTH,
+    )?;
+    common::none_or_verify_payload_integer_range(payload.deposit, 0, common::MAX_LEDGER_AMOUNT)?;
+    common::none_or_verify_payload_integer_range(payload.quantity, 1, common::MAX_TOOL_QUANTITY)?;
+    for part in [
+        &payload.location_room,
+        &payload.location_shelf,
+        &payload.location_bin,
+    ] {
+        common::none_or_verify_payload_text_length(
+            part.as_deref(),
+            0,
+            common::MAX_TOOL_LOCATION_LENGTH,
+        )?;
+    }
     common::none_or_verify_payload_integer_range(
         payload
             .category_ids
@@ -384,14 +606,43 @@ pub async fn update(
         common::MAX_TOOL_PHOTOS_LENGTH,
     )?;
 
-    let tool = match tools::update(
+    kit_handlers::verify_real_id_free_of_kits(
+        current[0].store_id,
+        payload.real_id.iter().cloned().collect(),
+        &mut *tx,
+    )
+    .await?;
+
+    if let Some(status) = payload.status {
+        let changed_by = match claims.subject_as_user_id() {
+            Some(id) => id,
+            None => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::UNAUTHORIZED,
+                    "ERR_AUTH",
+                    "Invalid user id in claims",
+                ))
+            }
+        };
+
+        change_statuses(
+            &current,
+            status,
+            StatusAction::Edited,
+            payload.status_reason,
+            Some(changed_by),
+            &mut tx,
+        )
+        .await?;
+    }
+
+    let mut tool = match tools::update(
         tool_id,
         payload.real_id,
         payload.rental_hours,
         payload.short_description,
         payload.long_description,
-        payload.status,
-        &state.db,
+        &mut *tx,
     )
     .await
     {
@@ -414,6 +665,117 @@ pub async fn update(
         }
     };
 
+    if let Some(deposit) = payload.deposit {
+        tool = match tools::update_deposit(tool_id, Some(deposit).filter(|d| *d > 0), &mut *tx)
+            .await
+        {
+            Ok(Some(t)) => t,
+            Ok(None) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        };
+    }
+
+    // a rented tool has every unit out, and shrinking past the units out would lend more than exist;
+    // the tool is locked so a check out can't slip in between the count and the update
+    if let Some(quantity) = payload.quantity.filter(|q| *q != tool.quantity) {
+        if tool.status == tool::ToolStatus::Rented as i32 {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                "Quantity cannot change while every unit is out",
+            ));
+        }
+        let units_out = select_open_units(vec![tool_id], &mut *tx)
+            .await?
+            .first()
+            .map_or(0, |u| u.units);
+        if units_out > 0 && i64::from(quantity) <= units_out {
+            return Err(common::ErrResponse::new(
+                StatusCode::CONFLICT,
+                "ERR_CONFLICT",
+                &format!("Quantity must stay above the {} units out", units_out),
+            ));
+        }
+
+        tool = match tools::update_quantity(tool_id, quantity, &mut *tx).await {
+            Ok(Some(t)) => t,
+            Ok(None) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        };
+    }
+
+    if payload.location_room.is_some()
+        || payload.location_shelf.is_some()
+        || payload.location_bin.is_some()
+    {
+        tool = match tools::update_location(
+            tool_id,
+            payload.location_room,
+            payload.location_shelf,
+            payload.location_bin,
+            &mut *tx,
+        )
+        .await
+        {
+            Ok(Some(t)) => t,
+            Ok(None) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "ERR_MIA",
+                    "Tool not found",
+                ));
+            }
+            Err(e) => {
+                return Err(common::ErrResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "ERR_DB",
+                    &e,
+                ));
+            }
+        };
+    }
+
+    // a status or quantity change can free the tool for the next hold in line, or take it away
+    if let Err(e) = refresh_queues(vec![tool_id], &mut tx).await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e,
+        ));
+    }
+
+    if let Err(e) = tx.commit().await {
+        return Err(common::ErrResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ERR_DB",
+            &e.to_string(),
+        ));
+    }
+
     if payload.category_ids.is_some() {
         let existing_categories =
             match tool_classifications::select(vec![tool_id], vec![], &state.db).await {
@@ -620,6 +982,14 @@ pub async fn update(
         })
         .collect();
 
+    let on_hold_for = select_hold_info(vec![tool.id], &state.db)
+        .await?
+        .pop()
+        .map(|(_, h)| h);
+    let open_repair_tickets = select_open_ticket_count(tool.id, &state.db).await?;
+    let available_quantity =
+        available_units(&tool, &select_open_units(vec![tool.id], &state.db).await?);
+
     let encoded = serde_json::to_vec(&tool).unwrap_or_default();
     state.comm.send_message("tools", &encoded).await.ok();
     Ok(Json(ToolWithText {
@@ -632,7 +1002,15 @@ pub async fn update(
         long_description: tool.long_description,
         pictures,
         status: tool.status,
+        deposit: tool.deposit,
+        quantity: tool.quantity,
+        available_quantity,
+        location_room: tool.location_room,
+        location_shelf: tool.location_shelf,
+        location_bin: tool.location_bin,
+        on_hold_for,
         categories,
+        open_repair_tickets,
     }))
 }
 
@@ -720,6 +1098,14 @@ pub async fn get_by_id(
         }
     };
 
+    let on_hold_for = select_hold_info(vec![tool.id], &state.db)
+        .await?
+        .pop()
+        .map(|(_, h)| h);
+    let open_repair_tickets = select_open_ticket_count(tool.id, &state.db).await?;
+    let available_quantity =
+        available_units(&tool, &select_open_units(vec![tool.id], &state.db).await?);
+
     Ok(Json(ToolWithText {
         id: tool.id,
         real_id: tool.real_id,
@@ -730,24 +1116,74 @@ pub async fn get_by_id(
         long_description: tool.long_description,
         pictures,
         status: tool.status,
+        deposit: tool.deposit,
+        quantity: tool.quantity,
+        available_quantity,
+        location_room: tool.location_room,
+        location_shelf: tool.location_shelf,
+        location_bin: tool.location_bin,
+        on_hold_for,
         categories,
+        open_repair_tickets,
     }))
 }
 
 pub async fn get_filtered(
+    claims: Claims,
     Query(params): Query<FilterParams>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<ToolSearchResponse>, common::ErrResponse> {
     let (offset, limit) = common::calculate_offset_limit(params.page.unwrap_or_default());
+    let term = params.term.unwrap_or_default();
+    let category_ids = params.categories.unwrap_or_default();
+    let statuses = params.statuses.unwrap_or_default();
+    let filter_store_ids = params.store_ids.unwrap_or_default();
+    let order_by = params.order_by.unwrap_or(if term.is_empty() {
+        tools::OrderBy::Id
+    } else {
+        tools::OrderBy::Relevance
+    });
 
-    let tools = match tools::select(
-        tools::SelectParams {
-            term: params.term.unwrap_or_default(),
-            category_ids: params.categories.unwrap_or_default(),
+    let scored = match state
+        .search
+        .search_tools(tools::SelectParams {
+            ids: vec![],
+            term: term.clone(),
+            category_ids: category_ids.clone(),
             match_all_categories: params.match_all_categories.unwrap_or_default(),
-            statuses: params.statuses.unwrap_or_default(),
-            store_ids: params.store_ids.unwrap_or_default(),
+            statuses: statuses.clone(),
+            store_ids: filter_store_ids.clone(),
             real_ids: vec![],
+            asset_store_ids: claims.tool_manager_store_ids(),
+            min_available: params.min_available.unwrap_or_default().max(0),
+            order_by,
+            offset,
+            limit,
+        })
+        .await
+    {
+        Ok(t) => t,
+        Err(e) => {
+            return Err(common::ErrResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "ERR_DB",
+                &e,
+            ));
+        }
+    };
+
+    let has_term = !term.is_empty();
+    let (tools, scores): (Vec<tool::Tool>, Vec<tools::Score>) = scored.into_iter().unzip();
+
+    // kits are paged on their own, alongside the tools
+    let kits = match tool_kits::select(
+        tool_kits::SelectParams {
+            ids: vec![],
+            store_ids: filter_store_ids,
+            real_ids: vec![],
+            term,
+            statuses,
+            category_ids,
             offset,
             limit,
         },
@@ -755,7 +1191,7 @@ pub async fn get_filtered(
     )
     .await
     {
-        Ok(t) => t,
+        Ok(k) => k,
         Err(e) => {
             return Err(common::ErrResponse::new(
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -764,10 +1200,16 @@ pub async fn get_filtered(
             ));
         }
     };
+    let kits = kit_handlers::with_members(kits, &state.db).await?;
 
     let tool_ids = tools.iter().map(|t| t.id).collect::<Vec<tool::Id>>();
-    let mut store_ids = tools.iter().map(|t| t.store_id).collect::<Vec<store::Id>>();
-    store_ids.dedup(); // better than nothing
+    let mut store_ids = tools
+        .iter()
+        .map(|t| t.store_id)
+        .chain(kits.iter().map(|k| k.kit.store_id))
+        .collect::<Vec<store::Id>>();
+    store_ids.sort();
+    store_ids.dedup();
 
     let mut classifications: Vec<ToolClassification> = vec![];
     if !tool_ids.is_empty() {
@@ -821,9 +1263,13 @@ pub async fn get_filtered(
         };
     }
 
+    let mut holds = select_hold_info(tool_ids.clone(), &state.db).await?;
+    let open_units = select_open_units(tool_ids.clone(), &state.db).await?;
+
     let tools_with_classifications = tools
         .iter()
-        .map(|t| {
+        .zip(scores)
+        .map(|(t, score)| {
             let classifications = classifications
                 .iter()
                 .filter(|c| c.tool_id == t.id)
@@ -849,7 +1295,18 @@ pub async fn get_filtered(
                 long_description: t.long_description.clone(),
                 pictures: tool_photos,
                 status: t.status,
+                deposit: t.deposit,
+                quantity: t.quantity,
+                available_quantity: available_units(t, &open_units),
+                location_room: t.location_room.clone(),
+                location_shelf: t.location_shelf.clone(),
+                location_bin: t.location_bin.clone(),
+                on_hold_for: holds
+                    .iter()
+                    .position(|(id, _)| *id == t.id)
+                    .map(|i| holds.swap_remove(i).1),
                 classifications,
+                score: has_term.then_some(score),
             }
         })
         .collect();
@@ -881,6 +1338,7 @@ pub async fn get_filtered(
 
     Ok(Json(ToolSearchResponse {
         tools: tools_with_classifications,
+        kits,
         stores,
         categories,
     }))
@@ -0,0 +1,308 @@
+use crate::qr_codes;
+use printpdf::{
+    BuiltinFont, Color, Greyscale, IndirectFontRef, Mm, PdfDocument, PdfLayerReference, Rect,
+};
+use serde::{Deserialize, Serialize};
+
+const LETTER_WIDTH_MM: f32 = 215.9;
+const LETTER_HEIGHT_MM: f32 = 279.4;
+const A4_WIDTH_MM: f32 = 210.0;
+const A4_HEIGHT_MM: f32 = 297.0;
+const GRID_MARGIN_MM: f32 = 10.0;
+const LABEL_PADDING_MM: f32 = 2.0;
+const MM_PER_PT: f32 = 0.3528;
+// rough average glyph width of helvetica, as a fraction of the font size
+const AVG_CHAR_WIDTH: f32 = 0.55;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum LabelLayout {
+    // letter, 3 x 10 address labels
+    #[default]
+    Avery5160,
+    // letter, 2 x 5 shipping labels
+    Avery5163,
+    // a4, 3 x 7 address labels
+    AveryL7160,
+    // any number of rows and columns, spread evenly over the page
+    Grid,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PageSize {
+    #[default]
+    Letter,
+    A4,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LabelSheet {
+    pub page_width: f32,
+    pub page_height: f32,
+    pub columns: usize,
+    pub rows: usize,
+    pub label_width: f32,
+    pub label_height: f32,
+    pub margin_top: f32,
+    pub margin_left: f32,
+    pub pitch_x: f32,
+    pub pitch_y: f32,
+}
+
+#[derive(Debug)]
+pub struct Label {
+    pub payload: String,
+    pub real_id: String,
+    pub short_description: String,
+    pub store_name: String,
+}
+
+impl LabelSheet {
+    pub fn from_layout(layout: LabelLayout) -> Self {
+        match layout {
+            LabelLayout::Avery5160 => Self {
+                page_width: LETTER_WIDTH_MM,
+                page_height: LETTER_HEIGHT_MM,
+                columns: 3,
+                rows: 10,
+                label_width: 66.675,
+                label_height: 25.4,
+                margin_top: 12.7,
+                margin_left: 4.7625,
+                pitch_x: 69.85,
+                pitch_y: 25.4,
+            },
+            LabelLayout::Avery5163 => Self {
+                page_width: LETTER_WIDTH_MM,
+                page_height: LETTER_HEIGHT_MM,
+                columns: 2,
+                rows: 5,
+                label_width: 101.6,
+                label_height: 50.8,
+                margin_top: 12.7,
+                margin_left: 3.96875,
+                pitch_x: 104.775,
+                pitch_y: 50.8,
+            },
+            LabelLayout::AveryL7160 => Self {
+                page_width: A4_WIDTH_MM,
+                page_height: A4_HEIGHT_MM,
+                columns: 3,
+                rows: 7,
+                label_width: 63.5,
+                label_height: 38.1,
+                margin_top: 15.15,
+                margin_left: 7.25,
+                pitch_x: 66.04,
+                pitch_y: 38.1,
+            },
+            LabelLayout::Grid => Self::grid(3, 10, PageSize::default()),
+        }
+    }
+
+    pub fn grid(columns: usize, rows: usize, page_size: PageSize) -> Self {
+        let (page_width, page_height) = match page_size {
+            PageSize::Letter => (LETTER_WIDTH_MM, LETTER_HEIGHT_MM),
+            PageSize::A4 => (A4_WIDTH_MM, A4_HEIGHT_MM),
+        };
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        let pitch_x = (page_width - 2.0 * GRID_MARGIN_MM) / columns as f32;
+        let pitch_y = (page_height - 2.0 * GRID_MARGIN_MM) / rows as f32;
+
+        Self {
+            page_width,
+            page_height,
+            columns,
+            rows,
+            label_width: pitch_x,
+            label_height: pitch_y,
+            margin_top: GRID_MARGIN_MM,
+            margin_left: GRID_MARGIN_MM,
+            pitch_x,
+            pitch_y,
+        }
+    }
+
+    fn per_page(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    // bottom left corner of the nth label on a page; pdf coordinates start at the bottom
+    fn origin(&self, n: usize) -> (f32, f32) {
+        let column = n % self.columns;
+        let row = n / self.columns;
+        let x = self.margin_left + column as f32 * self.pitch_x;
+        let y = self.page_height - self.margin_top - row as f32 * self.pitch_y - self.label_height;
+        (x, y)
+    }
+}
+
+struct Fonts {
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+}
+
+pub fn render_sheets(title: &str, sheet: &LabelSheet, labels: &[Label]) -> Result<Vec<u8>, String> {
+    let (doc, page, layer) =
+        PdfDocument::new(title, Mm(sheet.page_width), Mm(sheet.page_height), "labels");
+    let fonts = Fonts {
+        regular: doc
+            .add_builtin_font(BuiltinFont::Helvetica)
+            .map_err(|e| e.to_string())?,
+        bold: doc
+            .add_builtin_font(BuiltinFont::HelveticaBold)
+            .map_err(|e| e.to_string())?,
+    };
+
+    let mut current = doc.get_page(page).get_layer(layer);
+    for (i, label) in labels.iter().enumerate() {
+        let n = i % sheet.per_page();
+        if i > 0 && n == 0 {
+            let (page, layer) = doc.add_page(Mm(sheet.page_width), Mm(sheet.page_height), "labels");
+            current = doc.get_page(page).get_layer(layer);
+        }
+        draw_label(&current, &fonts, sheet, n, label)?;
+    }
+
+    doc.save_to_bytes().map_err(|e| e.to_string())
+}
+
+fn draw_label(
+    layer: &PdfLayerReference,
+    fonts: &Fonts,
+    sheet: &LabelSheet,
+    n: usize,
+    label: &Label,
+) -> Result<(), String> {
+    let (x, y) = sheet.origin(n);
+    let scale = (sheet.label_height / 25.4).clamp(0.6, 1.6);
+    let qr_size = (sheet.label_height - 2.0 * LABEL_PADDING_MM).min(sheet.label_width * 0.45);
+
+    layer.set_fill_color(Color::Greyscale(Greyscale::new(0.0, None)));
+    draw_code(
+        layer,
+        &label.payload,
+        x + LABEL_PADDING_MM,
+        y + LABEL_PADDING_MM,
+        qr_size,
+    )?;
+
+    let text_x = x + qr_size + 2.0 * LABEL_PADDING_MM;
+    let text_width = sheet.label_width - qr_size - 3.0 * LABEL_PADDING_MM;
+    let top = y + sheet.label_height - LABEL_PADDING_MM;
+
+    let id_size = 10.0 * scale;
+    let mut line_y = top - id_size * MM_PER_PT;
+    layer.use_text(
+        fit_text(&label.real_id, text_width, id_size),
+        id_size,
+        Mm(text_x),
+        Mm(line_y),
+        &fonts.bold,
+    );
+
+    let description_size = 7.0 * scale;
+    for line in wrap_text(&label.short_description, text_width, description_size, 2) {
+        line_y -= description_size * MM_PER_PT * 1.3;
+        layer.use_text(
+            line,
+            description_size,
+            Mm(text_x),
+            Mm(line_y),
+            &fonts.regular,
+        );
+    }
+
+    let store_size = 6.0 * scale;
+    layer.use_text(
+        fit_text(&label.store_name, text_width, store_size),
+        store_size,
+        Mm(text_x),
+        Mm(y + LABEL_PADDING_MM),
+        &fonts.regular,
+    );
+
+    Ok(())
+}
+
+// drawn as filled rectangles rather than an embedded image, so it stays sharp at any dpi
+fn draw_code(
+    layer: &PdfLayerReference,
+    payload: &str,
+    x: f32,
+    y: f32,
+    size: f32,
+) -> Result<(), String> {
+    let (width, modules) = qr_codes::render_modules(payload)?;
+    let module_size = size / width as f32;
+
+    for (row, cells) in modules.chunks(width).enumerate() {
+        let top = y + size - row as f32 * module_size;
+        let mut column = 0;
+        // merge runs of dark modules in a row into one rectangle
+        while column < width {
+            if !cells[column] {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < width && cells[column] {
+                column += 1;
+            }
+            layer.add_rect(Rect::new(
+                Mm(x + start as f32 * module_size),
+                Mm(top - module_size),
+                Mm(x + column as f32 * module_size),
+                Mm(top),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn max_chars(width: f32, font_size: f32) -> usize {
+    (width / (font_size * MM_PER_PT * AVG_CHAR_WIDTH)).max(1.0) as usize
+}
+
+fn fit_text(text: &str, width: f32, font_size: f32) -> String {
+    let max = max_chars(width, font_size);
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut fitted: String = text.chars().take(max.saturating_sub(3)).collect();
+    fitted.push_str("...");
+    fitted
+}
+
+fn wrap_text(text: &str, width: f32, font_size: f32, max_lines: usize) -> Vec<String> {
+    let max = max_chars(width, font_size);
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        if current.is_empty() {
+            current = word.to_string();
+        } else if current.chars().count() + 1 + word.chars().count() <= max {
+            current.push(' ');
+            current.push_str(word);
+        } else {
+            lines.push(std::mem::take(&mut current));
+            current = word.to_string();
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    if lines.len() > max_lines {
+        let rest = lines.split_off(max_lines - 1).join(" ");
+        lines.push(rest);
+    }
+    lines
+        .into_iter()
+        .map(|l| fit_text(&l, width, font_size))
+        .collect()
+}
@@ -2,13 +2,20 @@ mod auth;
 mod common;
 mod db_structs;
 mod handlers;
+mod labels;
+mod opening_hours;
+mod overdue_monitor;
+mod qr_codes;
 mod queries;
 mod rabbit;
+mod search;
+mod tool_lifecycle;
 mod usernames;
 
 use axum::{extract::DefaultBodyLimit, routing, Router};
 use handlers::photos;
 use rabbit::communicator::Communicator;
+use search::{elastic::ElasticSearch, postgres::PostgresSearch, SearchBackend};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use std::{env, error::Error, net::SocketAddr, sync::Arc};
 #[cfg(feature = "cors")]
@@ -18,12 +25,27 @@ use tower_http::cors::CorsLayer;
 pub struct AppState {
     db: PgPool,
     comm: Communicator,
+    search: Arc<dyn SearchBackend>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenvy::dotenv().expect("Failed to read .env file");
 
+    let pg_connection_string = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&pg_connection_string)
+        .await
+        .expect("Failed to create pool.");
+
+    // `lobster-tail reindex` rebuilds the elastic indices from postgres, then exits
+    if env::args().nth(1).as_deref() == Some("reindex") {
+        let (tool_count, store_count) = ElasticSearch::from_env(pool).reindex().await?;
+        println!("Reindexed {} tools and {} stores", tool_count, store_count);
+        return Ok(());
+    }
+
     let (_rabbit_conn, comm) = match rabbit::communicator::init().await {
         Ok((conn, comm)) => (Some(conn), comm),
         Err(e) => {
@@ -32,17 +54,27 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     };
 
-    let pg_connection_string = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&pg_connection_string)
-        .await
-        .expect("Failed to create pool.");
+    let search: Arc<dyn SearchBackend> = match search::configured_backend() {
+        search::BackendKind::Postgres => Arc::new(PostgresSearch::new(pool.clone())),
+        search::BackendKind::Elastic => {
+            let elastic = ElasticSearch::from_env(pool.clone());
+            if let Err(e) = elastic.create_indices().await {
+                eprintln!("Failed to create elastic indices: {}", e);
+            }
+            // the indexer keeps a connection of its own, and opens it again whenever it drops
+            tokio::spawn(search::indexer::run(elastic.clone()));
+            Arc::new(elastic)
+        }
+    };
+
     let shared_state = Arc::new(AppState {
         db: pool,
         comm: comm,
+        search,
     });
 
+    tokio::spawn(overdue_monitor::run(shared_state.clone()));
+
     {
         let photo_storage_path = photos::get_root_path();
         std::fs::create_dir_all(photo_storage_path)?;
@@ -88,6 +120,23 @@ async fn main() -> Result<(), Box<dyn Error>> {
             "/users/:user_id/permissions",
             routing::get(handlers::permissions::get_by_user),
         )
+        .route(
+            "/users/:user_id/qr",
+            routing::get(handlers::qr_codes::get_user_code),
+        )
+        .route(
+            "/users/:user_id/ledger",
+            routing::get(handlers::ledger_entries::get_by_user)
+                .post(handlers::ledger_entries::create_new),
+        )
+        .route(
+            "/users/:user_id/balance",
+            routing::get(handlers::ledger_entries::get_balance),
+        )
+        .route(
+            "/users/:user_id/holds",
+            routing::get(handlers::tool_holds::get_by_user),
+        )
         .route(
             "/stores",
             routing::get(handlers::stores::get_filtered).post(handlers::stores::create_new),
@@ -96,6 +145,68 @@ async fn main() -> Result<(), Box<dyn Error>> {
             "/stores/:store_id",
             routing::patch(handlers::stores::update_info).get(handlers::stores::get_by_id),
         )
+        .route(
+            "/stores/:store_id/qr",
+            routing::get(handlers::qr_codes::get_store_code),
+        )
+        .route(
+            "/stores/:store_id/fee-schedule",
+            routing::get(handlers::fee_schedules::get_by_store)
+                .put(handlers::fee_schedules::update),
+        )
+        .route(
+            "/stores/:store_id/hours",
+            routing::get(handlers::store_hours::get_by_store).put(handlers::store_hours::update),
+        )
+        .route(
+            "/stores/:store_id/holidays",
+            routing::post(handlers::store_hours::create_holiday),
+        )
+        .route(
+            "/stores/:store_id/holidays/:holiday_id",
+            routing::delete(handlers::store_hours::delete_holiday),
+        )
+        .route(
+            "/stores/:store_id/policy",
+            routing::get(handlers::store_policies::get_by_store)
+                .put(handlers::store_policies::update),
+        )
+        .route(
+            "/stores/:store_id/deposits",
+            routing::get(handlers::deposits::get_by_store),
+        )
+        .route(
+            "/stores/:store_id/maintenance-due",
+            routing::get(handlers::maintenance::get_due_by_store),
+        )
+        .route(
+            "/stores/:store_id/repair-tickets",
+            routing::get(handlers::repair_tickets::get_by_store),
+        )
+        .route(
+            "/stores/:store_id/labels",
+            routing::get(handlers::labels::get_store_labels),
+        )
+        .route(
+            "/stores/:store_id/tools/import",
+            routing::post(handlers::tool_imports::import),
+        )
+        .route(
+            "/stores/:store_id/tools/export",
+            routing::get(handlers::tool_imports::export),
+        )
+        .route(
+            "/stores/:store_id/tool-transfers",
+            routing::get(handlers::tool_transfers::get_by_store),
+        )
+        .route(
+            "/stores/:store_id/asset-register",
+            routing::get(handlers::tool_assets::get_register),
+        )
+        .route(
+            "/stores/:store_id/stocktakes",
+            routing::get(handlers::stocktakes::get_by_store).post(handlers::stocktakes::create_new),
+        )
         .route(
             "/stores/:store_id/status",
             routing::patch(handlers::stores::update_status),
@@ -127,6 +238,99 @@ async fn main() -> Result<(), Box<dyn Error>> {
             "/tools/:tool_id",
             routing::patch(handlers::tools::update).get(handlers::tools::get_by_id),
         )
+        .route(
+            "/tools/:tool_id/qr",
+            routing::get(handlers::qr_codes::get_tool_code),
+        )
+        .route(
+            "/tools/:tool_id/asset",
+            routing::get(handlers::tool_assets::get_by_tool).put(handlers::tool_assets::update),
+        )
+        .route(
+            "/tools/:tool_id/holds",
+            routing::get(handlers::tool_holds::get_by_tool)
+                .post(handlers::tool_holds::join)
+                .delete(handlers::tool_holds::leave),
+        )
+        .route(
+            "/tools/:tool_id/holds/order",
+            routing::patch(handlers::tool_holds::reorder),
+        )
+        .route(
+            "/tools/:tool_id/holds/clear",
+            routing::post(handlers::tool_holds::clear),
+        )
+        .route(
+            "/tools/:tool_id/condition-reports",
+            routing::get(handlers::condition_reports::get_by_tool),
+        )
+        .route(
+            "/tools/:tool_id/status-history",
+            routing::get(handlers::tool_status_history::get_by_tool),
+        )
+        .route(
+            "/tools/:tool_id/found",
+            routing::post(handlers::tool_status_history::mark_found),
+        )
+        .route(
+            "/tools/:tool_id/service-log",
+            routing::get(handlers::maintenance::get_service_log)
+                .post(handlers::maintenance::create_service_entry),
+        )
+        .route(
+            "/tools/:tool_id/repair-tickets",
+            routing::get(handlers::repair_tickets::get_by_tool)
+                .post(handlers::repair_tickets::create_new),
+        )
+        .route(
+            "/repair-tickets/:ticket_id",
+            routing::get(handlers::repair_tickets::get_by_id)
+                .patch(handlers::repair_tickets::update),
+        )
+        .route("/tool-kits", routing::post(handlers::tool_kits::create_new))
+        .route(
+            "/tool-kits/:kit_id",
+            routing::get(handlers::tool_kits::get_by_id)
+                .patch(handlers::tool_kits::update)
+                .delete(handlers::tool_kits::delete),
+        )
+        .route(
+            "/tool-transfers",
+            routing::post(handlers::tool_transfers::create_new),
+        )
+        .route(
+            "/tool-transfers/:transfer_id",
+            routing::get(handlers::tool_transfers::get_by_id),
+        )
+        .route(
+            "/tool-transfers/:transfer_id/status",
+            routing::patch(handlers::tool_transfers::update_status),
+        )
+        .route(
+            "/stocktakes/:stocktake_id",
+            routing::get(handlers::stocktakes::get_by_id),
+        )
+        .route(
+            "/stocktakes/:stocktake_id/scans",
+            routing::post(handlers::stocktakes::scan),
+        )
+        .route(
+            "/stocktakes/:stocktake_id/status",
+            routing::patch(handlers::stocktakes::update_status),
+        )
+        .route(
+            "/stocktakes/:stocktake_id/missing",
+            routing::post(handlers::stocktakes::mark_missing),
+        )
+        .route(
+            "/maintenance-schedules",
+            routing::get(handlers::maintenance::get_schedules)
+                .post(handlers::maintenance::create_schedule),
+        )
+        .route(
+            "/maintenance-schedules/:schedule_id",
+            routing::delete(handlers::maintenance::delete_schedule),
+        )
         .route(
             "/tools/exact-real-id",
             routing::get(handlers::tools::get_by_exact_real_id),
@@ -140,10 +344,57 @@ async fn main() -> Result<(), Box<dyn Error>> {
             "/rentals/check-out",
             routing::post(handlers::rentals::check_out),
         )
+        .route(
+            "/rentals/check-in/real-ids",
+            routing::post(handlers::rentals::check_in_by_real_ids),
+        )
+        .route(
+            "/rentals/check-out/real-ids",
+            routing::post(handlers::rentals::check_out_by_real_ids),
+        )
         .route(
             "/rentals/:rental_id",
             routing::patch(handlers::rentals::update).get(handlers::rentals::get_by_id),
         )
+        .route(
+            "/rentals/:rental_id/extensions",
+            routing::get(handlers::rental_extensions::get_by_rental)
+                .post(handlers::rental_extensions::request),
+        )
+        .route(
+            "/rentals/:rental_id/condition-reports",
+            routing::get(handlers::condition_reports::get_by_rental),
+        )
+        .route(
+            "/rentals/:rental_id/reminders",
+            routing::get(handlers::rentals::get_reminders),
+        )
+        .route("/loans/:loan_id", routing::get(handlers::loans::get_by_id))
+        .route(
+            "/loans/:loan_id/receipt",
+            routing::get(handlers::loans::get_receipt),
+        )
+        .route(
+            "/loans/:loan_id/check-in",
+            routing::post(handlers::loans::check_in),
+        )
+        .route(
+            "/rental-extensions/:extension_id/status",
+            routing::patch(handlers::rental_extensions::update_status),
+        )
+        .route(
+            "/reservations",
+            routing::get(handlers::reservations::get_filtered)
+                .post(handlers::reservations::create_new),
+        )
+        .route(
+            "/reservations/:reservation_id",
+            routing::get(handlers::reservations::get_by_id),
+        )
+        .route(
+            "/reservations/:reservation_id/cancel",
+            routing::post(handlers::reservations::cancel),
+        )
         .route(
             "/grievances",
             routing::get(handlers::grievances::get_filtered).post(handlers::grievances::create_new),
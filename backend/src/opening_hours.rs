@@ -0,0 +1,124 @@
+use crate::db_structs::{store_holiday::StoreHoliday, store_hours::StoreHours};
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+use time_tz::{timezones, OffsetDateTimeExt, PrimitiveDateTimeExt, Tz};
+
+// how far ahead to look for the next opening before deciding the store never opens
+const LOOKAHEAD_DAYS: i64 = 366;
+
+// longer than any clock change, past or present
+const MAX_GAP_MINUTES: i64 = 180;
+
+pub fn find_timezone(name: &str) -> Option<&'static Tz> {
+    timezones::get_by_name(name)
+}
+
+// When a store is open, worked out in its own timezone. A store with no weekly hours
+// is taken to be open all day, every day except its holidays.
+pub struct Schedule {
+    timezone: &'static Tz,
+    hours: Vec<StoreHours>,
+    holidays: Vec<StoreHoliday>,
+}
+
+impl Schedule {
+    // an unknown timezone falls back to utc, rather than leaving the store without a schedule
+    pub fn new(timezone: &str, hours: Vec<StoreHours>, holidays: Vec<StoreHoliday>) -> Self {
+        Self {
+            timezone: find_timezone(timezone).unwrap_or(timezones::db::UTC),
+            hours,
+            holidays,
+        }
+    }
+
+    pub fn has_hours(&self) -> bool {
+        !self.hours.is_empty()
+    }
+
+    fn is_holiday(&self, date: Date) -> bool {
+        self.holidays
+            .iter()
+            .any(|h| h.starts_on <= date && date <= h.ends_on)
+    }
+
+    // local wall clock time to an instant; a time skipped when the clocks spring forward moves on
+    // to the first minute after the gap
+    fn instant(&self, date: Date, time: Time) -> OffsetDateTime {
+        let local = PrimitiveDateTime::new(date, time);
+        (0..=MAX_GAP_MINUTES)
+            .find_map(|m| {
+                (local + Duration::minutes(m))
+                    .assume_timezone(self.timezone)
+                    .take_first()
+            })
+            .unwrap_or_else(|| local.assume_timezone_utc(self.timezone))
+    }
+
+    fn slots_on(&self, date: Date) -> Vec<(OffsetDateTime, OffsetDateTime)> {
+        if self.is_holiday(date) {
+            return vec![];
+        }
+
+        if !self.has_hours() {
+            return match date.next_day() {
+                Some(next) => vec![(
+                    self.instant(date, Time::MIDNIGHT),
+                    self.instant(next, Time::MIDNIGHT),
+                )],
+                None => vec![],
+            };
+        }
+
+        // hours that close at or before they open run past midnight, into the next day
+        let weekday = i32::from(date.weekday().number_from_monday());
+        self.hours
+            .iter()
+            .filter(|h| h.weekday == weekday)
+            .filter_map(|h| {
+                let closes_on = if h.closes_at <= h.opens_at {
+                    date.next_day()?
+                } else {
+                    date
+                };
+                Some((
+                    self.instant(date, h.opens_at),
+                    self.instant(closes_on, h.closes_at),
+                ))
+            })
+            .collect()
+    }
+
+    // the open period that the given moment falls in, or else the next one to start
+    pub fn next_slot(&self, at: OffsetDateTime) -> Option<(OffsetDateTime, OffsetDateTime)> {
+        // starting the day before, for a slot that runs past midnight
+        let today = at.to_timezone(self.timezone).date();
+        for offset in -1..LOOKAHEAD_DAYS {
+            let date = today.checked_add(Duration::days(offset))?;
+            let mut slots = self.slots_on(date);
+            slots.sort_by_key(|(start, _)| *start);
+            if let Some(slot) = slots.into_iter().find(|(_, end)| *end > at) {
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    pub fn is_open_at(&self, at: OffsetDateTime) -> bool {
+        self.next_slot(at).is_some_and(|(start, _)| start <= at)
+    }
+
+    // when the store is closed at the given moment, the moment it next opens
+    pub fn opens_at(&self, at: OffsetDateTime) -> Option<OffsetDateTime> {
+        self.next_slot(at)
+            .map(|(start, _)| start)
+            .filter(|start| *start > at)
+    }
+
+    // a due date that lands while the store is closed moves to the end of its next open period,
+    // so the tool can be brought back at any point during that time
+    pub fn roll_forward(&self, due: OffsetDateTime) -> OffsetDateTime {
+        match self.next_slot(due) {
+            Some((start, end)) if start > due => end,
+            _ => due,
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::common;
+use crate::db_structs::tool_status_change::StatusAction;
+use crate::db_structs::{grievance, rental, rental_reminder, store, tool};
+use crate::handlers::tool_holds::refresh_queues;
+use crate::handlers::tool_status_history::change_statuses;
+use crate::queries::rental_reminders::{self, OverdueRental};
+use crate::queries::{grievances, library, rentals, tools};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::{env, sync::Arc, time::Duration};
+
+pub const OVERDUE_ROUTING_KEY: &str = "rental.overdue";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverdueMessage {
+    pub event: String,
+    pub rental_id: rental::Id,
+    pub tool_id: tool::Id,
+    pub tool_real_id: tool::RealId,
+    pub store_id: store::Id,
+    pub renter_id: rental::RenterId,
+    pub due_date: rental::DueDate,
+    pub days_overdue: rental_reminder::DaysOverdue,
+}
+
+struct Thresholds {
+    reminder_interval_days: i32,
+    lost_after_days: Option<i32>,
+    grievance_after_days: Option<i32>,
+}
+
+// Runs forever, checking for overdue rentals every OVERDUE_CHECK_INTERVAL_SECONDS (or the env var of the same name).
+pub async fn run(state: Arc<AppState>) {
+    let seconds = env::var("OVERDUE_CHECK_INTERVAL_SECONDS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(common::OVERDUE_CHECK_INTERVAL_SECONDS);
+    let mut interval = tokio::time::interval(Duration::from_secs(seconds));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = check_all(&state).await {
+            eprintln!("Overdue monitor failed: {}", e);
+        }
+    }
+}
+
+async fn check_all(state: &AppState) -> Result<(), String> {
+    let thresholds = match library::select_information(&state.db).await? {
+        Some(info) => Thresholds {
+            reminder_interval_days: info.overdue_reminder_interval_days.max(1),
+            lost_after_days: info.overdue_lost_after_days,
+            grievance_after_days: info.overdue_grievance_after_days,
+        },
+        None => Thresholds {
+            reminder_interval_days: 1,
+            lost_after_days: None,
+            grievance_after_days: None,
+        },
+    };
+
+    let now = time::OffsetDateTime::now_utc();
+    let overdue = rental_reminders::select_overdue(now, &state.db).await?;
+
+    let mut unsent = 0;
+    for rental in &overdue {
+        let days_overdue = (now - rental.due_date).whole_days() as i32;
+
+        // one reminder per interval; intervals missed while the server was down are skipped, not replayed
+        let bucket = days_overdue - days_overdue % thresholds.reminder_interval_days;
+        if !send_reminder(state, rental, bucket).await? {
+            unsent += 1;
+        }
+
+        if let Some(days) = thresholds.lost_after_days.filter(|d| days_overdue >= *d) {
+            mark_lost(state, rental, days).await?;
+        }
+
+        if let Some(days) = thresholds
+            .grievance_after_days
+            .filter(|d| days_overdue >= *d)
+        {
+            open_grievance(state, rental, days, days_overdue).await?;
+        }
+    }
+
+    if unsent > 0 {
+        eprintln!("Overdue monitor could not publish {} reminders", unsent);
+    }
+
+    Ok(())
+}
+
+// Records the reminder, then publishes it once that has committed, so no event goes out for a reminder that
+// was rolled back. Returns false if the message could not be published.
+async fn send_reminder(
+    state: &AppState,
+    rental: &OverdueRental,
+    days_overdue: i32,
+) -> Result<bool, String> {
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+
+    let is_new = rental_reminders::insert(
+        rental.rental_id,
+        rental_reminder::ReminderKind::Overdue as i32,
+        days_overdue,
+        &mut *tx,
+    )
+    .await?;
+    if !is_new {
+        return Ok(true);
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    let msg = OverdueMessage {
+        event: OVERDUE_ROUTING_KEY.to_string(),
+        rental_id: rental.rental_id,
+        tool_id: rental.tool_id,
+        tool_real_id: rental.tool_real_id.clone(),
+        store_id: rental.store_id,
+        renter_id: rental.renter_id,
+        due_date: rental.due_date,
+        days_overdue,
+    };
+    let encoded = serde_json::to_vec(&msg).unwrap_or_default();
+    Ok(state
+        .comm
+        .send_event("rentals", OVERDUE_ROUTING_KEY, &encoded)
+        .await
+        .is_ok())
+}
+
+async fn mark_lost(state: &AppState, rental: &OverdueRental, days: i32) -> Result<(), String> {
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+
+    let is_new = rental_reminders::insert_escalation(
+        rental.rental_id,
+        rental_reminder::ReminderKind::MarkedLost as i32,
+        days,
+        &mut *tx,
+    )
+    .await?;
+    if !is_new {
+        return Ok(());
+    }
+
+    let tools = tools::select_by_ids_for_update(vec![rental.tool_id], &mut *tx).await?;
+    if rentals::mark_units_lost(rental.rental_id, &mut *tx)
+        .await?
+        .is_none()
+    {
+        return Ok(());
+    }
+    let lost_units = rentals::select_open_lost_units(rental.tool_id, &mut *tx).await?;
+
+    // a pool only goes Lost once every unit is; leave the tool alone if a manager already moved it out of Rented
+    let tools: Vec<tool::Tool> = tools
+        .into_iter()
+        .filter(|t| t.status == tool::ToolStatus::Rented as i32 && lost_units >= t.quantity as i64)
+        .collect();
+    if !tools.is_empty() {
+        change_statuses(
+            &tools,
+            tool::ToolStatus::Lost as i32,
+            StatusAction::MarkedLost,
+            Some(format!("{} days overdue", days)),
+            None,
+            &mut tx,
+        )
+        .await
+        .map_err(|e| e.details)?;
+        refresh_queues(vec![rental.tool_id], &mut tx).await?;
+    }
+
+    tx.commit().await.map_err(|e| e.to_string())
+}
+
+async fn open_grievance(
+    state: &AppState,
+    rental: &OverdueRental,
+    days: i32,
+    days_overdue: i32,
+) -> Result<(), String> {
+    let mut tx = state.db.begin().await.map_err(|e| e.to_string())?;
+
+    let is_new = rental_reminders::insert_escalation(
+        rental.rental_id,
+        rental_reminder::ReminderKind::GrievanceOpened as i32,
+        days,
+        &mut *tx,
+    )
+    .await?;
+    if !is_new {
+        return Ok(());
+    }
+
+    // with nobody to sign it, roll back and try again once someone has the role
+    let author_id =
+        match rental_reminders::select_grievance_author(rental.store_id, &mut *tx).await? {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+    grievances::insert(
+        author_id,
+        rental.renter_id,
+        format!("Overdue rental: {}", rental.tool_real_id),
+        format!(
+            "Opened automatically. Tool {} was due back {} and is {} days overdue.",
+            rental.tool_real_id, rental.due_date, days_overdue
+        ),
+        grievance::GrievanceStatus::Pending as i32,
+        &mut *tx,
+    )
+    .await?;
+
+    tx.commit().await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,94 @@
+use crate::db_structs::{store, tool, user};
+use image::{ImageFormat, Luma};
+use qrcode::{render::svg, QrCode};
+
+const PAYLOAD_PREFIX: &str = "lobster";
+const MIN_IMAGE_DIMENSION: u32 = 200;
+
+// What a phone at the shelf reads off a label. Tools are scanned by the real id printed on
+// them, which is only unique within a store, so their payload carries the store id as well.
+//   lobster:tool:<store id>:<real id>
+//   lobster:store:<store code>
+//   lobster:user:<user code>
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScannedCode {
+    Tool {
+        store_id: tool::StoreId,
+        real_id: tool::RealId,
+    },
+    Store(store::Code),
+    User(user::Code),
+}
+
+impl ScannedCode {
+    pub fn parse(payload: &str) -> Option<Self> {
+        let mut parts = payload.trim().splitn(3, ':');
+        if parts.next()? != PAYLOAD_PREFIX {
+            return None;
+        }
+        let kind = parts.next()?;
+        let rest = parts.next()?;
+        if rest.is_empty() {
+            return None;
+        }
+
+        match kind {
+            "tool" => {
+                let (store_id, real_id) = rest.split_once(':')?;
+                let store_id = store_id.parse::<tool::StoreId>().ok()?;
+                if real_id.is_empty() {
+                    return None;
+                }
+                Some(Self::Tool {
+                    store_id,
+                    real_id: real_id.to_string(),
+                })
+            }
+            "store" => Some(Self::Store(rest.to_string())),
+            "user" => Some(Self::User(rest.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn payload(&self) -> String {
+        match self {
+            Self::Tool { store_id, real_id } => {
+                format!("{}:tool:{}:{}", PAYLOAD_PREFIX, store_id, real_id)
+            }
+            Self::Store(code) => format!("{}:store:{}", PAYLOAD_PREFIX, code),
+            Self::User(code) => format!("{}:user:{}", PAYLOAD_PREFIX, code),
+        }
+    }
+}
+
+pub fn render_png(payload: &str) -> Result<Vec<u8>, String> {
+    let code = QrCode::new(payload.as_bytes()).map_err(|e| e.to_string())?;
+    let img = code
+        .render::<Luma<u8>>()
+        .min_dimensions(MIN_IMAGE_DIMENSION, MIN_IMAGE_DIMENSION)
+        .build();
+
+    let mut data = std::io::Cursor::new(Vec::new());
+    img.write_to(&mut data, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(data.into_inner())
+}
+
+pub fn render_svg(payload: &str) -> Result<String, String> {
+    let code = QrCode::new(payload.as_bytes()).map_err(|e| e.to_string())?;
+    Ok(code
+        .render::<svg::Color>()
+        .min_dimensions(MIN_IMAGE_DIMENSION, MIN_IMAGE_DIMENSION)
+        .build())
+}
+
+// the raw module grid, row by row, for renderers that draw the code themselves
+pub fn render_modules(payload: &str) -> Result<(usize, Vec<bool>), String> {
+    let code = QrCode::new(payload.as_bytes()).map_err(|e| e.to_string())?;
+    let modules = code
+        .to_colors()
+        .into_iter()
+        .map(|c| c == qrcode::Color::Dark)
+        .collect();
+    Ok((code.width(), modules))
+}
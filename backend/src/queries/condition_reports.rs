@@ -0,0 +1,138 @@
+use crate::common;
+use crate::db_structs::{condition_report, condition_report_photo, user};
+use crate::queries::tool_photos::TakenPhoto;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportWithText {
+    pub id: condition_report::Id,
+    pub tool_id: condition_report::ToolId,
+    pub rental_id: Option<condition_report::RentalId>,
+    pub reporter_id: condition_report::ReporterId,
+    pub reporter_username: user::Username,
+    pub grade: condition_report::Grade,
+    pub notes: Option<condition_report::Notes>,
+    pub tool_status: condition_report::ToolStatus,
+    pub created_at: condition_report::CreatedAt,
+}
+
+pub async fn select_grades(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.condition_grades cg;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    tool_id: condition_report::ToolId,
+    rental_id: Option<condition_report::RentalId>,
+    reporter_id: condition_report::ReporterId,
+    grade: condition_report::Grade,
+    notes: Option<condition_report::Notes>,
+    tool_status: condition_report::ToolStatus,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<condition_report::ConditionReport, String> {
+    sqlx::query_as!(
+        condition_report::ConditionReport,
+        r#"
+        INSERT INTO main.condition_reports (tool_id, rental_id, reporter_id, grade, notes, tool_status)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *;
+        "#,
+        tool_id,
+        rental_id,
+        reporter_id,
+        grade,
+        notes,
+        tool_status,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// moves uploaded photos that aren't attached to a tool yet over to the report
+pub async fn insert_photos(
+    report_id: condition_report_photo::ReportId,
+    photos: Vec<TakenPhoto>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<condition_report_photo::ConditionReportPhoto>, String> {
+    let (photo_keys, original_names): (Vec<String>, Vec<String>) = photos
+        .into_iter()
+        .map(|p| (p.photo_key, p.original_name))
+        .unzip();
+    sqlx::query_as!(
+        condition_report_photo::ConditionReportPhoto,
+        r#"
+        INSERT INTO main.condition_report_photos (report_id, photo_key, original_name)
+        SELECT $1, * FROM UNNEST($2::text[], $3::text[])
+        RETURNING *;
+        "#,
+        report_id,
+        &photo_keys,
+        &original_names,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select(
+    tool_ids: Vec<condition_report::ToolId>,
+    rental_ids: Vec<condition_report::RentalId>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<ReportWithText>, String> {
+    sqlx::query_as!(
+        ReportWithText,
+        r#"
+        SELECT
+            cr.id,
+            cr.tool_id,
+            cr.rental_id,
+            cr.reporter_id,
+            u.username AS reporter_username,
+            cr.grade,
+            cr.notes,
+            cr.tool_status,
+            cr.created_at
+        FROM main.condition_reports cr
+        JOIN main.users u ON cr.reporter_id = u.id
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR cr.tool_id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR cr.rental_id = ANY($2::integer[]))
+        ORDER BY cr.created_at DESC, cr.id DESC
+        LIMIT 200;
+        "#,
+        &tool_ids,
+        &rental_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_photos(
+    report_ids: Vec<condition_report_photo::ReportId>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<condition_report_photo::ConditionReportPhoto>, String> {
+    sqlx::query_as!(
+        condition_report_photo::ConditionReportPhoto,
+        r#"
+        SELECT *
+        FROM main.condition_report_photos crp
+        WHERE crp.report_id = ANY($1::integer[])
+        ORDER BY crp.id;
+        "#,
+        &report_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
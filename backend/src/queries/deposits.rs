@@ -0,0 +1,157 @@
+use crate::common;
+use crate::db_structs::{deposit, rental, store, tool, user};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SettleData {
+    pub id: deposit::Id,
+    pub status: deposit::Status,
+    pub kept_amount: deposit::KeptAmount,
+    pub reason: Option<deposit::Reason>,
+    pub condition_report_id: Option<deposit::ConditionReportId>,
+    pub settled_at: deposit::SettledAt,
+    pub settled_by: deposit::SettledBy,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositWithText {
+    pub id: deposit::Id,
+    pub rental_id: deposit::RentalId,
+    pub tool_id: deposit::ToolId,
+    pub tool_real_id: tool::RealId,
+    pub user_id: deposit::UserId,
+    pub username: user::Username,
+    pub amount: deposit::Amount,
+    pub status: deposit::Status,
+    pub kept_amount: Option<deposit::KeptAmount>,
+    pub reason: Option<deposit::Reason>,
+    pub condition_report_id: Option<deposit::ConditionReportId>,
+    pub taken_at: deposit::TakenAt,
+    pub settled_at: Option<deposit::SettledAt>,
+}
+
+pub async fn select_statuses(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.deposit_statuses;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// takes whatever deposit the tool asks for at the moment it goes out, once per unit
+pub async fn insert_for_rentals(
+    rental_ids: Vec<rental::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<deposit::Deposit>, String> {
+    sqlx::query_as!(
+        deposit::Deposit,
+        r#"
+        INSERT INTO main.deposits (rental_id, tool_id, user_id, store_id, amount, status)
+        SELECT mr.id, t.id, mr.renter_id, t.store_id, t.deposit * mr.quantity, $2
+        FROM main.rentals mr
+        JOIN main.tools t ON mr.tool_id = t.id
+        WHERE mr.id = ANY($1::integer[]) AND t.deposit > 0
+        RETURNING *;
+        "#,
+        &rental_ids,
+        deposit::DepositStatus::Held as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_rental_ids(
+    rental_ids: Vec<rental::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<deposit::Deposit>, String> {
+    sqlx::query_as!(
+        deposit::Deposit,
+        r#"
+        SELECT *
+        FROM main.deposits
+        WHERE rental_id = ANY($1::integer[])
+        ORDER BY id;
+        "#,
+        &rental_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn settle(
+    data: SettleData,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<deposit::Deposit, String> {
+    sqlx::query_as!(
+        deposit::Deposit,
+        r#"
+        UPDATE main.deposits
+        SET
+            status = $2,
+            kept_amount = $3,
+            reason = $4,
+            condition_report_id = $5,
+            settled_at = $6,
+            settled_by = $7
+        WHERE id = $1
+        RETURNING *;
+        "#,
+        data.id,
+        data.status,
+        data.kept_amount,
+        data.reason,
+        data.condition_report_id,
+        data.settled_at,
+        data.settled_by,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_store(
+    store_id: store::Id,
+    statuses: Vec<deposit::Status>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<DepositWithText>, String> {
+    sqlx::query_as!(
+        DepositWithText,
+        r#"
+        SELECT
+            d.id,
+            d.rental_id,
+            d.tool_id,
+            t.real_id AS tool_real_id,
+            d.user_id,
+            u.username,
+            d.amount,
+            d.status,
+            d.kept_amount,
+            d.reason,
+            d.condition_report_id,
+            d.taken_at,
+            d.settled_at
+        FROM main.deposits d
+        JOIN main.tools t ON d.tool_id = t.id
+        JOIN main.users u ON d.user_id = u.id
+        WHERE d.store_id = $1
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR d.status = ANY($2::integer[]))
+        ORDER BY d.taken_at, d.id;
+        "#,
+        store_id,
+        &statuses,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
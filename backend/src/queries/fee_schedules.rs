@@ -0,0 +1,48 @@
+use crate::db_structs::{fee_schedule, store};
+
+pub async fn select_by_store_ids(
+    store_ids: Vec<store::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<fee_schedule::FeeSchedule>, String> {
+    sqlx::query_as!(
+        fee_schedule::FeeSchedule,
+        r#"
+        SELECT *
+        FROM main.fee_schedules
+        WHERE store_id = ANY($1::integer[]);
+        "#,
+        &store_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn upsert(
+    schedule: fee_schedule::FeeSchedule,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<fee_schedule::FeeSchedule, String> {
+    sqlx::query_as!(
+        fee_schedule::FeeSchedule,
+        r#"
+        INSERT INTO main.fee_schedules (store_id, grace_hours, fee_per_day, fee_cap, max_balance, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (store_id) DO UPDATE SET
+            grace_hours = EXCLUDED.grace_hours,
+            fee_per_day = EXCLUDED.fee_per_day,
+            fee_cap = EXCLUDED.fee_cap,
+            max_balance = EXCLUDED.max_balance,
+            updated_at = EXCLUDED.updated_at
+        RETURNING *;
+        "#,
+        schedule.store_id,
+        schedule.grace_hours,
+        schedule.fee_per_day,
+        schedule.fee_cap,
+        schedule.max_balance,
+        schedule.updated_at,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -45,7 +45,7 @@ pub async fn insert(
     title: grievance::Title,
     description: grievance::Description,
     status: grievance::Status,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<grievance::Grievance, String> {
     sqlx::query_as!(
         grievance::Grievance,
@@ -0,0 +1,157 @@
+use crate::common;
+use crate::db_structs::{ledger_entry, rental};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InsertData {
+    pub user_id: ledger_entry::UserId,
+    pub store_id: ledger_entry::StoreId,
+    pub kind: ledger_entry::Kind,
+    pub amount: ledger_entry::Amount,
+    pub rental_id: Option<ledger_entry::RentalId>,
+    pub note: Option<ledger_entry::Note>,
+    pub created_by: Option<ledger_entry::CreatedBy>,
+}
+
+pub async fn select_kinds(db: &sqlx::Pool<sqlx::Postgres>) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.ledger_entry_kinds;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    data: InsertData,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<ledger_entry::LedgerEntry, String> {
+    sqlx::query_as!(
+        ledger_entry::LedgerEntry,
+        r#"
+        INSERT INTO main.ledger_entries (user_id, store_id, kind, amount, rental_id, note, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *;
+        "#,
+        data.user_id,
+        data.store_id,
+        data.kind,
+        data.amount,
+        data.rental_id,
+        data.note,
+        data.created_by,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// charges every started day past the due date, but only once the grace period is used up,
+// and for each unit on the rental
+pub async fn insert_overdue_fees(
+    rental_ids: Vec<rental::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<ledger_entry::LedgerEntry>, String> {
+    sqlx::query_as!(
+        ledger_entry::LedgerEntry,
+        r#"
+        INSERT INTO main.ledger_entries (user_id, store_id, kind, amount, rental_id)
+        SELECT
+            mr.renter_id,
+            t.store_id,
+            $2,
+            (LEAST(
+                fs.fee_per_day::bigint * CEIL(EXTRACT(EPOCH FROM (mr.end_date - mr.due_date)) / 86400)::bigint,
+                COALESCE(fs.fee_cap, 2147483647)
+            ) * mr.quantity)::integer,
+            mr.id
+        FROM main.rentals mr
+        JOIN main.tools t ON mr.tool_id = t.id
+        JOIN main.fee_schedules fs ON t.store_id = fs.store_id
+        WHERE mr.id = ANY($1::integer[])
+            AND fs.fee_per_day > 0
+            AND mr.end_date > mr.due_date + interval '1' HOUR * fs.grace_hours
+        ON CONFLICT DO NOTHING
+        RETURNING *;
+        "#,
+        &rental_ids,
+        ledger_entry::LedgerEntryKind::OverdueFee as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_user_id(
+    user_id: ledger_entry::UserId,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<ledger_entry::LedgerEntry>, String> {
+    sqlx::query_as!(
+        ledger_entry::LedgerEntry,
+        r#"
+        SELECT *
+        FROM main.ledger_entries
+        WHERE user_id = $1
+        ORDER BY created_at DESC, id DESC;
+        "#,
+        user_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_balance(
+    user_id: ledger_entry::UserId,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<i64, String> {
+    match sqlx::query!(
+        r#"
+        SELECT COALESCE(SUM(amount), 0)::bigint AS "balance!"
+        FROM main.ledger_entries
+        WHERE user_id = $1;
+        "#,
+        user_id,
+    )
+    .fetch_one(db)
+    .await
+    {
+        Ok(row) => Ok(row.balance),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// what the user's open, overdue rentals would be charged if they came back right now
+pub async fn select_accruing_overdue_fees(
+    user_id: ledger_entry::UserId,
+    now: time::OffsetDateTime,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<i64, String> {
+    match sqlx::query!(
+        r#"
+        SELECT COALESCE(SUM(LEAST(
+            fs.fee_per_day::bigint * CEIL(EXTRACT(EPOCH FROM ($2::timestamptz - mr.due_date)) / 86400)::bigint,
+            COALESCE(fs.fee_cap, 2147483647)
+        ) * mr.quantity), 0)::bigint AS "accruing!"
+        FROM main.rentals mr
+        JOIN main.tools t ON mr.tool_id = t.id
+        JOIN main.fee_schedules fs ON t.store_id = fs.store_id
+        WHERE mr.renter_id = $1
+            AND mr.end_date IS NULL
+            AND fs.fee_per_day > 0
+            AND $2::timestamptz > mr.due_date + interval '1' HOUR * fs.grace_hours;
+        "#,
+        user_id,
+        now,
+    )
+    .fetch_one(db)
+    .await
+    {
+        Ok(row) => Ok(row.accruing),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -37,6 +37,11 @@ pub async fn select_information(
 
 pub async fn update_information(
     name: Option<String>,
+    overdue_reminder_interval_days: Option<i32>,
+    overdue_lost_after_days: Option<i32>,
+    overdue_grievance_after_days: Option<i32>,
+    clear_overdue_lost_after_days: bool,
+    clear_overdue_grievance_after_days: bool,
     db: &sqlx::Pool<sqlx::Postgres>,
 ) -> Result<Option<LibraryInformation>, String> {
     sqlx::query_as!(
@@ -44,10 +49,18 @@ pub async fn update_information(
         r#"
         UPDATE main.library_information li
         SET
-            name = COALESCE($1, li.name)
+            name = COALESCE($1, li.name),
+            overdue_reminder_interval_days = COALESCE($2, li.overdue_reminder_interval_days),
+            overdue_lost_after_days = CASE WHEN $5 THEN NULL ELSE COALESCE($3, li.overdue_lost_after_days) END,
+            overdue_grievance_after_days = CASE WHEN $6 THEN NULL ELSE COALESCE($4, li.overdue_grievance_after_days) END
         RETURNING *;
         "#,
         name,
+        overdue_reminder_interval_days,
+        overdue_lost_after_days,
+        overdue_grievance_after_days,
+        clear_overdue_lost_after_days,
+        clear_overdue_grievance_after_days,
     )
     .fetch_optional(db)
     .await
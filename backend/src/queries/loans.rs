@@ -0,0 +1,57 @@
+use crate::common;
+use crate::db_structs::loan;
+
+pub async fn select_channels(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.loan_channels;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    renter_id: loan::RenterId,
+    performed_by: loan::PerformedBy,
+    channel: loan::Channel,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<loan::Loan, String> {
+    sqlx::query_as!(
+        loan::Loan,
+        r#"
+        INSERT INTO main.loans (renter_id, performed_by, channel)
+        VALUES ($1, $2, $3)
+        RETURNING *;
+        "#,
+        renter_id,
+        performed_by,
+        channel,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_id(
+    id: loan::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<loan::Loan>, String> {
+    sqlx::query_as!(
+        loan::Loan,
+        r#"
+        SELECT *
+        FROM main.loans
+        WHERE id = $1;
+        "#,
+        id
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,287 @@
+use crate::db_structs::{
+    maintenance_schedule, service_log_entry, service_log_photo, store, tool, user,
+};
+use crate::queries::tool_photos::TakenPhoto;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryWithText {
+    pub id: service_log_entry::Id,
+    pub tool_id: service_log_entry::ToolId,
+    pub schedule_id: Option<service_log_entry::ScheduleId>,
+    pub schedule_name: Option<maintenance_schedule::Name>,
+    pub performed_by: service_log_entry::PerformedBy,
+    pub performed_by_username: user::Username,
+    pub notes: Option<service_log_entry::Notes>,
+    pub performed_at: service_log_entry::PerformedAt,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct DueMaintenance {
+    pub tool_id: tool::Id,
+    pub tool_real_id: tool::RealId,
+    pub tool_short_description: tool::ShortDescription,
+    pub store_id: store::Id,
+    pub schedule_id: maintenance_schedule::Id,
+    pub schedule_name: maintenance_schedule::Name,
+    pub every_rentals: Option<maintenance_schedule::EveryRentals>,
+    pub every_days: Option<maintenance_schedule::EveryDays>,
+    pub last_serviced_at: Option<service_log_entry::PerformedAt>,
+    pub rentals_since: i64,
+    pub due_at: Option<time::OffsetDateTime>,
+}
+
+pub async fn insert_schedule(
+    tool_id: Option<maintenance_schedule::ToolId>,
+    category_id: Option<maintenance_schedule::CategoryId>,
+    name: maintenance_schedule::Name,
+    every_rentals: Option<maintenance_schedule::EveryRentals>,
+    every_days: Option<maintenance_schedule::EveryDays>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<maintenance_schedule::MaintenanceSchedule, String> {
+    sqlx::query_as!(
+        maintenance_schedule::MaintenanceSchedule,
+        r#"
+        INSERT INTO main.maintenance_schedules (tool_id, category_id, name, every_rentals, every_days)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *;
+        "#,
+        tool_id,
+        category_id,
+        name,
+        every_rentals,
+        every_days,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// schedules that apply to any of the tools, whether set on the tool or on one of its
+// categories, plus those set on any of the categories; everything if both are empty
+pub async fn select_schedules(
+    tool_ids: Vec<tool::Id>,
+    category_ids: Vec<maintenance_schedule::CategoryId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<maintenance_schedule::MaintenanceSchedule>, String> {
+    sqlx::query_as!(
+        maintenance_schedule::MaintenanceSchedule,
+        r#"
+        SELECT ms.*
+        FROM main.maintenance_schedules ms
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL AND ARRAY_LENGTH($2::integer[], 1) IS NULL)
+            OR ms.tool_id = ANY($1::integer[])
+            OR ms.category_id = ANY($2::integer[])
+            OR ms.category_id IN (
+                SELECT tc.category_id
+                FROM main.tool_classifications tc
+                WHERE tc.tool_id = ANY($1::integer[])
+            )
+        ORDER BY ms.id;
+        "#,
+        &tool_ids,
+        &category_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_schedule_by_id(
+    id: maintenance_schedule::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<maintenance_schedule::MaintenanceSchedule>, String> {
+    sqlx::query_as!(
+        maintenance_schedule::MaintenanceSchedule,
+        r#"
+        SELECT *
+        FROM main.maintenance_schedules ms
+        WHERE ms.id = $1;
+        "#,
+        id,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn delete_schedule(
+    id: maintenance_schedule::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        DELETE FROM main.maintenance_schedules ms
+        WHERE ms.id = $1;
+        "#,
+        id,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn insert_entry(
+    tool_id: service_log_entry::ToolId,
+    schedule_id: Option<service_log_entry::ScheduleId>,
+    performed_by: service_log_entry::PerformedBy,
+    notes: Option<service_log_entry::Notes>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<service_log_entry::ServiceLogEntry, String> {
+    sqlx::query_as!(
+        service_log_entry::ServiceLogEntry,
+        r#"
+        INSERT INTO main.service_log (tool_id, schedule_id, performed_by, notes)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *;
+        "#,
+        tool_id,
+        schedule_id,
+        performed_by,
+        notes,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// same as condition reports, uploaded photos not yet on a tool move over to the entry
+pub async fn insert_photos(
+    entry_id: service_log_photo::EntryId,
+    photos: Vec<TakenPhoto>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<service_log_photo::ServiceLogPhoto>, String> {
+    let (photo_keys, original_names): (Vec<String>, Vec<String>) = photos
+        .into_iter()
+        .map(|p| (p.photo_key, p.original_name))
+        .unzip();
+    sqlx::query_as!(
+        service_log_photo::ServiceLogPhoto,
+        r#"
+        INSERT INTO main.service_log_photos (entry_id, photo_key, original_name)
+        SELECT $1, * FROM UNNEST($2::text[], $3::text[])
+        RETURNING *;
+        "#,
+        entry_id,
+        &photo_keys,
+        &original_names,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_entries(
+    tool_id: tool::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<EntryWithText>, String> {
+    sqlx::query_as!(
+        EntryWithText,
+        r#"
+        SELECT
+            sl.id,
+            sl.tool_id,
+            sl.schedule_id,
+            ms.name AS "schedule_name?",
+            sl.performed_by,
+            u.username AS performed_by_username,
+            sl.notes,
+            sl.performed_at
+        FROM main.service_log sl
+        JOIN main.users u ON sl.performed_by = u.id
+        LEFT JOIN main.maintenance_schedules ms ON sl.schedule_id = ms.id
+        WHERE sl.tool_id = $1
+        ORDER BY sl.performed_at DESC, sl.id DESC
+        LIMIT 200;
+        "#,
+        tool_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_photos(
+    entry_ids: Vec<service_log_photo::EntryId>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<service_log_photo::ServiceLogPhoto>, String> {
+    sqlx::query_as!(
+        service_log_photo::ServiceLogPhoto,
+        r#"
+        SELECT *
+        FROM main.service_log_photos slp
+        WHERE slp.entry_id = ANY($1::integer[])
+        ORDER BY slp.id;
+        "#,
+        &entry_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// Every schedule that is due for a tool that isn't retired. Rentals and days are counted from
+// the last time the tool was serviced for that schedule, or from when the schedule was set up.
+pub async fn select_due(
+    store_ids: Vec<store::Id>,
+    tool_ids: Vec<tool::Id>,
+    at: time::OffsetDateTime,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<DueMaintenance>, String> {
+    sqlx::query_as!(
+        DueMaintenance,
+        r#"
+        SELECT
+            t.id AS tool_id,
+            t.real_id AS tool_real_id,
+            t.short_description AS tool_short_description,
+            t.store_id,
+            ms.id AS schedule_id,
+            ms.name AS schedule_name,
+            ms.every_rentals,
+            ms.every_days,
+            ls.performed_at AS "last_serviced_at?",
+            rc.count AS "rentals_since!",
+            COALESCE(ls.performed_at, ms.created_at) + MAKE_INTERVAL(days => ms.every_days) AS due_at
+        FROM main.tools t
+        JOIN main.maintenance_schedules ms ON ms.tool_id = t.id
+            OR ms.category_id IN (
+                SELECT tc.category_id
+                FROM main.tool_classifications tc
+                WHERE tc.tool_id = t.id
+            )
+        LEFT JOIN LATERAL (
+            SELECT MAX(sl.performed_at) AS performed_at
+            FROM main.service_log sl
+            WHERE sl.tool_id = t.id AND (sl.schedule_id IS NULL OR sl.schedule_id = ms.id)
+        ) ls ON TRUE
+        LEFT JOIN LATERAL (
+            SELECT COUNT(*) AS count
+            FROM main.rentals mr
+            WHERE mr.tool_id = t.id AND mr.start_date >= COALESCE(ls.performed_at, ms.created_at)
+        ) rc ON TRUE
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR t.store_id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR t.id = ANY($2::integer[]))
+            AND t.status <> $4
+            AND (
+                rc.count >= ms.every_rentals
+                OR COALESCE(ls.performed_at, ms.created_at) + MAKE_INTERVAL(days => ms.every_days) <= $3
+            )
+        ORDER BY t.store_id, t.id, ms.id;
+        "#,
+        &store_ids,
+        &tool_ids,
+        at,
+        tool::ToolStatus::Retired as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
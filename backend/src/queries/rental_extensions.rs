@@ -0,0 +1,157 @@
+use crate::common;
+use crate::db_structs::{rental_extension, reservation, tool_hold};
+
+pub async fn select_statuses(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.extension_statuses es;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    rental_id: rental_extension::RentalId,
+    requested_by: rental_extension::RequestedBy,
+    previous_due_date: rental_extension::PreviousDueDate,
+    requested_due_date: rental_extension::RequestedDueDate,
+    note: Option<rental_extension::Note>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<rental_extension::RentalExtension, String> {
+    sqlx::query_as!(
+        rental_extension::RentalExtension,
+        r#"
+        INSERT INTO main.rental_extensions (rental_id, requested_by, previous_due_date, requested_due_date, note, status)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *;
+        "#,
+        rental_id,
+        requested_by,
+        previous_due_date,
+        requested_due_date,
+        note,
+        rental_extension::ExtensionStatus::Pending as i32,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_id(
+    id: rental_extension::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<rental_extension::RentalExtension>, String> {
+    sqlx::query_as!(
+        rental_extension::RentalExtension,
+        r#"
+        SELECT *
+        FROM main.rental_extensions
+        WHERE id = $1;
+        "#,
+        id,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_rental_id(
+    rental_id: rental_extension::RentalId,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<rental_extension::RentalExtension>, String> {
+    sqlx::query_as!(
+        rental_extension::RentalExtension,
+        r#"
+        SELECT *
+        FROM main.rental_extensions
+        WHERE rental_id = $1
+        ORDER BY created_at, id;
+        "#,
+        rental_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn decide(
+    id: rental_extension::Id,
+    status: rental_extension::Status,
+    decided_by: Option<rental_extension::DecidedBy>,
+    decision_note: Option<rental_extension::DecisionNote>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<rental_extension::RentalExtension>, String> {
+    sqlx::query_as!(
+        rental_extension::RentalExtension,
+        r#"
+        UPDATE main.rental_extensions re
+        SET
+            status = $2,
+            decided_by = $3,
+            decided_at = CURRENT_TIMESTAMP,
+            decision_note = $4
+        WHERE re.id = $1 AND re.status = $5
+        RETURNING *;
+        "#,
+        id,
+        status,
+        decided_by,
+        decision_note,
+        rental_extension::ExtensionStatus::Pending as i32,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// an extension is blocked by anyone else waiting in line for the tool, or by a booking that starts before the new due date
+pub async fn count_blockers(
+    tool_id: reservation::ToolId,
+    renter_id: reservation::UserId,
+    from: reservation::StartDate,
+    to: reservation::EndDate,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<(i64, i64), String> {
+    match sqlx::query!(
+        r#"
+        SELECT
+            (
+                SELECT COUNT(*)
+                FROM main.tool_holds th
+                WHERE th.tool_id = $1
+                    AND th.user_id != $2
+                    AND th.status = ANY($5::integer[])
+            ) AS "holds!",
+            (
+                SELECT COUNT(*)
+                FROM main.reservations r
+                WHERE r.tool_id = $1
+                    AND r.user_id != $2
+                    AND r.status = $6
+                    AND r.start_date < $4
+                    AND $3 < r.end_date
+            ) AS "reservations!";
+        "#,
+        tool_id,
+        renter_id,
+        from,
+        to,
+        &vec![
+            tool_hold::HoldStatus::Waiting as i32,
+            tool_hold::HoldStatus::Ready as i32,
+        ],
+        reservation::ReservationStatus::Active as i32,
+    )
+    .fetch_one(db)
+    .await
+    {
+        Ok(row) => Ok((row.holds, row.reservations)),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,143 @@
+use crate::auth::claims::Roles;
+use crate::db_structs::{rental, rental_reminder, store, tool, user};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct OverdueRental {
+    pub rental_id: rental::Id,
+    pub tool_id: tool::Id,
+    pub tool_real_id: tool::RealId,
+    pub store_id: store::Id,
+    pub renter_id: rental::RenterId,
+    pub start_date: rental::StartDate,
+    pub due_date: rental::DueDate,
+}
+
+pub async fn select_overdue(
+    now: time::OffsetDateTime,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<OverdueRental>, String> {
+    sqlx::query_as!(
+        OverdueRental,
+        r#"
+        SELECT
+            mr.id AS rental_id,
+            mr.tool_id,
+            t.real_id AS tool_real_id,
+            t.store_id,
+            mr.renter_id,
+            mr.start_date,
+            mr.due_date
+        FROM main.rentals mr
+        JOIN main.tools t ON mr.tool_id = t.id
+        WHERE mr.end_date IS NULL AND mr.due_date < $1
+        ORDER BY mr.due_date, mr.id;
+        "#,
+        now,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_rental_id(
+    rental_id: rental_reminder::RentalId,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<rental_reminder::RentalReminder>, String> {
+    sqlx::query_as!(
+        rental_reminder::RentalReminder,
+        r#"
+        SELECT *
+        FROM main.rental_reminders
+        WHERE rental_id = $1
+        ORDER BY created_at, id;
+        "#,
+        rental_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// returns false if this reminder was already recorded
+pub async fn insert(
+    rental_id: rental_reminder::RentalId,
+    kind: rental_reminder::Kind,
+    days_overdue: rental_reminder::DaysOverdue,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<bool, String> {
+    match sqlx::query!(
+        r#"
+        INSERT INTO main.rental_reminders (rental_id, kind, days_overdue)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING;
+        "#,
+        rental_id,
+        kind,
+        days_overdue,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected() > 0),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// escalations happen once per rental, even if the threshold is changed afterwards
+pub async fn insert_escalation(
+    rental_id: rental_reminder::RentalId,
+    kind: rental_reminder::Kind,
+    days_overdue: rental_reminder::DaysOverdue,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<bool, String> {
+    match sqlx::query!(
+        r#"
+        INSERT INTO main.rental_reminders (rental_id, kind, days_overdue)
+        SELECT $1, $2, $3
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM main.rental_reminders rr
+            WHERE rr.rental_id = $1 AND rr.kind = $2
+        )
+        ON CONFLICT DO NOTHING;
+        "#,
+        rental_id,
+        kind,
+        days_overdue,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected() > 0),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// someone to sign automatic grievances: a tool manager of the store if there is one, otherwise a user or library admin
+pub async fn select_grievance_author(
+    store_id: store::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<user::Id>, String> {
+    match sqlx::query!(
+        r#"
+        SELECT p.user_id
+        FROM main.permissions p
+        WHERE p.status = 1
+            AND ((p.role_id = $2 AND p.store_id = $1) OR p.role_id IN ($3, $4))
+        ORDER BY CASE p.role_id WHEN $2 THEN 1 WHEN $4 THEN 2 ELSE 3 END, p.id
+        LIMIT 1;
+        "#,
+        store_id,
+        Roles::ToolManager as i32,
+        Roles::LibraryAdmin as i32,
+        Roles::UserAdmin as i32,
+    )
+    .fetch_optional(db)
+    .await
+    {
+        Ok(row) => Ok(row.map(|r| r.user_id)),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -17,32 +17,106 @@ pub struct SelectParams {
     pub limit: i64,
 }
 
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OpenRentalCount {
+    pub store_id: tool::StoreId,
+    pub open_count: i64,
+    pub overdue_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OpenUnits {
+    pub tool_id: rental::ToolId,
+    pub units: i64,
+}
+
 #[derive(Debug, Serialize, Deserialize, sqlx::Type)]
 pub enum OrderBy {
     StartDate = 1,
     EndDate = 2,
 }
 
-pub async fn insert(
-    tool_id: rental::ToolId,
+// due_date defaults to start_date plus the tool's rental hours, cut short by any store policy;
+// quantities line up with tool_ids
+pub async fn insert_many(
+    tool_ids: Vec<rental::ToolId>,
+    quantities: Vec<rental::Quantity>,
     renter_id: rental::RenterId,
     start_date: rental::StartDate,
-    end_date: Option<rental::EndDate>,
-    db: &sqlx::Pool<sqlx::Postgres>,
-) -> Result<rental::Rental, String> {
+    due_date: Option<rental::DueDate>,
+    loan_id: Option<rental::LoanId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<rental::Rental>, String> {
     sqlx::query_as!(
         rental::Rental,
         r#"
-        INSERT INTO main.rentals (tool_id, renter_id, start_date, end_date)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO main.rentals (tool_id, renter_id, start_date, due_date, loan_id, quantity)
+        SELECT
+            t.id,
+            $2,
+            $3::timestamptz,
+            LEAST(
+                COALESCE($4, $3::timestamptz + interval '1' HOUR * t.rental_hours),
+                $3::timestamptz + interval '1' HOUR * sp.max_rental_hours
+            ),
+            $5,
+            u.quantity
+        FROM UNNEST($1::integer[], $6::integer[]) AS u(tool_id, quantity)
+        JOIN main.tools t ON u.tool_id = t.id
+        LEFT JOIN main.store_policies sp ON t.store_id = sp.store_id
         RETURNING *;
         "#,
-        tool_id,
+        &tool_ids,
         renter_id,
         start_date,
-        end_date
+        due_date,
+        loan_id,
+        &quantities,
     )
-    .fetch_one(db)
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn update_due_dates(
+    rental_ids: Vec<rental::Id>,
+    due_dates: Vec<rental::DueDate>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<rental::Rental>, String> {
+    sqlx::query_as!(
+        rental::Rental,
+        r#"
+        UPDATE main.rentals mr
+        SET due_date = u.due_date
+        FROM UNNEST($1::integer[], $2::timestamptz[]) AS u(id, due_date)
+        WHERE mr.id = u.id
+        RETURNING mr.*;
+        "#,
+        &rental_ids,
+        &due_dates,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn close_open(
+    rental_ids: Vec<rental::Id>,
+    end_date: rental::EndDate,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<rental::Rental>, String> {
+    sqlx::query_as!(
+        rental::Rental,
+        r#"
+        UPDATE main.rentals mr
+        SET end_date = $2
+        WHERE mr.id = ANY($1::integer[]) AND mr.end_date IS NULL
+        RETURNING *;
+        "#,
+        &rental_ids,
+        end_date,
+    )
+    .fetch_all(db)
     .await
     .map_err(|e| e.to_string())
 }
@@ -51,7 +125,8 @@ pub async fn update(
     id: rental::Id,
     start_date: Option<rental::StartDate>,
     end_date: Option<rental::EndDate>,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    due_date: Option<rental::DueDate>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<Option<rental::Rental>, String> {
     sqlx::query_as!(
         rental::Rental,
@@ -59,13 +134,15 @@ pub async fn update(
         UPDATE main.rentals mr
         SET
             start_date = COALESCE($2, mr.start_date),
-            end_date = COALESCE($3, mr.end_date)
+            end_date = COALESCE($3, mr.end_date),
+            due_date = COALESCE($4, mr.due_date)
         WHERE id = $1
         RETURNING *;
         "#,
         id,
         start_date,
         end_date,
+        due_date,
     )
     .fetch_optional(db)
     .await
@@ -75,7 +152,7 @@ pub async fn update(
 pub async fn clear_fields(
     id: rental::Id,
     end_date: bool,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<Option<rental::Rental>, String> {
     sqlx::query_as!(
         rental::Rental,
@@ -112,7 +189,7 @@ pub async fn select(
             AND (($6::timestamp with time zone IS NULL AND $7::timestamp with time zone IS NULL AND mr.end_date IS NULL)
                 OR (COALESCE($6, '1970-01-01 00:00:00+00'::timestamp with time zone) <= mr.end_date AND mr.end_date < COALESCE($7, '9999-12-31 23:59:59+00'::timestamp with time zone)))
             AND (mr.end_date IS NULL = $8::bool)
-            AND ($9::bool IS NULL OR ((mr.start_date + interval '1' HOUR * t.rental_hours) < CURRENT_TIMESTAMP) = $9::bool)
+            AND ($9::bool IS NULL OR (mr.due_date < CURRENT_TIMESTAMP) = $9::bool)
         ORDER BY (
             CASE $11::bool
                 WHEN TRUE THEN CASE $10::integer
@@ -153,7 +230,7 @@ pub async fn select(
 
 pub async fn select_by_id(
     id: rental::Id,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<Option<rental::Rental>, String> {
     sqlx::query_as!(
         rental::Rental,
@@ -168,3 +245,127 @@ pub async fn select_by_id(
     .await
     .map_err(|e| e.to_string())
 }
+
+pub async fn select_by_loan_id(
+    loan_id: rental::LoanId,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<rental::Rental>, String> {
+    sqlx::query_as!(
+        rental::Rental,
+        r#"
+        SELECT *
+        FROM main.rentals
+        WHERE loan_id = $1
+        ORDER BY id;
+        "#,
+        loan_id
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// open rentals of the given tools, and the given rentals if they're still open
+pub async fn select_open(
+    tool_ids: Vec<rental::ToolId>,
+    rental_ids: Vec<rental::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<rental::Rental>, String> {
+    sqlx::query_as!(
+        rental::Rental,
+        r#"
+        SELECT *
+        FROM main.rentals mr
+        WHERE (mr.tool_id = ANY($1::integer[]) OR mr.id = ANY($2::integer[]))
+            AND mr.end_date IS NULL
+        ORDER BY mr.id;
+        "#,
+        &tool_ids,
+        &rental_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// units out on open rentals, per tool; tools with nothing out are left out
+pub async fn select_open_units(
+    tool_ids: Vec<rental::ToolId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<OpenUnits>, String> {
+    sqlx::query_as!(
+        OpenUnits,
+        r#"
+        SELECT mr.tool_id, SUM(mr.quantity)::bigint AS "units!"
+        FROM main.rentals mr
+        WHERE mr.tool_id = ANY($1::integer[]) AND mr.end_date IS NULL
+        GROUP BY mr.tool_id;
+        "#,
+        &tool_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// every unit still out on the rental is written off as lost
+pub async fn mark_units_lost(
+    id: rental::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<rental::Rental>, String> {
+    sqlx::query_as!(
+        rental::Rental,
+        r#"
+        UPDATE main.rentals mr
+        SET lost_quantity = mr.quantity
+        WHERE mr.id = $1 AND mr.end_date IS NULL
+        RETURNING *;
+        "#,
+        id,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_open_lost_units(
+    tool_id: rental::ToolId,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<i64, String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(SUM(mr.lost_quantity), 0)::bigint AS "units!"
+        FROM main.rentals mr
+        WHERE mr.tool_id = $1 AND mr.end_date IS NULL;
+        "#,
+        tool_id,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_open_counts(
+    renter_id: rental::RenterId,
+    now: time::OffsetDateTime,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<OpenRentalCount>, String> {
+    sqlx::query_as!(
+        OpenRentalCount,
+        r#"
+        SELECT
+            t.store_id,
+            SUM(mr.quantity)::bigint AS "open_count!",
+            COUNT(*) FILTER (WHERE mr.due_date < $2) AS "overdue_count!"
+        FROM main.rentals mr
+        JOIN main.tools t ON mr.tool_id = t.id
+        WHERE mr.renter_id = $1 AND mr.end_date IS NULL
+        GROUP BY t.store_id;
+        "#,
+        renter_id,
+        now,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,263 @@
+use crate::common;
+use crate::db_structs::{repair_ticket, repair_ticket_photo, store, tool, user};
+use crate::queries::tool_photos::TakenPhoto;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelectParams {
+    pub ids: Vec<repair_ticket::Id>,
+    pub tool_ids: Vec<repair_ticket::ToolId>,
+    pub store_ids: Vec<store::Id>,
+    pub reporter_ids: Vec<repair_ticket::ReporterId>,
+    pub statuses: Vec<repair_ticket::Status>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+// every field is written as given, so read the ticket first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateData {
+    pub id: repair_ticket::Id,
+    pub status: repair_ticket::Status,
+    pub priority: Option<repair_ticket::Priority>,
+    pub assigned_to: Option<repair_ticket::AssignedTo>,
+    pub resolution: Option<repair_ticket::Resolution>,
+    pub updated_at: repair_ticket::UpdatedAt,
+    pub closed_at: Option<repair_ticket::ClosedAt>,
+    pub closed_by: Option<repair_ticket::ClosedBy>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct TicketWithText {
+    pub id: repair_ticket::Id,
+    pub tool_id: repair_ticket::ToolId,
+    pub tool_real_id: tool::RealId,
+    pub tool_short_description: tool::ShortDescription,
+    pub store_id: store::Id,
+    pub reporter_id: repair_ticket::ReporterId,
+    pub reporter_username: user::Username,
+    pub description: repair_ticket::Description,
+    pub status: repair_ticket::Status,
+    pub priority: Option<repair_ticket::Priority>,
+    pub assigned_to: Option<repair_ticket::AssignedTo>,
+    pub assigned_to_username: Option<user::Username>,
+    pub resolution: Option<repair_ticket::Resolution>,
+    pub created_at: repair_ticket::CreatedAt,
+    pub updated_at: repair_ticket::UpdatedAt,
+    pub closed_at: Option<repair_ticket::ClosedAt>,
+    pub closed_by: Option<repair_ticket::ClosedBy>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct OpenTicketCount {
+    pub tool_id: tool::Id,
+    pub count: i64,
+}
+
+pub async fn select_statuses(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.repair_ticket_statuses;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_priorities(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.repair_ticket_priorities;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    tool_id: repair_ticket::ToolId,
+    reporter_id: repair_ticket::ReporterId,
+    description: repair_ticket::Description,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<repair_ticket::RepairTicket, String> {
+    sqlx::query_as!(
+        repair_ticket::RepairTicket,
+        r#"
+        INSERT INTO main.repair_tickets (tool_id, reporter_id, description, status)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *;
+        "#,
+        tool_id,
+        reporter_id,
+        description,
+        repair_ticket::RepairTicketStatus::Open as i32,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// same as condition reports, uploaded photos not yet on a tool move over to the ticket
+pub async fn insert_photos(
+    ticket_id: repair_ticket_photo::TicketId,
+    photos: Vec<TakenPhoto>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<repair_ticket_photo::RepairTicketPhoto>, String> {
+    let (photo_keys, original_names): (Vec<String>, Vec<String>) = photos
+        .into_iter()
+        .map(|p| (p.photo_key, p.original_name))
+        .unzip();
+    sqlx::query_as!(
+        repair_ticket_photo::RepairTicketPhoto,
+        r#"
+        INSERT INTO main.repair_ticket_photos (ticket_id, photo_key, original_name)
+        SELECT $1, * FROM UNNEST($2::text[], $3::text[])
+        RETURNING *;
+        "#,
+        ticket_id,
+        &photo_keys,
+        &original_names,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn update(
+    data: UpdateData,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<repair_ticket::RepairTicket>, String> {
+    sqlx::query_as!(
+        repair_ticket::RepairTicket,
+        r#"
+        UPDATE main.repair_tickets
+        SET
+            status = $2,
+            priority = $3,
+            assigned_to = $4,
+            resolution = $5,
+            updated_at = $6,
+            closed_at = $7,
+            closed_by = $8
+        WHERE id = $1
+        RETURNING *;
+        "#,
+        data.id,
+        data.status,
+        data.priority,
+        data.assigned_to,
+        data.resolution,
+        data.updated_at,
+        data.closed_at,
+        data.closed_by,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// newest first
+pub async fn select(
+    params: SelectParams,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<TicketWithText>, String> {
+    sqlx::query_as!(
+        TicketWithText,
+        r#"
+        SELECT
+            rt.id,
+            rt.tool_id,
+            t.real_id AS tool_real_id,
+            t.short_description AS tool_short_description,
+            t.store_id,
+            rt.reporter_id,
+            r.username AS reporter_username,
+            rt.description,
+            rt.status,
+            rt.priority,
+            rt.assigned_to,
+            a.username AS "assigned_to_username?",
+            rt.resolution,
+            rt.created_at,
+            rt.updated_at,
+            rt.closed_at,
+            rt.closed_by
+        FROM main.repair_tickets rt
+        JOIN main.tools t ON rt.tool_id = t.id
+        JOIN main.users r ON rt.reporter_id = r.id
+        LEFT JOIN main.users a ON rt.assigned_to = a.id
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR rt.id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR rt.tool_id = ANY($2::integer[]))
+            AND (ARRAY_LENGTH($3::integer[], 1) IS NULL OR t.store_id = ANY($3::integer[]))
+            AND (ARRAY_LENGTH($4::integer[], 1) IS NULL OR rt.reporter_id = ANY($4::integer[]))
+            AND (ARRAY_LENGTH($5::integer[], 1) IS NULL OR rt.status = ANY($5::integer[]))
+        ORDER BY rt.created_at DESC, rt.id DESC
+        OFFSET $6 LIMIT $7;
+        "#,
+        &params.ids,
+        &params.tool_ids,
+        &params.store_ids,
+        &params.reporter_ids,
+        &params.statuses,
+        params.offset,
+        params.limit,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_photos(
+    ticket_ids: Vec<repair_ticket_photo::TicketId>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<repair_ticket_photo::RepairTicketPhoto>, String> {
+    sqlx::query_as!(
+        repair_ticket_photo::RepairTicketPhoto,
+        r#"
+        SELECT *
+        FROM main.repair_ticket_photos rtp
+        WHERE rtp.ticket_id = ANY($1::integer[])
+        ORDER BY rtp.id;
+        "#,
+        &ticket_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// open and triaged tickets, per tool; tools without any are left out
+pub async fn select_open_counts(
+    tool_ids: Vec<tool::Id>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<OpenTicketCount>, String> {
+    sqlx::query_as!(
+        OpenTicketCount,
+        r#"
+        SELECT rt.tool_id, COUNT(*) AS "count!"
+        FROM main.repair_tickets rt
+        WHERE rt.tool_id = ANY($1::integer[]) AND rt.status = ANY($2::integer[])
+        GROUP BY rt.tool_id;
+        "#,
+        &tool_ids,
+        &[
+            repair_ticket::RepairTicketStatus::Open as i32,
+            repair_ticket::RepairTicketStatus::Triaged as i32,
+        ],
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,320 @@
+use crate::common;
+use crate::db_structs::{reservation, store, tool, user};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ReservationWithText {
+    pub id: reservation::Id,
+    pub tool_id: reservation::ToolId,
+    pub tool_real_id: tool::RealId,
+    pub tool_short_description: tool::ShortDescription,
+    pub store_id: store::Id,
+    pub user_id: reservation::UserId,
+    pub username: user::Username,
+    pub start_date: reservation::StartDate,
+    pub end_date: reservation::EndDate,
+    pub status: reservation::Status,
+    pub created_at: reservation::CreatedAt,
+    pub rental_id: Option<reservation::RentalId>,
+    pub quantity: reservation::Quantity,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelectParams {
+    pub tool_ids: Vec<reservation::ToolId>,
+    pub user_ids: Vec<reservation::UserId>,
+    pub store_ids: Vec<tool::StoreId>,
+    pub statuses: Vec<reservation::Status>,
+    pub overlapping: common::DateBetween,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+pub async fn select_statuses(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.reservation_statuses rs;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    tool_id: reservation::ToolId,
+    user_id: reservation::UserId,
+    start_date: reservation::StartDate,
+    end_date: reservation::EndDate,
+    status: reservation::Status,
+    quantity: reservation::Quantity,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<reservation::Reservation, String> {
+    sqlx::query_as!(
+        reservation::Reservation,
+        r#"
+        INSERT INTO main.reservations (tool_id, user_id, start_date, end_date, status, quantity)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING *;
+        "#,
+        tool_id,
+        user_id,
+        start_date,
+        end_date,
+        status,
+        quantity,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_id(
+    id: reservation::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<ReservationWithText>, String> {
+    sqlx::query_as!(
+        ReservationWithText,
+        r#"
+        SELECT
+            r.id,
+            r.tool_id,
+            t.real_id AS tool_real_id,
+            t.short_description AS tool_short_description,
+            t.store_id,
+            r.user_id,
+            u.username,
+            r.start_date,
+            r.end_date,
+            r.status,
+            r.created_at,
+            r.rental_id,
+            r.quantity
+        FROM main.reservations r
+        JOIN main.tools t ON r.tool_id = t.id
+        JOIN main.users u ON r.user_id = u.id
+        WHERE r.id = $1;
+        "#,
+        id,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select(
+    params: SelectParams,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<ReservationWithText>, String> {
+    sqlx::query_as!(
+        ReservationWithText,
+        r#"
+        SELECT
+            r.id,
+            r.tool_id,
+            t.real_id AS tool_real_id,
+            t.short_description AS tool_short_description,
+            t.store_id,
+            r.user_id,
+            u.username,
+            r.start_date,
+            r.end_date,
+            r.status,
+            r.created_at,
+            r.rental_id,
+            r.quantity
+        FROM main.reservations r
+        JOIN main.tools t ON r.tool_id = t.id
+        JOIN main.users u ON r.user_id = u.id
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR r.tool_id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR r.user_id = ANY($2::integer[]))
+            AND (ARRAY_LENGTH($3::integer[], 1) IS NULL OR t.store_id = ANY($3::integer[]))
+            AND (ARRAY_LENGTH($4::integer[], 1) IS NULL OR r.status = ANY($4::integer[]))
+            AND r.start_date < COALESCE($6, '9999-12-31 23:59:59+00'::timestamp with time zone)
+            AND COALESCE($5, '1970-01-01 00:00:00+00'::timestamp with time zone) < r.end_date
+        ORDER BY r.start_date, r.id
+        OFFSET $7 LIMIT $8;
+        "#,
+        &params.tool_ids, // 1
+        &params.user_ids,
+        &params.store_ids,
+        &params.statuses,
+        params.overlapping.start, // 5
+        params.overlapping.end,
+        params.offset,
+        params.limit,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// units held by active reservations anywhere in the window
+pub async fn select_overlapping_units(
+    tool_id: reservation::ToolId,
+    start_date: reservation::StartDate,
+    end_date: reservation::EndDate,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<i64, String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(SUM(r.quantity), 0)::bigint AS "units!"
+        FROM main.reservations r
+        WHERE r.tool_id = $1
+            AND r.status = 1
+            AND r.start_date < $3
+            AND $2 < r.end_date;
+        "#,
+        tool_id,
+        start_date,
+        end_date,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// units out on open rentals in the window; open rentals are judged by their due date, not by when they actually come back
+pub async fn select_overlapping_rental_units(
+    tool_id: reservation::ToolId,
+    start_date: reservation::StartDate,
+    end_date: reservation::EndDate,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<i64, String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(SUM(mr.quantity), 0)::bigint AS "units!"
+        FROM main.rentals mr
+        WHERE mr.tool_id = $1
+            AND mr.end_date IS NULL
+            AND mr.start_date < $3
+            AND $2 < mr.due_date;
+        "#,
+        tool_id,
+        start_date,
+        end_date,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_current(
+    tool_ids: Vec<reservation::ToolId>,
+    now: time::OffsetDateTime,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<ReservationWithText>, String> {
+    sqlx::query_as!(
+        ReservationWithText,
+        r#"
+        SELECT
+            r.id,
+            r.tool_id,
+            t.real_id AS tool_real_id,
+            t.short_description AS tool_short_description,
+            t.store_id,
+            r.user_id,
+            u.username,
+            r.start_date,
+            r.end_date,
+            r.status,
+            r.created_at,
+            r.rental_id,
+            r.quantity
+        FROM main.reservations r
+        JOIN main.tools t ON r.tool_id = t.id
+        JOIN main.users u ON r.user_id = u.id
+        WHERE r.tool_id = ANY($1::integer[])
+            AND r.status = 1
+            AND r.start_date <= $2
+            AND $2 < r.end_date;
+        "#,
+        &tool_ids,
+        now,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn update_status(
+    id: reservation::Id,
+    status: reservation::Status,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Option<reservation::Reservation>, String> {
+    sqlx::query_as!(
+        reservation::Reservation,
+        r#"
+        UPDATE main.reservations r
+        SET status = $2
+        WHERE r.id = $1
+        RETURNING *;
+        "#,
+        id,
+        status,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// links each current reservation to the open rental that was just created for it
+pub async fn fulfill_current(
+    tool_ids: Vec<reservation::ToolId>,
+    user_id: reservation::UserId,
+    now: time::OffsetDateTime,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<reservation::Reservation>, String> {
+    sqlx::query_as!(
+        reservation::Reservation,
+        r#"
+        UPDATE main.reservations r
+        SET status = $4, rental_id = mr.id
+        FROM main.rentals mr
+        WHERE mr.tool_id = r.tool_id
+            AND mr.end_date IS NULL
+            AND mr.renter_id = r.user_id
+            AND r.tool_id = ANY($1::integer[])
+            AND r.user_id = $2
+            AND r.status = 1
+            AND r.start_date <= $3
+            AND $3 < r.end_date
+        RETURNING r.*;
+        "#,
+        &tool_ids,
+        user_id,
+        now,
+        reservation::ReservationStatus::Fulfilled as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// cancels every reservation still to come for the tools, e.g. when they leave the store
+pub async fn cancel_active(
+    tool_ids: Vec<reservation::ToolId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<reservation::Reservation>, String> {
+    sqlx::query_as!(
+        reservation::Reservation,
+        r#"
+        UPDATE main.reservations r
+        SET status = $2
+        WHERE r.tool_id = ANY($1::integer[])
+            AND r.status = 1
+        RETURNING *;
+        "#,
+        &tool_ids,
+        reservation::ReservationStatus::Cancelled as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
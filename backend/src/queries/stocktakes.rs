@@ -0,0 +1,245 @@
+use crate::common;
+use crate::db_structs::{stocktake, stocktake_discrepancy, stocktake_scan, store, tool};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelectParams {
+    pub ids: Vec<stocktake::Id>,
+    pub store_ids: Vec<store::Id>,
+    pub statuses: Vec<stocktake::Status>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+pub async fn select_statuses(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.stocktake_statuses;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// a store has at most one open stocktake, so this finds nothing if another is already open
+pub async fn insert(
+    store_id: stocktake::StoreId,
+    opened_by: stocktake::OpenedBy,
+    note: Option<stocktake::Note>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<stocktake::Stocktake>, String> {
+    sqlx::query_as!(
+        stocktake::Stocktake,
+        r#"
+        INSERT INTO main.stocktakes (store_id, opened_by, note, status)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (store_id) WHERE status = 1 DO NOTHING
+        RETURNING *;
+        "#,
+        store_id,
+        opened_by,
+        note,
+        stocktake::StocktakeStatus::Open as i32,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// newest first
+pub async fn select(
+    params: SelectParams,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<stocktake::Stocktake>, String> {
+    sqlx::query_as!(
+        stocktake::Stocktake,
+        r#"
+        SELECT st.*
+        FROM main.stocktakes st
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR st.id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR st.store_id = ANY($2::integer[]))
+            AND (ARRAY_LENGTH($3::integer[], 1) IS NULL OR st.status = ANY($3::integer[]))
+        ORDER BY st.opened_at DESC, st.id DESC
+        OFFSET $4 LIMIT $5;
+        "#,
+        &params.ids,
+        &params.store_ids,
+        &params.statuses,
+        params.offset,
+        params.limit,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_for_update(
+    id: stocktake::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<stocktake::Stocktake>, String> {
+    sqlx::query_as!(
+        stocktake::Stocktake,
+        r#"
+        SELECT *
+        FROM main.stocktakes
+        WHERE id = $1
+        FOR UPDATE;
+        "#,
+        id,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// scanning the same tool twice keeps the first scan
+pub async fn insert_scans(
+    stocktake_id: stocktake_scan::StocktakeId,
+    tool_ids: Vec<tool::Id>,
+    scanned_by: stocktake_scan::ScannedBy,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<stocktake_scan::StocktakeScan>, String> {
+    sqlx::query_as!(
+        stocktake_scan::StocktakeScan,
+        r#"
+        INSERT INTO main.stocktake_scans (stocktake_id, tool_id, scanned_by)
+        SELECT $1, tool_id, $3 FROM UNNEST($2::integer[]) AS tool_id
+        ON CONFLICT (stocktake_id, tool_id) DO NOTHING
+        RETURNING *;
+        "#,
+        stocktake_id,
+        &tool_ids,
+        scanned_by,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn count_scans(
+    stocktake_id: stocktake::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<i64, String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM main.stocktake_scans
+        WHERE stocktake_id = $1;
+        "#,
+        stocktake_id,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// What the stocktake would find if it closed now: available tools in the store that nobody
+// scanned, and scanned tools that aren't available.
+pub async fn select_live_discrepancies(
+    stocktake_id: stocktake::Id,
+    store_id: store::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<stocktake_discrepancy::StocktakeDiscrepancy>, String> {
+    sqlx::query_as!(
+        stocktake_discrepancy::StocktakeDiscrepancy,
+        r#"
+        SELECT
+            $1::integer AS "stocktake_id!",
+            t.id AS tool_id,
+            (ss.tool_id IS NOT NULL) AS "scanned!",
+            t.status AS tool_status
+        FROM main.tools t
+        LEFT JOIN main.stocktake_scans ss ON ss.stocktake_id = $1 AND ss.tool_id = t.id
+        WHERE
+            (t.store_id = $2 AND t.status = $3 AND ss.tool_id IS NULL)
+            OR (ss.tool_id IS NOT NULL AND t.status <> $3)
+        ORDER BY t.id;
+        "#,
+        stocktake_id,
+        store_id,
+        tool::ToolStatus::Available as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// keeps what the live comparison finds at this moment, so the result doesn't drift afterwards
+pub async fn insert_discrepancies(
+    stocktake_id: stocktake::Id,
+    store_id: store::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<stocktake_discrepancy::StocktakeDiscrepancy>, String> {
+    sqlx::query_as!(
+        stocktake_discrepancy::StocktakeDiscrepancy,
+        r#"
+        INSERT INTO main.stocktake_discrepancies (stocktake_id, tool_id, scanned, tool_status)
+        SELECT $1, t.id, (ss.tool_id IS NOT NULL), t.status
+        FROM main.tools t
+        LEFT JOIN main.stocktake_scans ss ON ss.stocktake_id = $1 AND ss.tool_id = t.id
+        WHERE
+            (t.store_id = $2 AND t.status = $3 AND ss.tool_id IS NULL)
+            OR (ss.tool_id IS NOT NULL AND t.status <> $3)
+        RETURNING *;
+        "#,
+        stocktake_id,
+        store_id,
+        tool::ToolStatus::Available as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_discrepancies(
+    stocktake_id: stocktake::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<stocktake_discrepancy::StocktakeDiscrepancy>, String> {
+    sqlx::query_as!(
+        stocktake_discrepancy::StocktakeDiscrepancy,
+        r#"
+        SELECT *
+        FROM main.stocktake_discrepancies
+        WHERE stocktake_id = $1
+        ORDER BY tool_id;
+        "#,
+        stocktake_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// closing or cancelling; only an open stocktake can end
+pub async fn end(
+    id: stocktake::Id,
+    status: stocktake::Status,
+    closed_by: stocktake::ClosedBy,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<stocktake::Stocktake>, String> {
+    sqlx::query_as!(
+        stocktake::Stocktake,
+        r#"
+        UPDATE main.stocktakes st
+        SET
+            status = $2,
+            closed_by = $3,
+            closed_at = CURRENT_TIMESTAMP
+        WHERE st.id = $1 AND st.status = $4
+        RETURNING *;
+        "#,
+        id,
+        status,
+        closed_by,
+        stocktake::StocktakeStatus::Open as i32,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -0,0 +1,130 @@
+use crate::db_structs::{store, store_holiday, store_hours};
+
+pub async fn select_by_store_ids(
+    store_ids: Vec<store::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<store_hours::StoreHours>, String> {
+    sqlx::query_as!(
+        store_hours::StoreHours,
+        r#"
+        SELECT *
+        FROM main.store_hours
+        WHERE store_id = ANY($1::integer[])
+        ORDER BY store_id, weekday, opens_at;
+        "#,
+        &store_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn delete_by_store_id(
+    store_id: store::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        DELETE FROM main.store_hours
+        WHERE store_id = $1;
+        "#,
+        store_id,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn insert_many(
+    store_id: store::Id,
+    weekdays: Vec<store_hours::Weekday>,
+    opens_at: Vec<store_hours::OpensAt>,
+    closes_at: Vec<store_hours::ClosesAt>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<store_hours::StoreHours>, String> {
+    sqlx::query_as!(
+        store_hours::StoreHours,
+        r#"
+        INSERT INTO main.store_hours (store_id, weekday, opens_at, closes_at)
+        SELECT $1, * FROM UNNEST($2::integer[], $3::time[], $4::time[])
+        RETURNING *;
+        "#,
+        store_id,
+        &weekdays,
+        &opens_at,
+        &closes_at,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// only holidays that have not finished before the given day
+pub async fn select_holidays(
+    store_ids: Vec<store::Id>,
+    from: store_holiday::EndsOn,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<store_holiday::StoreHoliday>, String> {
+    sqlx::query_as!(
+        store_holiday::StoreHoliday,
+        r#"
+        SELECT *
+        FROM main.store_holidays
+        WHERE store_id = ANY($1::integer[]) AND ends_on >= $2
+        ORDER BY store_id, starts_on;
+        "#,
+        &store_ids,
+        from,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert_holiday(
+    store_id: store::Id,
+    starts_on: store_holiday::StartsOn,
+    ends_on: store_holiday::EndsOn,
+    name: Option<store_holiday::Name>,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<store_holiday::StoreHoliday, String> {
+    sqlx::query_as!(
+        store_holiday::StoreHoliday,
+        r#"
+        INSERT INTO main.store_holidays (store_id, starts_on, ends_on, name)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *;
+        "#,
+        store_id,
+        starts_on,
+        ends_on,
+        name,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn delete_holiday(
+    holiday_id: store_holiday::Id,
+    store_id: store::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        DELETE FROM main.store_holidays
+        WHERE id = $1 AND store_id = $2;
+        "#,
+        holiday_id,
+        store_id,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
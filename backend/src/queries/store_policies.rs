@@ -0,0 +1,50 @@
+use crate::db_structs::{store, store_policy};
+
+pub async fn select_by_store_ids(
+    store_ids: Vec<store::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<store_policy::StorePolicy>, String> {
+    sqlx::query_as!(
+        store_policy::StorePolicy,
+        r#"
+        SELECT *
+        FROM main.store_policies
+        WHERE store_id = ANY($1::integer[]);
+        "#,
+        &store_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn upsert(
+    policy: store_policy::StorePolicy,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<store_policy::StorePolicy, String> {
+    sqlx::query_as!(
+        store_policy::StorePolicy,
+        r#"
+        INSERT INTO main.store_policies (store_id, max_open_rentals, max_tools_per_check_out, min_account_age_days, block_overdue, max_rental_hours, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (store_id) DO UPDATE SET
+            max_open_rentals = EXCLUDED.max_open_rentals,
+            max_tools_per_check_out = EXCLUDED.max_tools_per_check_out,
+            min_account_age_days = EXCLUDED.min_account_age_days,
+            block_overdue = EXCLUDED.block_overdue,
+            max_rental_hours = EXCLUDED.max_rental_hours,
+            updated_at = EXCLUDED.updated_at
+        RETURNING *;
+        "#,
+        policy.store_id,
+        policy.max_open_rentals,
+        policy.max_tools_per_check_out,
+        policy.min_account_age_days,
+        policy.block_overdue,
+        policy.max_rental_hours,
+        policy.updated_at,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
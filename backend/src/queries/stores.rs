@@ -65,7 +65,7 @@ pub async fn select_no_contact(
     sqlx::query_as!(
         store::Store,
         r#"
-        SELECT ms.id, ms.name, ms.status, '' AS "location!: _", '' AS "email_address!: _", '' AS "phone_number!: _", ms.rental_information, ms.other_information, '' AS "code!: _", ms.created_at
+        SELECT ms.id, ms.name, ms.status, '' AS "location!: _", '' AS "email_address!: _", '' AS "phone_number!: _", ms.rental_information, ms.other_information, '' AS "code!: _", ms.created_at, ms.timezone
         FROM main.stores ms
         LEFT JOIN main.permissions p ON ms.id = p.store_id AND p.status = 1
         WHERE
@@ -109,7 +109,7 @@ pub async fn select_by_code(
 
 pub async fn select_by_ids(
     store_ids: Vec<store::Id>,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<Vec<store::Store>, String> {
     sqlx::query_as!(
         store::Store,
@@ -125,6 +125,29 @@ pub async fn select_by_ids(
     .map_err(|e| e.to_string())
 }
 
+// by id, every store after after_id, for going through them all in batches
+pub async fn select_after(
+    after_id: store::Id,
+    limit: i64,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<store::Store>, String> {
+    sqlx::query_as!(
+        store::Store,
+        r#"
+        SELECT *
+        FROM main.stores ms
+        WHERE ms.id > $1
+        ORDER BY ms.id
+        LIMIT $2;
+        "#,
+        after_id,
+        limit,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
 pub async fn insert(
     name: store::Name,
     status: store::Status,
@@ -196,3 +219,24 @@ pub async fn update(
     .await
     .map_err(|e| e.to_string())
 }
+
+pub async fn update_timezone(
+    store_id: store::Id,
+    timezone: store::Timezone,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<store::Store>, String> {
+    sqlx::query_as!(
+        store::Store,
+        r#"
+        UPDATE main.stores
+        SET timezone = $2
+        WHERE id = $1
+        RETURNING *;
+        "#,
+        store_id,
+        timezone,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
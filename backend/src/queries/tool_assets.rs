@@ -0,0 +1,128 @@
+use crate::common;
+use crate::db_structs::{store, tool, tool_asset, user};
+use serde::{Deserialize, Serialize};
+
+pub async fn select_acquisition_sources(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.tool_acquisition_sources;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_tool_ids(
+    tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_asset::ToolAsset>, String> {
+    sqlx::query_as!(
+        tool_asset::ToolAsset,
+        r#"
+        SELECT *
+        FROM main.tool_assets
+        WHERE tool_id = ANY($1::integer[]);
+        "#,
+        &tool_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn upsert(
+    asset: tool_asset::ToolAsset,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<tool_asset::ToolAsset, String> {
+    sqlx::query_as!(
+        tool_asset::ToolAsset,
+        r#"
+        INSERT INTO main.tool_assets (tool_id, brand, model, serial_number, acquired_on, acquisition_source, donated_by, replacement_value, warranty_expires_on, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (tool_id) DO UPDATE SET
+            brand = EXCLUDED.brand,
+            model = EXCLUDED.model,
+            serial_number = EXCLUDED.serial_number,
+            acquired_on = EXCLUDED.acquired_on,
+            acquisition_source = EXCLUDED.acquisition_source,
+            donated_by = EXCLUDED.donated_by,
+            replacement_value = EXCLUDED.replacement_value,
+            warranty_expires_on = EXCLUDED.warranty_expires_on,
+            updated_at = EXCLUDED.updated_at
+        RETURNING *;
+        "#,
+        asset.tool_id,
+        asset.brand,
+        asset.model,
+        asset.serial_number,
+        asset.acquired_on,
+        asset.acquisition_source,
+        asset.donated_by,
+        asset.replacement_value,
+        asset.warranty_expires_on,
+        asset.updated_at,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterRow {
+    pub tool_id: tool::Id,
+    pub real_id: tool::RealId,
+    pub short_description: tool::ShortDescription,
+    pub status: tool::Status,
+    pub quantity: tool::Quantity,
+    pub brand: Option<tool_asset::Brand>,
+    pub model: Option<tool_asset::Model>,
+    pub serial_number: Option<tool_asset::SerialNumber>,
+    pub acquired_on: Option<tool_asset::AcquiredOn>,
+    pub acquisition_source: Option<tool_asset::AcquisitionSource>,
+    pub donated_by: Option<tool_asset::DonatedBy>,
+    pub donated_by_username: Option<user::Username>,
+    pub replacement_value: Option<tool_asset::ReplacementValue>,
+    pub warranty_expires_on: Option<tool_asset::WarrantyExpiresOn>,
+}
+
+// every tool in the store, whether or not it has asset details yet
+pub async fn select_register(
+    store_id: store::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<RegisterRow>, String> {
+    sqlx::query_as!(
+        RegisterRow,
+        r#"
+        SELECT
+            t.id AS tool_id,
+            t.real_id,
+            t.short_description,
+            t.status,
+            t.quantity,
+            ta.brand AS "brand?",
+            ta.model AS "model?",
+            ta.serial_number AS "serial_number?",
+            ta.acquired_on AS "acquired_on?",
+            ta.acquisition_source AS "acquisition_source?",
+            ta.donated_by AS "donated_by?",
+            u.username AS "donated_by_username?",
+            ta.replacement_value AS "replacement_value?",
+            ta.warranty_expires_on AS "warranty_expires_on?"
+        FROM main.tools t
+        LEFT JOIN main.tool_assets ta ON ta.tool_id = t.id
+        LEFT JOIN main.users u ON u.id = ta.donated_by
+        WHERE t.store_id = $1
+        ORDER BY t.status, t.real_id, t.id;
+        "#,
+        store_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -86,4 +86,33 @@ pub async fn select (
     .fetch_all(db)
     .await
     .map_err(|e| e.to_string())
-}
\ No newline at end of file
+}
+
+// categories whose name or one of whose synonyms is any of the given names, ignoring case
+pub async fn select_by_names(
+    names: Vec<String>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_category::ToolCategory>, String> {
+    sqlx::query_as!(
+        tool_category::ToolCategory,
+        r#"
+        SELECT tc.*
+        FROM main.tool_categories tc
+        WHERE
+            LOWER(tc.name) = ANY($1::text[])
+            OR EXISTS (
+                SELECT 1
+                FROM UNNEST(tc.synonyms) s
+                WHERE LOWER(s) = ANY($1::text[])
+            )
+        ORDER BY tc.id;
+        "#,
+        &names
+            .iter()
+            .map(|n| n.to_lowercase())
+            .collect::<Vec<String>>(),
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
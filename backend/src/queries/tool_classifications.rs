@@ -2,7 +2,7 @@ use crate::db_structs::tool_classification;
 
 pub async fn insert(
     data: Vec<tool_classification::ToolClassification>,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<(), String> {
     if data.is_empty() {
         return Ok(());
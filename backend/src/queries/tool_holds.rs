@@ -0,0 +1,271 @@
+use crate::common;
+use crate::db_structs::{store, tool, tool_hold, user};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct HoldWithText {
+    pub id: tool_hold::Id,
+    pub tool_id: tool_hold::ToolId,
+    pub tool_real_id: tool::RealId,
+    pub tool_short_description: tool::ShortDescription,
+    pub store_id: store::Id,
+    pub user_id: tool_hold::UserId,
+    pub username: user::Username,
+    pub position: tool_hold::Position,
+    pub status: tool_hold::Status,
+    pub created_at: tool_hold::CreatedAt,
+    pub ready_at: Option<tool_hold::ReadyAt>,
+    pub expires_at: Option<tool_hold::ExpiresAt>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelectParams {
+    pub tool_ids: Vec<tool_hold::ToolId>,
+    pub user_ids: Vec<tool_hold::UserId>,
+    pub statuses: Vec<tool_hold::Status>,
+}
+
+pub async fn select_statuses(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.hold_statuses hs;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    tool_id: tool_hold::ToolId,
+    user_id: tool_hold::UserId,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<tool_hold::ToolHold, String> {
+    sqlx::query_as!(
+        tool_hold::ToolHold,
+        r#"
+        INSERT INTO main.tool_holds (tool_id, user_id, position, status)
+        SELECT $1, $2, COALESCE(MAX(th.position), 0) + 1, 1
+        FROM main.tool_holds th
+        WHERE th.tool_id = $1 AND th.status IN (1, 2)
+        RETURNING *;
+        "#,
+        tool_id,
+        user_id,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select(
+    params: SelectParams,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<HoldWithText>, String> {
+    sqlx::query_as!(
+        HoldWithText,
+        r#"
+        SELECT
+            th.id,
+            th.tool_id,
+            t.real_id AS tool_real_id,
+            t.short_description AS tool_short_description,
+            t.store_id,
+            th.user_id,
+            u.username,
+            th.position,
+            th.status,
+            th.created_at,
+            th.ready_at,
+            th.expires_at
+        FROM main.tool_holds th
+        JOIN main.tools t ON th.tool_id = t.id
+        JOIN main.users u ON th.user_id = u.id
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR th.tool_id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR th.user_id = ANY($2::integer[]))
+            AND (ARRAY_LENGTH($3::integer[], 1) IS NULL OR th.status = ANY($3::integer[]))
+        ORDER BY th.tool_id, th.position, th.id
+        LIMIT 1000;
+        "#,
+        &params.tool_ids,
+        &params.user_ids,
+        &params.statuses,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_on_hold(
+    tool_ids: Vec<tool_hold::ToolId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<HoldWithText>, String> {
+    sqlx::query_as!(
+        HoldWithText,
+        r#"
+        SELECT
+            th.id,
+            th.tool_id,
+            t.real_id AS tool_real_id,
+            t.short_description AS tool_short_description,
+            t.store_id,
+            th.user_id,
+            u.username,
+            th.position,
+            th.status,
+            th.created_at,
+            th.ready_at,
+            th.expires_at
+        FROM main.tool_holds th
+        JOIN main.tools t ON th.tool_id = t.id
+        JOIN main.users u ON th.user_id = u.id
+        WHERE th.tool_id = ANY($1::integer[])
+            AND th.status = 2
+            AND th.expires_at > CURRENT_TIMESTAMP;
+        "#,
+        &tool_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn update_statuses(
+    tool_ids: Vec<tool_hold::ToolId>,
+    user_ids: Vec<tool_hold::UserId>,
+    status: tool_hold::Status,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_hold::ToolHold>, String> {
+    sqlx::query_as!(
+        tool_hold::ToolHold,
+        r#"
+        UPDATE main.tool_holds th
+        SET status = $3
+        WHERE th.tool_id = ANY($1::integer[])
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR th.user_id = ANY($2::integer[]))
+            AND th.status IN (1, 2)
+        RETURNING *;
+        "#,
+        &tool_ids,
+        &user_ids,
+        status,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn update_positions(
+    ids: Vec<tool_hold::Id>,
+    positions: Vec<tool_hold::Position>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        UPDATE main.tool_holds th
+        SET position = p.position
+        FROM UNNEST($1::integer[], $2::integer[]) AS p(id, position)
+        WHERE th.id = p.id;
+        "#,
+        &ids,
+        &positions,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn expire_lapsed(
+    tool_ids: Vec<tool_hold::ToolId>,
+    now: time::OffsetDateTime,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        UPDATE main.tool_holds th
+        SET status = $3
+        WHERE (ARRAY_LENGTH($1::integer[], 1) IS NULL OR th.tool_id = ANY($1::integer[]))
+            AND th.status = 2
+            AND th.expires_at <= $2;
+        "#,
+        &tool_ids,
+        now,
+        tool_hold::HoldStatus::Expired as i32,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// a ready hold goes back to waiting if the tool is no longer available, or if it was moved off the front of the queue
+pub async fn demote_stale(
+    tool_ids: Vec<tool_hold::ToolId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        UPDATE main.tool_holds th
+        SET status = 1, ready_at = NULL, expires_at = NULL
+        FROM main.tools t
+        WHERE th.tool_id = t.id
+            AND (ARRAY_LENGTH($1::integer[], 1) IS NULL OR th.tool_id = ANY($1::integer[]))
+            AND th.status = 2
+            AND (t.status != 1 OR th.position > (
+                SELECT MIN(f.position)
+                FROM main.tool_holds f
+                WHERE f.tool_id = th.tool_id AND f.status IN (1, 2)
+            ));
+        "#,
+        &tool_ids,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub async fn promote_first(
+    tool_ids: Vec<tool_hold::ToolId>,
+    now: time::OffsetDateTime,
+    window_hours: i32,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_hold::ToolHold>, String> {
+    sqlx::query_as!(
+        tool_hold::ToolHold,
+        r#"
+        UPDATE main.tool_holds th
+        SET status = 2, ready_at = $2::timestamptz, expires_at = $2::timestamptz + interval '1' HOUR * $3::integer
+        FROM (
+            SELECT DISTINCT ON (w.tool_id) w.id
+            FROM main.tool_holds w
+            JOIN main.tools t ON w.tool_id = t.id
+            WHERE (ARRAY_LENGTH($1::integer[], 1) IS NULL OR w.tool_id = ANY($1::integer[]))
+                AND w.status IN (1, 2)
+                AND t.status = 1
+            ORDER BY w.tool_id, w.position, w.id
+        ) first_in_line
+        WHERE th.id = first_in_line.id AND th.status = 1
+        RETURNING th.*;
+        "#,
+        &tool_ids,
+        now,
+        window_hours,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
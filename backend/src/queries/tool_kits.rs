@@ -0,0 +1,217 @@
+use crate::db_structs::{store, tool, tool_kit, tool_kit_member};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelectParams {
+    pub ids: Vec<tool_kit::Id>,
+    pub store_ids: Vec<store::Id>,
+    pub real_ids: Vec<tool_kit::RealId>,
+    pub term: String,
+    pub statuses: Vec<tool::Status>,
+    pub category_ids: Vec<i32>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+pub async fn insert(
+    real_id: tool_kit::RealId,
+    store_id: tool_kit::StoreId,
+    short_description: tool_kit::ShortDescription,
+    long_description: Option<tool_kit::LongDescription>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<tool_kit::ToolKit, String> {
+    sqlx::query_as!(
+        tool_kit::ToolKit,
+        r#"
+        INSERT INTO main.tool_kits (real_id, store_id, short_description, long_description)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *;
+        "#,
+        real_id,
+        store_id,
+        short_description,
+        long_description,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn update(
+    id: tool_kit::Id,
+    real_id: Option<tool_kit::RealId>,
+    short_description: Option<tool_kit::ShortDescription>,
+    long_description: Option<tool_kit::LongDescription>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<tool_kit::ToolKit>, String> {
+    sqlx::query_as!(
+        tool_kit::ToolKit,
+        r#"
+        UPDATE main.tool_kits
+        SET
+            real_id = COALESCE($2, real_id),
+            short_description = COALESCE($3, short_description),
+            long_description = COALESCE($4, long_description)
+        WHERE id = $1
+        RETURNING *;
+        "#,
+        id,
+        real_id,
+        short_description,
+        long_description,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn delete(id: tool_kit::Id, db: impl sqlx::PgExecutor<'_>) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        DELETE FROM main.tool_kits tk
+        WHERE tk.id = $1;
+        "#,
+        id,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// A kit only counts as available when every one of its tools has a unit to lend that isn't out,
+// held for pickup or reserved, so asking for available kits leaves out any with a tool that
+// isn't. Any other status matches a kit with at least one tool in it.
+pub async fn select(
+    params: SelectParams,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_kit::ToolKit>, String> {
+    sqlx::query_as!(
+        tool_kit::ToolKit,
+        r#"
+        SELECT tk.*
+        FROM main.tool_kits tk
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR tk.id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR tk.store_id = ANY($2::integer[]))
+            AND (ARRAY_LENGTH($3::text[], 1) IS NULL OR tk.real_id = ANY($3::text[]))
+            AND ($4::text = '' OR $4::text <% (tk.real_id || ' ' || tk.short_description || ' ' || COALESCE(tk.long_description, '')))
+            AND (ARRAY_LENGTH($5::integer[], 1) IS NULL OR EXISTS (
+                SELECT 1
+                FROM main.tool_kit_members km
+                JOIN main.tool_classifications tc ON km.tool_id = tc.tool_id
+                WHERE km.kit_id = tk.id AND tc.category_id = ANY($5::integer[])
+            ))
+            AND (
+                ARRAY_LENGTH($6::integer[], 1) IS NULL
+                OR ($7 = ANY($6::integer[]) AND (
+                    SELECT BOOL_AND(t.status = $7 AND t.quantity > (
+                        SELECT COALESCE(SUM(mr.quantity), 0)
+                        FROM main.rentals mr
+                        WHERE mr.tool_id = t.id AND mr.end_date IS NULL
+                    ) + (
+                        SELECT COUNT(*)
+                        FROM main.tool_holds th
+                        WHERE th.tool_id = t.id AND th.status = 2 AND th.expires_at > CURRENT_TIMESTAMP
+                    ) + (
+                        SELECT COUNT(*)
+                        FROM main.reservations r
+                        WHERE r.tool_id = t.id AND r.status = 1
+                            AND r.start_date <= CURRENT_TIMESTAMP AND CURRENT_TIMESTAMP < r.end_date
+                    ))
+                    FROM main.tool_kit_members km
+                    JOIN main.tools t ON km.tool_id = t.id
+                    WHERE km.kit_id = tk.id
+                ))
+                OR EXISTS (
+                    SELECT 1
+                    FROM main.tool_kit_members km
+                    JOIN main.tools t ON km.tool_id = t.id
+                    WHERE km.kit_id = tk.id AND t.status <> $7 AND t.status = ANY($6::integer[])
+                )
+            )
+        ORDER BY tk.id
+        OFFSET $8 LIMIT $9;
+        "#,
+        &params.ids, // 1
+        &params.store_ids,
+        &params.real_ids,
+        params.term, // 4
+        &params.category_ids,
+        &params.statuses,
+        tool::ToolStatus::Available as i32,
+        params.offset, // 8
+        params.limit,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// members of the given kits, and of any kit one of the given tools is in
+pub async fn select_members(
+    kit_ids: Vec<tool_kit::Id>,
+    tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_kit_member::ToolKitMember>, String> {
+    sqlx::query_as!(
+        tool_kit_member::ToolKitMember,
+        r#"
+        SELECT km.*
+        FROM main.tool_kit_members km
+        WHERE
+            km.kit_id = ANY($1::integer[])
+            OR km.kit_id IN (
+                SELECT kmt.kit_id
+                FROM main.tool_kit_members kmt
+                WHERE kmt.tool_id = ANY($2::integer[])
+            )
+        ORDER BY km.kit_id, km.tool_id;
+        "#,
+        &kit_ids,
+        &tool_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert_members(
+    kit_id: tool_kit::Id,
+    tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<(), String> {
+    sqlx::query!(
+        r#"
+        INSERT INTO main.tool_kit_members (kit_id, tool_id)
+        SELECT $1, * FROM UNNEST($2::integer[]);
+        "#,
+        kit_id,
+        &tool_ids,
+    )
+    .execute(db)
+    .await
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+pub async fn delete_members(
+    kit_id: tool_kit::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        DELETE FROM main.tool_kit_members km
+        WHERE km.kit_id = $1;
+        "#,
+        kit_id,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
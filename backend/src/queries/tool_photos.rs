@@ -34,6 +34,31 @@ pub async fn insert(data: Vec<InsertData>, db: &sqlx::Pool<sqlx::Postgres>) -> R
     .map_err(|e| e.to_string())
 }
 
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TakenPhoto {
+    pub photo_key: tool_photo::PhotoKey,
+    pub original_name: tool_photo::OriginalName,
+}
+
+// removes uploads not yet attached to a tool, for something else to keep them
+pub async fn take_unattached(
+    photo_keys: Vec<tool_photo::PhotoKey>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<TakenPhoto>, String> {
+    sqlx::query_as!(
+        TakenPhoto,
+        r#"
+        DELETE FROM main.tool_photos tp
+        WHERE tp.photo_key = ANY($1::text[]) AND tp.tool_id IS NULL
+        RETURNING tp.photo_key, tp.original_name;
+        "#,
+        &photo_keys,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
 pub async fn update_tool_id(
     id: tool_photo::Id,
     tool_id: Option<tool_photo::ToolId>,
@@ -0,0 +1,107 @@
+use crate::common;
+use crate::db_structs::{tool, tool_status_change, user};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeWithText {
+    pub id: tool_status_change::Id,
+    pub tool_id: tool_status_change::ToolId,
+    pub from_status: Option<tool_status_change::FromStatus>,
+    pub to_status: tool_status_change::ToStatus,
+    pub action: tool_status_change::Action,
+    pub reason: Option<tool_status_change::Reason>,
+    pub changed_by: Option<tool_status_change::ChangedBy>,
+    pub changed_by_username: Option<user::Username>,
+    pub changed_at: tool_status_change::ChangedAt,
+}
+
+pub async fn select_actions(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.tool_status_actions;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert_created(
+    tool_id: tool::Id,
+    status: tool::Status,
+    changed_by: tool_status_change::ChangedBy,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<tool_status_change::ToolStatusChange, String> {
+    sqlx::query_as!(
+        tool_status_change::ToolStatusChange,
+        r#"
+        INSERT INTO main.tool_status_history (tool_id, to_status, action, changed_by)
+        VALUES ($1, $2, $3, $4)
+        RETURNING *;
+        "#,
+        tool_id,
+        status,
+        tool_status_change::StatusAction::Created as i32,
+        changed_by,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// one entry per tool, all moving to the same status for the same reason
+pub async fn insert_many(
+    tool_ids: Vec<tool::Id>,
+    from_statuses: Vec<tool::Status>,
+    to_status: tool::Status,
+    action: tool_status_change::Action,
+    reason: Option<tool_status_change::Reason>,
+    changed_by: Option<tool_status_change::ChangedBy>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        INSERT INTO main.tool_status_history (tool_id, from_status, to_status, action, reason, changed_by)
+        SELECT t.tool_id, t.from_status, $3, $4, $5, $6
+        FROM UNNEST($1::integer[], $2::integer[]) AS t(tool_id, from_status);
+        "#,
+        &tool_ids,
+        &from_statuses,
+        to_status,
+        action,
+        reason,
+        changed_by,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// oldest first
+pub async fn select_by_tool_id(
+    tool_id: tool::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<ChangeWithText>, String> {
+    sqlx::query_as!(
+        ChangeWithText,
+        r#"
+        SELECT h.id, h.tool_id, h.from_status, h.to_status, h.action, h.reason, h.changed_by, u.username AS "changed_by_username?", h.changed_at
+        FROM main.tool_status_history h
+        LEFT JOIN main.users u ON h.changed_by = u.id
+        WHERE h.tool_id = $1
+        ORDER BY h.changed_at, h.id;
+        "#,
+        tool_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
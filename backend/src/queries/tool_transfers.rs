@@ -0,0 +1,170 @@
+use crate::common;
+use crate::db_structs::{store, tool, tool_transfer, tool_transfer_item};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelectParams {
+    pub ids: Vec<tool_transfer::Id>,
+    // transfers out of or into any of these stores
+    pub store_ids: Vec<store::Id>,
+    pub statuses: Vec<tool_transfer::Status>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+pub async fn select_statuses(
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<common::Status>, String> {
+    sqlx::query_as!(
+        common::Status,
+        r#"
+        SELECT *
+        FROM fixed.tool_transfer_statuses;
+        "#,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert(
+    from_store_id: tool_transfer::FromStoreId,
+    to_store_id: tool_transfer::ToStoreId,
+    proposed_by: tool_transfer::ProposedBy,
+    note: Option<tool_transfer::Note>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<tool_transfer::ToolTransfer, String> {
+    sqlx::query_as!(
+        tool_transfer::ToolTransfer,
+        r#"
+        INSERT INTO main.tool_transfers (from_store_id, to_store_id, proposed_by, note, status)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *;
+        "#,
+        from_store_id,
+        to_store_id,
+        proposed_by,
+        note,
+        tool_transfer::TransferStatus::Pending as i32,
+    )
+    .fetch_one(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn insert_items(
+    transfer_id: tool_transfer_item::TransferId,
+    tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<(), String> {
+    sqlx::query!(
+        r#"
+        INSERT INTO main.tool_transfer_items (transfer_id, tool_id)
+        SELECT $1, * FROM UNNEST($2::integer[]);
+        "#,
+        transfer_id,
+        &tool_ids,
+    )
+    .execute(db)
+    .await
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+// newest first
+pub async fn select(
+    params: SelectParams,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_transfer::ToolTransfer>, String> {
+    sqlx::query_as!(
+        tool_transfer::ToolTransfer,
+        r#"
+        SELECT tt.*
+        FROM main.tool_transfers tt
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR tt.id = ANY($1::integer[]))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR tt.from_store_id = ANY($2::integer[]) OR tt.to_store_id = ANY($2::integer[]))
+            AND (ARRAY_LENGTH($3::integer[], 1) IS NULL OR tt.status = ANY($3::integer[]))
+        ORDER BY tt.created_at DESC, tt.id DESC
+        OFFSET $4 LIMIT $5;
+        "#,
+        &params.ids,
+        &params.store_ids,
+        &params.statuses,
+        params.offset,
+        params.limit,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_items(
+    transfer_ids: Vec<tool_transfer::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_transfer_item::ToolTransferItem>, String> {
+    sqlx::query_as!(
+        tool_transfer_item::ToolTransferItem,
+        r#"
+        SELECT *
+        FROM main.tool_transfer_items tti
+        WHERE tti.transfer_id = ANY($1::integer[])
+        ORDER BY tti.transfer_id, tti.tool_id;
+        "#,
+        &transfer_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// tools already waiting on another transfer
+pub async fn select_pending_items(
+    tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool_transfer_item::ToolTransferItem>, String> {
+    sqlx::query_as!(
+        tool_transfer_item::ToolTransferItem,
+        r#"
+        SELECT tti.*
+        FROM main.tool_transfer_items tti
+        JOIN main.tool_transfers tt ON tti.transfer_id = tt.id
+        WHERE tti.tool_id = ANY($1::integer[]) AND tt.status = $2;
+        "#,
+        &tool_ids,
+        tool_transfer::TransferStatus::Pending as i32,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn decide(
+    id: tool_transfer::Id,
+    status: tool_transfer::Status,
+    decided_by: tool_transfer::DecidedBy,
+    decision_note: Option<tool_transfer::DecisionNote>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<tool_transfer::ToolTransfer>, String> {
+    sqlx::query_as!(
+        tool_transfer::ToolTransfer,
+        r#"
+        UPDATE main.tool_transfers tt
+        SET
+            status = $2,
+            decided_by = $3,
+            decided_at = CURRENT_TIMESTAMP,
+            decision_note = $4
+        WHERE tt.id = $1 AND tt.status = $5
+        RETURNING *;
+        "#,
+        id,
+        status,
+        decided_by,
+        decision_note,
+        tool_transfer::TransferStatus::Pending as i32,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
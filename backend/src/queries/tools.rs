@@ -4,16 +4,49 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SelectParams {
+    pub ids: Vec<tool::Id>,
     pub term: String,
     pub statuses: Vec<tool::Status>,
     pub store_ids: Vec<store::Id>,
     pub category_ids: Vec<i32>,
     pub match_all_categories: bool,
     pub real_ids: Vec<tool::RealId>,
+    // stores whose brand, model and serial numbers the term can match, the ones the caller manages
+    pub asset_store_ids: Vec<store::Id>,
+    // zero for any
+    pub min_available: tool::Quantity,
+    pub order_by: OrderBy,
     pub offset: i64,
     pub limit: i64,
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+pub enum OrderBy {
+    Id = 1,
+    Relevance = 2,
+}
+
+pub type Score = f32;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ToolMatch {
+    pub id: tool::Id,
+    pub score: Score,
+}
+
+// what a search index holds for a tool, with the same text the postgres search matches on
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchDocument {
+    pub id: tool::Id,
+    pub store_id: tool::StoreId,
+    pub real_id: tool::RealId,
+    pub short_description: tool::ShortDescription,
+    pub long_description: Option<tool::LongDescription>,
+    pub category_text: String,
+    pub asset_text: String,
+}
+
 pub async fn select_statuses(
     db: &sqlx::Pool<sqlx::Postgres>,
 ) -> Result<Vec<common::Status>, String> {
@@ -29,28 +62,45 @@ pub async fn select_statuses(
     .map_err(|e| e.to_string())
 }
 
+// what a new tool starts with; everything else is left to the column defaults
+#[derive(Debug)]
+pub struct NewTool {
+    pub real_id: tool::RealId,
+    pub store_id: store::Id,
+    pub rental_hours: tool::RentalHours,
+    pub short_description: tool::ShortDescription,
+    pub long_description: Option<tool::LongDescription>,
+    pub status: tool::Status,
+    pub deposit: Option<tool::Deposit>,
+    pub quantity: tool::Quantity,
+    pub location_room: Option<tool::LocationRoom>,
+    pub location_shelf: Option<tool::LocationShelf>,
+    pub location_bin: Option<tool::LocationBin>,
+}
+
 pub async fn insert(
-    real_id: tool::RealId,
-    store_id: store::Id,
-    rental_hours: tool::RentalHours,
-    short_description: tool::ShortDescription,
-    long_description: Option<tool::LongDescription>,
-    status: tool::Status,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    new_tool: NewTool,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<tool::Tool, String> {
     sqlx::query_as!(
         tool::Tool,
         r#"
-        INSERT INTO main.tools (real_id, store_id, rental_hours, short_description, long_description, status)
-        VALUES ($1, $2, $3, $4, $5, $6)
+        INSERT INTO main.tools (real_id, store_id, rental_hours, short_description, long_description, status, deposit, quantity,
+            location_room, location_shelf, location_bin)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NULLIF($9, ''), NULLIF($10, ''), NULLIF($11, ''))
         RETURNING *;
         "#,
-        real_id,
-        store_id,
-        rental_hours,
-        short_description,
-        long_description,
-        status,
+        new_tool.real_id,
+        new_tool.store_id,
+        new_tool.rental_hours,
+        new_tool.short_description,
+        new_tool.long_description,
+        new_tool.status,
+        new_tool.deposit,
+        new_tool.quantity,
+        new_tool.location_room,
+        new_tool.location_shelf,
+        new_tool.location_bin,
     )
     .fetch_one(db)
     .await
@@ -63,8 +113,7 @@ pub async fn update(
     rental_hours: Option<tool::RentalHours>,
     short_description: Option<tool::ShortDescription>,
     long_description: Option<tool::LongDescription>,
-    status: Option<tool::Status>,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<Option<tool::Tool>, String> {
     sqlx::query_as!(
         tool::Tool,
@@ -74,8 +123,7 @@ pub async fn update(
             real_id = COALESCE($2, real_id),
             rental_hours = COALESCE($3, rental_hours),
             short_description = COALESCE($4, short_description),
-            long_description = COALESCE($5, long_description),
-            status = COALESCE($6, status)
+            long_description = COALESCE($5, long_description)
         WHERE id = $1
         RETURNING *;
         "#,
@@ -84,37 +132,196 @@ pub async fn update(
         rental_hours,
         short_description,
         long_description,
-        status,
     )
     .fetch_optional(db)
     .await
     .map_err(|e| e.to_string())
 }
 
+// kept out of update, which already takes plenty of arguments
+pub async fn update_deposit(
+    tool_id: tool::Id,
+    deposit: Option<tool::Deposit>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        UPDATE main.tools
+        SET deposit = $2
+        WHERE id = $1
+        RETURNING *;
+        "#,
+        tool_id,
+        deposit,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// kept apart like the deposit; a tool with more than one unit is pooled
+pub async fn update_quantity(
+    tool_id: tool::Id,
+    quantity: tool::Quantity,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        UPDATE main.tools
+        SET quantity = $2
+        WHERE id = $1
+        RETURNING *;
+        "#,
+        tool_id,
+        quantity,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// where the tool sits in the store; a missing part is left as it was, and an empty one is cleared
+pub async fn update_location(
+    tool_id: tool::Id,
+    room: Option<tool::LocationRoom>,
+    shelf: Option<tool::LocationShelf>,
+    bin: Option<tool::LocationBin>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        UPDATE main.tools
+        SET
+            location_room = CASE WHEN $2::text IS NULL THEN location_room ELSE NULLIF($2, '') END,
+            location_shelf = CASE WHEN $3::text IS NULL THEN location_shelf ELSE NULLIF($3, '') END,
+            location_bin = CASE WHEN $4::text IS NULL THEN location_bin ELSE NULLIF($4, '') END
+        WHERE id = $1
+        RETURNING *;
+        "#,
+        tool_id,
+        room,
+        shelf,
+        bin,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// A pooled tool stays available while it has units left, so asking for rented tools also finds
+// pooled ones with some units out.
 pub async fn select(
     params: SelectParams,
     db: &sqlx::Pool<sqlx::Postgres>,
 ) -> Result<Vec<tool::Tool>, String> {
-    let mut min_category_matches = 0;
-    if !params.match_all_categories {
-        // counterintuitive, but makes the query work
-        min_category_matches = i32::try_from(params.category_ids.len()).unwrap_or(1);
+    Ok(select_scored(params, db)
+        .await?
+        .into_iter()
+        .map(|(t, _)| t)
+        .collect())
+}
+
+// Ranks the tools against the term as well as filtering them. A word counts for most in the
+// real id or short description, then in the names and synonyms of the tool's categories, then
+// in the long description; close spellings still match, for less, and so do brand, model and
+// serial number at the stores in asset_store_ids. An exact real id comes first. Without a term
+// there's nothing to rank, the score is zero and the tools come by id.
+//
+// Each way of matching is its own indexed condition, so postgres can combine the indexes rather
+// than scan every tool: the tool's text against idx_tools_search_document and idx_fuzzy_tools,
+// and the categories and assets through the ids they match. A term with no words to search for,
+// only stop words say, skips the full text search rather than have postgres complain about it.
+pub async fn select_scored(
+    params: SelectParams,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<(tool::Tool, Score)>, String> {
+    if params.term.trim().is_empty() {
+        return Ok(select_filtered(params, db)
+            .await?
+            .into_iter()
+            .map(|t| (t, 0.0))
+            .collect());
     }
 
-    sqlx::query_as!(
-        tool::Tool,
+    let matches = sqlx::query_as!(
+        ToolMatch,
         r#"
-        SELECT mt.*
+        WITH term AS (
+            SELECT
+                (CASE WHEN to_tsvector('english', $1::text) = ''::tsvector THEN NULL
+                    ELSE websearch_to_tsquery('english', $1::text) END) AS query
+        )
+        SELECT mt.id, sc.score AS "score!"
         FROM main.tools mt
-        LEFT JOIN main.tool_classifications tc ON mt.id = tc.tool_id
+        CROSS JOIN term
+        LEFT JOIN main.tool_assets ta ON mt.id = ta.tool_id
+        LEFT JOIN LATERAL (
+            SELECT COALESCE(SUM(mr.quantity), 0) AS units
+            FROM main.rentals mr
+            WHERE mr.tool_id = mt.id AND mr.end_date IS NULL
+        ) ou ON TRUE
+        LEFT JOIN LATERAL (
+            SELECT STRING_AGG(c.name || ' ' || ARRAY_TO_STRING(c.synonyms, ' '), ' ') AS words
+            FROM main.tool_classifications tc
+            JOIN main.tool_categories c ON tc.category_id = c.id
+            WHERE tc.tool_id = mt.id
+        ) cw ON TRUE
+        CROSS JOIN LATERAL (
+            SELECT
+                mt.real_id || ' ' || mt.short_description || ' ' || COALESCE(mt.long_description, '') AS tool_text,
+                COALESCE(cw.words, '') AS category_text,
+                (CASE WHEN mt.store_id = ANY($14::integer[])
+                    THEN COALESCE(ta.brand, '') || ' ' || COALESCE(ta.model, '') || ' ' || COALESCE(ta.serial_number, '')
+                    ELSE '' END) AS asset_text
+        ) doc
+        CROSS JOIN LATERAL (
+            SELECT (
+                COALESCE(ts_rank_cd(
+                    setweight(to_tsvector('simple', mt.real_id), 'A')
+                        || setweight(to_tsvector('english', mt.short_description), 'A')
+                        || setweight(to_tsvector('english', doc.category_text), 'B')
+                        || setweight(to_tsvector('english', COALESCE(mt.long_description, '')), 'C'),
+                    term.query
+                ), 0)
+                + GREATEST(word_similarity($1::text, doc.tool_text), word_similarity($1::text, doc.category_text), word_similarity($1::text, doc.asset_text)) / 2
+                + (CASE WHEN LOWER(mt.real_id) = LOWER($1::text) THEN 1 ELSE 0 END)
+            )::real AS score
+        ) sc
         WHERE
-            ($1::text = '' OR $1::text <% (mt.real_id || ' ' || mt.short_description || ' ' || COALESCE(mt.long_description, '')))
-            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR mt.status = ANY($2::integer[]))
+            (
+                (setweight(to_tsvector('simple', mt.real_id), 'A')
+                    || setweight(to_tsvector('english', mt.short_description), 'A')
+                    || setweight(to_tsvector('english', COALESCE(mt.long_description, '')), 'C')) @@ term.query
+                OR $1::text <% (mt.real_id || ' ' || mt.short_description || ' ' || COALESCE(mt.long_description, ''))
+                OR mt.id = ANY(ARRAY(
+                    SELECT tc.tool_id
+                    FROM main.tool_classifications tc
+                    JOIN main.tool_categories c ON tc.category_id = c.id
+                    WHERE to_tsvector('english', c.name || ' ' || ARRAY_TO_STRING(c.synonyms, ' ')) @@ term.query
+                        OR $1::text <% (c.name || ' ' || ARRAY_TO_STRING(c.synonyms, ' '))
+                ))
+                OR mt.id = ANY(ARRAY(
+                    SELECT ta.tool_id
+                    FROM main.tool_assets ta
+                    JOIN main.tools at ON ta.tool_id = at.id
+                    WHERE at.store_id = ANY($14::integer[])
+                        AND $1::text <% (COALESCE(ta.brand, '') || ' ' || COALESCE(ta.model, '') || ' ' || COALESCE(ta.serial_number, ''))
+                ))
+            )
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR mt.status = ANY($2::integer[]) OR ($9 = ANY($2::integer[]) AND ou.units > 0))
             AND (ARRAY_LENGTH($3::integer[], 1) IS NULL OR mt.store_id = ANY($3::integer[]))
             AND (ARRAY_LENGTH($4::text[], 1) IS NULL OR mt.real_id = ANY($4::text[]))
-            AND (ARRAY_LENGTH($5::integer[], 1) IS NULL OR tc.category_id = ANY($5::integer[]))
-        GROUP BY mt.id having count(*) >= COALESCE(NULLIF(ARRAY_LENGTH($5::integer[], 1), $6), 1)
-        ORDER BY mt.id 
+            AND (ARRAY_LENGTH($5::integer[], 1) IS NULL OR (
+                SELECT COUNT(*)
+                FROM main.tool_classifications tc
+                WHERE tc.tool_id = mt.id AND tc.category_id = ANY($5::integer[])
+            ) >= (CASE WHEN $6::bool THEN ARRAY_LENGTH($5::integer[], 1) ELSE 1 END))
+            AND ($10 = 0 OR (mt.status = $11 AND mt.quantity - ou.units >= $10))
+            AND (ARRAY_LENGTH($13::integer[], 1) IS NULL OR mt.id = ANY($13::integer[]))
+        ORDER BY (CASE WHEN $12::integer = 2 THEN sc.score ELSE 0 END) DESC, mt.id
         OFFSET $7 LIMIT $8;
         "#,
         params.term, // 1
@@ -122,9 +329,70 @@ pub async fn select(
         &params.store_ids,
         &params.real_ids, // 4
         &params.category_ids,
-        min_category_matches,
+        params.match_all_categories,
         params.offset,
         params.limit, // 8
+        tool::ToolStatus::Rented as i32,
+        params.min_available,
+        tool::ToolStatus::Available as i32,
+        params.order_by as i32, // 12
+        &params.ids,
+        &params.asset_store_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let mut tools = select_by_ids(matches.iter().map(|m| m.id).collect(), db).await?;
+    Ok(matches
+        .into_iter()
+        .filter_map(|m| {
+            let index = tools.iter().position(|t| t.id == m.id)?;
+            Some((tools.swap_remove(index), m.score))
+        })
+        .collect())
+}
+
+// the filters of select_scored on their own, by id
+async fn select_filtered(
+    params: SelectParams,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        SELECT mt.*
+        FROM main.tools mt
+        LEFT JOIN LATERAL (
+            SELECT COALESCE(SUM(mr.quantity), 0) AS units
+            FROM main.rentals mr
+            WHERE mr.tool_id = mt.id AND mr.end_date IS NULL
+        ) ou ON TRUE
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR mt.status = ANY($1::integer[]) OR ($8 = ANY($1::integer[]) AND ou.units > 0))
+            AND (ARRAY_LENGTH($2::integer[], 1) IS NULL OR mt.store_id = ANY($2::integer[]))
+            AND (ARRAY_LENGTH($3::text[], 1) IS NULL OR mt.real_id = ANY($3::text[]))
+            AND (ARRAY_LENGTH($4::integer[], 1) IS NULL OR (
+                SELECT COUNT(*)
+                FROM main.tool_classifications tc
+                WHERE tc.tool_id = mt.id AND tc.category_id = ANY($4::integer[])
+            ) >= (CASE WHEN $5::bool THEN ARRAY_LENGTH($4::integer[], 1) ELSE 1 END))
+            AND ($9 = 0 OR (mt.status = $10 AND mt.quantity - ou.units >= $9))
+            AND (ARRAY_LENGTH($11::integer[], 1) IS NULL OR mt.id = ANY($11::integer[]))
+        ORDER BY mt.id
+        OFFSET $6 LIMIT $7;
+        "#,
+        &params.statuses, // 1
+        &params.store_ids,
+        &params.real_ids,
+        &params.category_ids, // 4
+        params.match_all_categories,
+        params.offset,
+        params.limit,
+        tool::ToolStatus::Rented as i32, // 8
+        params.min_available,
+        tool::ToolStatus::Available as i32,
+        &params.ids,
     )
     .fetch_all(db)
     .await
@@ -133,16 +401,122 @@ pub async fn select(
 
 pub async fn select_by_ids(
     tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        SELECT *
+        FROM main.tools
+        WHERE id = ANY($1::integer[]);
+        "#,
+        &tool_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// by id, for the tools given or every tool after after_id when there are none
+pub async fn select_search_documents(
+    tool_ids: Vec<tool::Id>,
+    after_id: tool::Id,
+    limit: i64,
     db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<SearchDocument>, String> {
+    sqlx::query_as!(
+        SearchDocument,
+        r#"
+        SELECT
+            mt.id,
+            mt.store_id,
+            mt.real_id,
+            mt.short_description,
+            mt.long_description,
+            COALESCE(cw.words, '') AS "category_text!",
+            CONCAT_WS(' ', ta.brand, ta.model, ta.serial_number) AS "asset_text!"
+        FROM main.tools mt
+        LEFT JOIN main.tool_assets ta ON mt.id = ta.tool_id
+        LEFT JOIN LATERAL (
+            SELECT STRING_AGG(c.name || ' ' || ARRAY_TO_STRING(c.synonyms, ' '), ' ') AS words
+            FROM main.tool_classifications tc
+            JOIN main.tool_categories c ON tc.category_id = c.id
+            WHERE tc.tool_id = mt.id
+        ) cw ON TRUE
+        WHERE
+            (ARRAY_LENGTH($1::integer[], 1) IS NULL OR mt.id = ANY($1::integer[]))
+            AND mt.id > $2
+        ORDER BY mt.id
+        LIMIT $3;
+        "#,
+        &tool_ids,
+        after_id,
+        limit,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+pub async fn select_by_ids_for_update(
+    tool_ids: Vec<tool::Id>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<Vec<tool::Tool>, String> {
     sqlx::query_as!(
         tool::Tool,
         r#"
         SELECT *
         FROM main.tools
+        WHERE id = ANY($1::integer[])
+        ORDER BY id
+        FOR UPDATE;
+        "#,
+        &tool_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// status changes go through handlers::tool_status_history::change_statuses, which keeps the history
+pub async fn update_statuses(
+    tool_ids: Vec<tool::Id>,
+    status: tool::Status,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<u64, String> {
+    match sqlx::query!(
+        r#"
+        UPDATE main.tools
+        SET status = $2
         WHERE id = ANY($1::integer[]);
         "#,
         &tool_ids,
+        status,
+    )
+    .execute(db)
+    .await
+    {
+        Ok(res) => Ok(res.rows_affected()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// the only way a tool changes store; photos and categories hang off the tool id, so they go along
+pub async fn update_store_ids(
+    tool_ids: Vec<tool::Id>,
+    store_id: store::Id,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        UPDATE main.tools
+        SET store_id = $2
+        WHERE id = ANY($1::integer[])
+        RETURNING *;
+        "#,
+        &tool_ids,
+        store_id,
     )
     .fetch_all(db)
     .await
@@ -170,3 +544,98 @@ pub async fn select_exact_real(
     .await
     .map_err(|e| e.to_string())
 }
+
+pub async fn select_by_store_real_ids(
+    store_ids: Vec<tool::StoreId>,
+    real_ids: Vec<tool::RealId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        SELECT *
+        FROM main.tools
+        WHERE (store_id, real_id) IN (
+            SELECT * FROM UNNEST($1::integer[], $2::text[])
+        )
+        ORDER BY id;
+        "#,
+        &store_ids,
+        &real_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+// locks the tools found, for a batch check in or out by real id
+pub async fn select_by_store_real_ids_for_update(
+    store_id: tool::StoreId,
+    real_ids: Vec<tool::RealId>,
+    db: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<tool::Tool>, String> {
+    sqlx::query_as!(
+        tool::Tool,
+        r#"
+        SELECT *
+        FROM main.tools
+        WHERE store_id = $1 AND real_id = ANY($2::text[])
+        ORDER BY id
+        FOR UPDATE;
+        "#,
+        store_id,
+        &real_ids,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ToolWithCategoryNames {
+    pub real_id: tool::RealId,
+    pub short_description: tool::ShortDescription,
+    pub long_description: Option<tool::LongDescription>,
+    pub rental_hours: tool::RentalHours,
+    pub deposit: Option<tool::Deposit>,
+    pub quantity: tool::Quantity,
+    pub location_room: Option<tool::LocationRoom>,
+    pub location_shelf: Option<tool::LocationShelf>,
+    pub location_bin: Option<tool::LocationBin>,
+    pub status: tool::Status,
+    pub categories: Vec<String>,
+}
+
+// every tool in the store, in the shape the bulk import takes
+pub async fn select_for_export(
+    store_id: store::Id,
+    db: &sqlx::Pool<sqlx::Postgres>,
+) -> Result<Vec<ToolWithCategoryNames>, String> {
+    sqlx::query_as!(
+        ToolWithCategoryNames,
+        r#"
+        SELECT
+            t.real_id,
+            t.short_description,
+            t.long_description,
+            t.rental_hours,
+            t.deposit,
+            t.quantity,
+            t.location_room,
+            t.location_shelf,
+            t.location_bin,
+            t.status,
+            COALESCE(ARRAY_AGG(tc.name ORDER BY tc.name) FILTER (WHERE tc.id IS NOT NULL), '{}') AS "categories!"
+        FROM main.tools t
+        LEFT JOIN main.tool_classifications tcl ON t.id = tcl.tool_id
+        LEFT JOIN main.tool_categories tc ON tcl.category_id = tc.id
+        WHERE t.store_id = $1
+        GROUP BY t.id
+        ORDER BY t.id;
+        "#,
+        store_id,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|e| e.to_string())
+}
@@ -132,7 +132,7 @@ pub async fn select_by_email(
 
 pub async fn select_by_ids(
     ids: Vec<user::Id>,
-    db: &sqlx::Pool<sqlx::Postgres>,
+    db: impl sqlx::PgExecutor<'_>,
 ) -> Result<Vec<user::User>, String> {
     sqlx::query_as!(
         user::User,
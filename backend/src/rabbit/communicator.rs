@@ -66,6 +66,16 @@ impl Communicator {
         &self,
         queue: &str,
         payload: &[u8],
+    ) -> Result<PublisherConfirm, String> {
+        self.send_event(queue, "", payload).await
+    }
+
+    // the exchanges are fanout, so the routing key is only there to tell consumers what kind of event they got
+    pub async fn send_event(
+        &self,
+        queue: &str,
+        routing_key: &str,
+        payload: &[u8],
     ) -> Result<PublisherConfirm, String> {
         let chan = match self.channel {
             Some(ref c) => c,
@@ -74,7 +84,7 @@ impl Communicator {
 
         chan.basic_publish(
             queue,
-            "",
+            routing_key,
             BasicPublishOptions::default(),
             payload,
             BasicProperties::default(),
@@ -0,0 +1,524 @@
+use super::postgres::PostgresSearch;
+use super::SearchBackend;
+use crate::common;
+use crate::db_structs::{store, tool};
+use crate::queries::{stores, tools};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::env;
+
+pub const TOOLS_INDEX: &str = "lobster-tools";
+pub const STORES_INDEX: &str = "lobster-stores";
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    hits: Hits,
+}
+
+#[derive(Debug, Deserialize)]
+struct Hits {
+    hits: Vec<Hit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Hit {
+    #[serde(rename = "_id")]
+    id: String,
+    #[serde(rename = "_score")]
+    score: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkResponse {
+    errors: bool,
+}
+
+// Elasticsearch finds and ranks the matches for a term, then postgres filters them, so the
+// filters mean the same whichever backend is used. Only the first MAX_SEARCH_CANDIDATES matches
+// are filtered and paged. Without a term there's nothing to rank, and postgres does it all.
+#[derive(Debug, Clone)]
+pub struct ElasticSearch {
+    url: String,
+    client: reqwest::Client,
+    db: PgPool,
+    postgres: PostgresSearch,
+}
+
+fn tool_mappings() -> Value {
+    json!({
+        "properties": {
+            "storeId": { "type": "integer" },
+            "realId": { "type": "text", "fields": { "keyword": { "type": "keyword" } } },
+            "shortDescription": { "type": "text", "analyzer": "english" },
+            "longDescription": { "type": "text", "analyzer": "english" },
+            "categoryText": { "type": "text", "analyzer": "english" },
+            "assetText": { "type": "text" }
+        }
+    })
+}
+
+fn store_mappings() -> Value {
+    json!({
+        "properties": {
+            "status": { "type": "integer" },
+            "name": { "type": "text" },
+            "rentalInformation": { "type": "text", "analyzer": "english" },
+            "otherInformation": { "type": "text", "analyzer": "english" },
+            "location": { "type": "text" },
+            "emailAddress": { "type": "text" },
+            "phoneNumber": { "type": "text" }
+        }
+    })
+}
+
+// everything but the code, which is never searched
+fn store_document(store: &store::Store) -> Value {
+    json!({
+        "status": store.status,
+        "name": store.name,
+        "rentalInformation": store.rental_information,
+        "otherInformation": store.other_information,
+        "location": store.location,
+        "emailAddress": store.email_address,
+        "phoneNumber": store.phone_number,
+    })
+}
+
+// ids in the order they were ranked, each at most once
+fn in_ranked_order<T>(
+    ranked: &[(i32, f32)],
+    mut found: Vec<T>,
+    id_of: impl Fn(&T) -> i32,
+) -> Vec<(T, f32)> {
+    ranked
+        .iter()
+        .filter_map(|(id, score)| {
+            let index = found.iter().position(|f| id_of(f) == *id)?;
+            Some((found.swap_remove(index), *score))
+        })
+        .collect()
+}
+
+impl ElasticSearch {
+    pub fn from_env(db: PgPool) -> Self {
+        let url = env::var("ELASTIC_URL").expect("ELASTIC_URL must be set to search with elastic");
+        ElasticSearch {
+            url: url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+            postgres: PostgresSearch::new(db.clone()),
+            db,
+        }
+    }
+
+    // TOOLS_INDEX and STORES_INDEX are aliases, each for one versioned index behind it, so a
+    // reindex can build the next version while searches use the last one
+    fn versioned_index(alias: &str) -> String {
+        format!(
+            "{}-{}",
+            alias,
+            time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000
+        )
+    }
+
+    async fn create_index(
+        &self,
+        index: &str,
+        mappings: Value,
+        alias: Option<&str>,
+    ) -> Result<(), String> {
+        let mut body = json!({ "mappings": mappings });
+        if let Some(alias) = alias {
+            body["aliases"] = json!({ alias: {} });
+        }
+        self.client
+            .put(format!("{}/{}", self.url, index))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // the indices the alias points at, or the name itself when it's a plain index left from
+    // before the aliases; empty when there's neither
+    async fn indices_behind(&self, alias: &str) -> Result<Vec<String>, String> {
+        let res = self
+            .client
+            .get(format!("{}/_alias/{}", self.url, alias))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if res.status().is_success() {
+            let found: serde_json::Map<String, Value> =
+                res.json().await.map_err(|e| e.to_string())?;
+            if !found.is_empty() {
+                return Ok(found.into_iter().map(|(index, _)| index).collect());
+            }
+        } else if res.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(format!("Failed to look up {}: {}", alias, res.status()));
+        }
+
+        let plain = self
+            .client
+            .head(format!("{}/{}", self.url, alias))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if plain.status().is_success() {
+            Ok(vec![alias.to_string()])
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    // creates whichever index is missing, and leaves the others alone
+    pub async fn create_indices(&self) -> Result<(), String> {
+        for (alias, mappings) in [
+            (TOOLS_INDEX, tool_mappings()),
+            (STORES_INDEX, store_mappings()),
+        ] {
+            if self.indices_behind(alias).await?.is_empty() {
+                self.create_index(&Self::versioned_index(alias), mappings, Some(alias))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Builds both indices again from postgres into new versions, then points the aliases at them
+    // and drops the old ones in one step, so searches carry on against the old versions until
+    // the new ones are complete. For a first run, and after changes the exchanges don't carry,
+    // like a renamed category. Returns how many tools and stores.
+    pub async fn reindex(&self) -> Result<(usize, usize), String> {
+        let tools_index = Self::versioned_index(TOOLS_INDEX);
+        let stores_index = Self::versioned_index(STORES_INDEX);
+        self.create_index(&tools_index, tool_mappings(), None)
+            .await?;
+        self.create_index(&stores_index, store_mappings(), None)
+            .await?;
+
+        let mut tool_count = 0;
+        let mut after_id = 0;
+        loop {
+            let documents = tools::select_search_documents(
+                vec![],
+                after_id,
+                common::SEARCH_REINDEX_BATCH_SIZE,
+                &self.db,
+            )
+            .await?;
+            let Some(last) = documents.last() else {
+                break;
+            };
+            after_id = last.id;
+            tool_count += documents.len();
+            self.bulk_index(
+                &tools_index,
+                documents.iter().map(|d| (d.id, json!(d))).collect(),
+            )
+            .await?;
+        }
+
+        let mut store_count = 0;
+        let mut after_id = 0;
+        loop {
+            let found =
+                stores::select_after(after_id, common::SEARCH_REINDEX_BATCH_SIZE, &self.db).await?;
+            let Some(last) = found.last() else {
+                break;
+            };
+            after_id = last.id;
+            store_count += found.len();
+            self.bulk_index(
+                &stores_index,
+                found.iter().map(|s| (s.id, store_document(s))).collect(),
+            )
+            .await?;
+        }
+
+        let mut actions = vec![];
+        for (alias, index) in [(TOOLS_INDEX, &tools_index), (STORES_INDEX, &stores_index)] {
+            for old in self.indices_behind(alias).await? {
+                actions.push(json!({ "remove_index": { "index": old } }));
+            }
+            actions.push(json!({ "add": { "index": index, "alias": alias } }));
+        }
+        self.client
+            .post(format!("{}/_aliases", self.url))
+            .json(&json!({ "actions": actions }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+
+        Ok((tool_count, store_count))
+    }
+
+    // brings the given tools up to date, and drops any that are gone
+    pub async fn index_tools(&self, tool_ids: Vec<tool::Id>) -> Result<(), String> {
+        if tool_ids.is_empty() {
+            return Ok(());
+        }
+        let documents =
+            tools::select_search_documents(tool_ids.clone(), 0, tool_ids.len() as i64, &self.db)
+                .await?;
+        for id in tool_ids {
+            if !documents.iter().any(|d| d.id == id) {
+                self.delete_document(TOOLS_INDEX, id).await?;
+            }
+        }
+        self.bulk_index(
+            TOOLS_INDEX,
+            documents.iter().map(|d| (d.id, json!(d))).collect(),
+        )
+        .await
+    }
+
+    pub async fn index_stores(&self, store_ids: Vec<store::Id>) -> Result<(), String> {
+        if store_ids.is_empty() {
+            return Ok(());
+        }
+        let found = stores::select(
+            stores::SelectParams {
+                ids: store_ids.clone(),
+                statuses: vec![],
+                term: String::new(),
+                user_ids: vec![],
+                offset: 0,
+                limit: store_ids.len() as i64,
+            },
+            &self.db,
+        )
+        .await?;
+        for id in store_ids {
+            if !found.iter().any(|s| s.id == id) {
+                self.delete_document(STORES_INDEX, id).await?;
+            }
+        }
+        self.bulk_index(
+            STORES_INDEX,
+            found.iter().map(|s| (s.id, store_document(s))).collect(),
+        )
+        .await
+    }
+
+    async fn bulk_index(&self, index: &str, documents: Vec<(i32, Value)>) -> Result<(), String> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+
+        let mut body = String::new();
+        for (id, document) in documents {
+            body.push_str(
+                &json!({ "index": { "_index": index, "_id": id.to_string() } }).to_string(),
+            );
+            body.push('\n');
+            body.push_str(&document.to_string());
+            body.push('\n');
+        }
+
+        let res: BulkResponse = self
+            .client
+            .post(format!("{}/_bulk", self.url))
+            .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        if res.errors {
+            return Err(format!("Failed to index some documents into {}", index));
+        }
+        Ok(())
+    }
+
+    async fn delete_document(&self, index: &str, id: i32) -> Result<(), String> {
+        let res = self
+            .client
+            .delete(format!("{}/{}/_doc/{}", self.url, index, id))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !res.status().is_success() && res.status() != reqwest::StatusCode::NOT_FOUND {
+            return Err(format!(
+                "Failed to drop {} from {}: {}",
+                id,
+                index,
+                res.status()
+            ));
+        }
+        Ok(())
+    }
+
+    // ids and scores of the best matches, best first
+    async fn search(&self, index: &str, query: Value) -> Result<Vec<(i32, f32)>, String> {
+        let res: SearchResponse = self
+            .client
+            .post(format!("{}/{}/_search", self.url, index))
+            .json(&json!({
+                "size": common::MAX_SEARCH_CANDIDATES,
+                "_source": false,
+                "query": query,
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(res
+            .hits
+            .hits
+            .into_iter()
+            .filter_map(|h| Some((h.id.parse::<i32>().ok()?, h.score.unwrap_or_default())))
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl SearchBackend for ElasticSearch {
+    // A word counts for most in the real id or short description, then in the tool's categories,
+    // then anywhere else. Close spellings match for less, and an exact real id comes first.
+    async fn search_tools(
+        &self,
+        params: tools::SelectParams,
+    ) -> Result<Vec<(tool::Tool, tools::Score)>, String> {
+        if params.term.is_empty() {
+            return self.postgres.search_tools(params).await;
+        }
+
+        let fields = [
+            "realId^3",
+            "shortDescription^3",
+            "categoryText^2",
+            "longDescription",
+        ];
+        let mut filter = vec![];
+        if !params.store_ids.is_empty() {
+            filter.push(json!({ "terms": { "storeId": params.store_ids } }));
+        }
+        let mut should = vec![
+            json!({ "multi_match": { "query": params.term, "fields": fields } }),
+            json!({ "multi_match": { "query": params.term, "fields": fields, "fuzziness": "AUTO", "boost": 0.5 } }),
+            json!({ "term": { "realId.keyword": { "value": params.term, "case_insensitive": true, "boost": 10 } } }),
+        ];
+        // brand, model and serial number only match at the stores the caller manages
+        if !params.asset_store_ids.is_empty() {
+            should.push(json!({
+                "bool": {
+                    "should": [
+                        { "match": { "assetText": params.term } },
+                        { "match": { "assetText": { "query": params.term, "fuzziness": "AUTO", "boost": 0.5 } } }
+                    ],
+                    "minimum_should_match": 1,
+                    "filter": [{ "terms": { "storeId": params.asset_store_ids } }],
+                }
+            }));
+        }
+        let ranked = self
+            .search(
+                TOOLS_INDEX,
+                json!({
+                    "bool": {
+                        "should": should,
+                        "minimum_should_match": 1,
+                        "filter": filter,
+                    }
+                }),
+            )
+            .await?;
+        if ranked.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let (order_by, offset, limit) = (params.order_by, params.offset, params.limit);
+        let found = tools::select(
+            tools::SelectParams {
+                ids: ranked.iter().map(|(id, _)| *id).collect(),
+                term: String::new(),
+                order_by: tools::OrderBy::Id,
+                offset: 0,
+                limit: common::MAX_SEARCH_CANDIDATES,
+                ..params
+            },
+            &self.db,
+        )
+        .await?;
+
+        let mut scored = in_ranked_order(&ranked, found, |t| t.id);
+        if let tools::OrderBy::Id = order_by {
+            scored.sort_by_key(|(t, _)| t.id);
+        }
+        Ok(scored
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn search_stores(
+        &self,
+        params: stores::SelectParams,
+        with_contact_info: bool,
+    ) -> Result<Vec<store::Store>, String> {
+        if params.term.is_empty() {
+            return self.postgres.search_stores(params, with_contact_info).await;
+        }
+
+        let mut fields = vec!["name^3", "rentalInformation", "otherInformation"];
+        if with_contact_info {
+            fields.extend(["location^2", "emailAddress", "phoneNumber"]);
+        }
+        let mut filter = vec![];
+        if !params.statuses.is_empty() {
+            filter.push(json!({ "terms": { "status": params.statuses } }));
+        }
+        let ranked = self
+            .search(
+                STORES_INDEX,
+                json!({
+                    "bool": {
+                        "must": { "multi_match": { "query": params.term, "fields": fields, "fuzziness": "AUTO" } },
+                        "filter": filter,
+                    }
+                }),
+            )
+            .await?;
+        if ranked.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let (offset, limit) = (params.offset, params.limit);
+        let found = self
+            .postgres
+            .search_stores(
+                stores::SelectParams {
+                    ids: ranked.iter().map(|(id, _)| *id).collect(),
+                    term: String::new(),
+                    offset: 0,
+                    limit: common::MAX_SEARCH_CANDIDATES,
+                    ..params
+                },
+                with_contact_info,
+            )
+            .await?;
+
+        Ok(in_ranked_order(&ranked, found, |s| s.id)
+            .into_iter()
+            .map(|(s, _)| s)
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+}
@@ -0,0 +1,137 @@
+use super::elastic::ElasticSearch;
+use crate::common;
+use crate::rabbit::communicator::Communicator;
+use futures::StreamExt;
+use lapin::{options::*, types::FieldTable, Connection, ConnectionProperties};
+use serde::Deserialize;
+use std::{env, time::Duration};
+
+// survives the backend restarting, so changes made while it was down are indexed once it's back
+const QUEUE: &str = "search-indexer";
+
+// the tools and stores exchanges carry the whole row, but only the id is needed to reload it
+#[derive(Debug, Deserialize)]
+struct Published {
+    id: i32,
+}
+
+// Runs forever, keeping the elastic indices in step with every tool and store sent to the
+// exchanges. Each change is read again from postgres, so a late or repeated message can't leave
+// an old copy behind. A lost connection is opened again after SEARCH_INDEXER_RETRY_SECONDS.
+pub async fn run(elastic: ElasticSearch) {
+    let retry = Duration::from_secs(common::SEARCH_INDEXER_RETRY_SECONDS);
+    loop {
+        match consume(&elastic).await {
+            Ok(()) => eprintln!("Search indexer lost its rabbitMQ channel, reconnecting"),
+            Err(e) => eprintln!("Search indexer failed, reconnecting: {}", e),
+        }
+        tokio::time::sleep(retry).await;
+    }
+}
+
+// Returns when the channel closes. A message is only acked once its change is in the index, and
+// one that couldn't be indexed goes back on the queue to be tried again.
+async fn consume(elastic: &ElasticSearch) -> Result<(), String> {
+    let addr = env::var("RABBIT_URL").map_err(|e| e.to_string())?;
+    let connection = Connection::connect(&addr, ConnectionProperties::default())
+        .await
+        .map_err(|e| e.to_string())?;
+    let channel = connection
+        .create_channel()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // the exchanges may not be there yet if the indexer connects first
+    let comm = Communicator::new(Some(channel.clone()));
+    for exchange in ["tools", "stores"] {
+        comm.declare_queue(exchange).await?;
+    }
+
+    channel
+        .queue_declare(
+            QUEUE,
+            QueueDeclareOptions {
+                durable: true,
+                ..QueueDeclareOptions::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    for exchange in ["tools", "stores"] {
+        channel
+            .queue_bind(
+                QUEUE,
+                exchange,
+                "",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    channel
+        .basic_qos(common::SEARCH_INDEXER_PREFETCH, BasicQosOptions::default())
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut consumer = channel
+        .basic_consume(
+            QUEUE,
+            "search-indexer",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    while let Some(delivery) = consumer.next().await {
+        let delivery = delivery.map_err(|e| e.to_string())?;
+        let id = match serde_json::from_slice::<Published>(&delivery.data) {
+            Ok(p) => p.id,
+            Err(e) => {
+                // trying again won't make it readable
+                eprintln!("Search indexer dropped a message: {}", e);
+                delivery
+                    .acker
+                    .nack(BasicNackOptions::default())
+                    .await
+                    .map_err(|e| e.to_string())?;
+                continue;
+            }
+        };
+
+        let indexed = match delivery.exchange.as_str() {
+            "tools" => elastic.index_tools(vec![id]).await,
+            "stores" => elastic.index_stores(vec![id]).await,
+            _ => Ok(()),
+        };
+        match indexed {
+            Ok(()) => delivery
+                .acker
+                .ack(BasicAckOptions::default())
+                .await
+                .map_err(|e| e.to_string())?,
+            Err(e) => {
+                eprintln!(
+                    "Search indexer failed on {} {}, will try again: {}",
+                    delivery.exchange.as_str(),
+                    id,
+                    e
+                );
+                // elastic is likely down for a moment, so give it one before the retry
+                tokio::time::sleep(Duration::from_secs(common::SEARCH_INDEXER_RETRY_SECONDS)).await;
+                delivery
+                    .acker
+                    .nack(BasicNackOptions {
+                        requeue: true,
+                        ..BasicNackOptions::default()
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+pub mod elastic;
+pub mod indexer;
+pub mod postgres;
+
+use crate::db_structs::{store, tool};
+use crate::queries::{stores, tools};
+use std::env;
+
+// Where /tools and /stores look for a search term. SEARCH_BACKEND picks one, postgres unless
+// it's set to elastic.
+#[async_trait::async_trait]
+pub trait SearchBackend: Send + Sync {
+    async fn search_tools(
+        &self,
+        params: tools::SelectParams,
+    ) -> Result<Vec<(tool::Tool, tools::Score)>, String>;
+
+    // without contact info, a store's location, email and phone number can't be matched either
+    async fn search_stores(
+        &self,
+        params: stores::SelectParams,
+        with_contact_info: bool,
+    ) -> Result<Vec<store::Store>, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    Postgres,
+    Elastic,
+}
+
+pub fn configured_backend() -> BackendKind {
+    match env::var("SEARCH_BACKEND").unwrap_or_default().as_str() {
+        "" | "postgres" => BackendKind::Postgres,
+        "elastic" => BackendKind::Elastic,
+        other => panic!("SEARCH_BACKEND must be postgres or elastic, not {}", other),
+    }
+}
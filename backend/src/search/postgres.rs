@@ -0,0 +1,38 @@
+use super::SearchBackend;
+use crate::db_structs::{store, tool};
+use crate::queries::{stores, tools};
+use sqlx::PgPool;
+
+// the trigram and full text search in the queries themselves, which needs no index kept in sync
+#[derive(Debug, Clone)]
+pub struct PostgresSearch {
+    db: PgPool,
+}
+
+impl PostgresSearch {
+    pub fn new(db: PgPool) -> Self {
+        PostgresSearch { db }
+    }
+}
+
+#[async_trait::async_trait]
+impl SearchBackend for PostgresSearch {
+    async fn search_tools(
+        &self,
+        params: tools::SelectParams,
+    ) -> Result<Vec<(tool::Tool, tools::Score)>, String> {
+        tools::select_scored(params, &self.db).await
+    }
+
+    async fn search_stores(
+        &self,
+        params: stores::SelectParams,
+        with_contact_info: bool,
+    ) -> Result<Vec<store::Store>, String> {
+        if with_contact_info {
+            stores::select(params, &self.db).await
+        } else {
+            stores::select_no_contact(params, &self.db).await
+        }
+    }
+}
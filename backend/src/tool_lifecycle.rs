@@ -0,0 +1,55 @@
+use crate::db_structs::tool::{self, ToolStatus, ToolStatus::*};
+use crate::db_structs::tool_status_change::StatusAction::{self, *};
+
+// Every move a tool's status is allowed to make, and what has to happen for it. Anything
+// not listed is refused. Retired is final, and a lost or stolen tool only comes back by
+// being found (which is what a check in of a lost tool counts as).
+const TRANSITIONS: &[(ToolStatus, &[ToolStatus], StatusAction)] = &[
+    (Available, &[Rented], CheckedOut),
+    (Rented, &[Available, Maintenance, Broken], CheckedIn),
+    (Lost, &[Available, Maintenance, Broken], Found),
+    (Stolen, &[Available, Maintenance, Broken], Found),
+    (Rented, &[Lost], MarkedLost),
+    (Available, &[Unknown], StoreClosed),
+    (Available, &[Maintenance, Broken], TicketOpened),
+    (Available, &[Lost, Unknown], Stocktake),
+    (Maintenance, &[Broken], TicketOpened),
+    (
+        Available,
+        &[Maintenance, Broken, Lost, Stolen, Retired, Unknown],
+        Edited,
+    ),
+    (Rented, &[Lost, Stolen], Edited),
+    (Maintenance, &[Available, Broken, Retired, Unknown], Edited),
+    (Broken, &[Maintenance, Retired], Edited),
+    (Lost, &[Stolen, Retired], Edited),
+    (Stolen, &[Retired], Edited),
+    (
+        Unknown,
+        &[Available, Maintenance, Broken, Lost, Stolen, Retired],
+        Edited,
+    ),
+];
+
+pub fn can_transition(from: tool::Status, to: tool::Status, action: StatusAction) -> bool {
+    TRANSITIONS.iter().any(|(f, targets, a)| {
+        *f as i32 == from && *a == action && targets.iter().any(|t| *t as i32 == to)
+    })
+}
+
+// a lost or stolen tool coming back counts as found, and a tool that wasn't out at all is just
+// having its condition noted
+pub fn check_in_action(status: tool::Status) -> StatusAction {
+    if status == Rented as i32 {
+        CheckedIn
+    } else if status == Lost as i32 || status == Stolen as i32 {
+        Found
+    } else {
+        Edited
+    }
+}
+
+// a tool can be created in any status that doesn't need a rental behind it
+pub fn is_initial_status(status: tool::Status) -> bool {
+    status != Rented as i32
+}
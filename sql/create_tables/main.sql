@@ -3,6 +3,9 @@ CREATE SCHEMA IF NOT EXISTS main;
 CREATE TABLE main.library_information (
     uuid UUID DEFAULT gen_random_uuid() NOT NULL,
     name TEXT NOT NULL,
+    overdue_reminder_interval_days INTEGER DEFAULT 1 NOT NULL,
+    overdue_lost_after_days INTEGER,
+    overdue_grievance_after_days INTEGER,
     PRIMARY KEY (uuid)
 );
 
@@ -55,6 +58,7 @@ CREATE TABLE main.stores (
     other_information TEXT,
     code TEXT NOT NULL,
     created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    timezone TEXT DEFAULT 'UTC' NOT NULL,
     PRIMARY KEY (id),
     UNIQUE (name),
     UNIQUE (code),
@@ -79,6 +83,11 @@ CREATE TABLE main.tools (
     short_description TEXT NOT NULL,
     long_description TEXT,
     status INTEGER NOT NULL,
+    deposit INTEGER,
+    quantity INTEGER DEFAULT 1 NOT NULL,
+    location_room TEXT,
+    location_shelf TEXT,
+    location_bin TEXT,
     PRIMARY KEY (id),
     CONSTRAINT fk_status
       FOREIGN KEY(status)
@@ -87,6 +96,26 @@ CREATE TABLE main.tools (
 
 CREATE INDEX IF NOT EXISTS idx_tools_store_id ON main.tools USING btree(store_id);
 
+CREATE TABLE main.tool_status_history (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    tool_id INTEGER NOT NULL,
+    from_status INTEGER,
+    to_status INTEGER NOT NULL,
+    action INTEGER NOT NULL,
+    reason TEXT,
+    changed_by INTEGER,
+    changed_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_to_status
+      FOREIGN KEY(to_status)
+        REFERENCES fixed.tool_statuses(id),
+    CONSTRAINT fk_action
+      FOREIGN KEY(action)
+        REFERENCES fixed.tool_status_actions(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tool_status_history_tool_id ON main.tool_status_history USING btree(tool_id, changed_at);
+
 CREATE TABLE main.tool_photos (
     id INTEGER GENERATED ALWAYS AS IDENTITY,
     tool_id INTEGER,
@@ -107,17 +136,420 @@ CREATE TABLE main.tool_classifications (
 CREATE INDEX IF NOT EXISTS idx_tool_classifications_tool_id ON main.tool_classifications USING btree(tool_id);
 CREATE INDEX IF NOT EXISTS idx_tool_classifications_category_id ON main.tool_classifications USING btree(category_id);
 
+CREATE TABLE main.tool_kits (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    real_id TEXT NOT NULL,
+    store_id INTEGER NOT NULL,
+    short_description TEXT NOT NULL,
+    long_description TEXT,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_tool_kits_store_id_real_id ON main.tool_kits USING btree(store_id, real_id);
+
+CREATE TABLE main.tool_kit_members (
+    kit_id INTEGER NOT NULL,
+    tool_id INTEGER NOT NULL,
+    PRIMARY KEY (kit_id, tool_id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_tool_kit_members_tool_id ON main.tool_kit_members USING btree(tool_id);
+
 CREATE TABLE main.rentals (
     id INTEGER GENERATED ALWAYS AS IDENTITY,
     tool_id INTEGER NOT NULL,
     renter_id INTEGER NOT NULL,
     start_date TIMESTAMPTZ NOT NULL,
     end_date TIMESTAMPTZ,
+    due_date TIMESTAMPTZ NOT NULL,
+    loan_id INTEGER,
+    quantity INTEGER DEFAULT 1 NOT NULL,
+    lost_quantity INTEGER DEFAULT 0 NOT NULL,
     PRIMARY KEY (id)
 );
 
 CREATE INDEX IF NOT EXISTS idx_rentals_tool_id ON main.rentals USING btree(tool_id);
 CREATE INDEX IF NOT EXISTS idx_rentals_renter_id ON main.rentals USING btree(renter_id);
+CREATE INDEX IF NOT EXISTS idx_rentals_loan_id ON main.rentals USING btree(loan_id);
+CREATE INDEX IF NOT EXISTS idx_rentals_open_tool_id ON main.rentals USING btree(tool_id) WHERE end_date IS NULL;
+
+-- a tool never has more units out than it owns; the tool row lock serialises concurrent check-outs
+CREATE FUNCTION main.check_open_rental_units() RETURNS TRIGGER AS $$
+DECLARE
+    owned INTEGER;
+    out INTEGER;
+BEGIN
+    IF NEW.end_date IS NOT NULL THEN
+        RETURN NULL;
+    END IF;
+
+    SELECT mt.quantity INTO owned FROM main.tools mt WHERE mt.id = NEW.tool_id FOR UPDATE;
+    SELECT COALESCE(SUM(mr.quantity), 0) INTO out FROM main.rentals mr WHERE mr.tool_id = NEW.tool_id AND mr.end_date IS NULL;
+
+    IF out > owned THEN
+        RAISE EXCEPTION 'tool % has % units out but owns %', NEW.tool_id, out, owned
+            USING ERRCODE = 'check_violation';
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER trg_rentals_open_units
+    AFTER INSERT OR UPDATE OF tool_id, end_date, quantity ON main.rentals
+    FOR EACH ROW EXECUTE FUNCTION main.check_open_rental_units();
+
+CREATE TABLE main.loans (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    receipt_number TEXT GENERATED ALWAYS AS ('L-' || LPAD(id::text, 6, '0')) STORED NOT NULL,
+    renter_id INTEGER NOT NULL,
+    performed_by INTEGER NOT NULL,
+    channel INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE (receipt_number),
+    CONSTRAINT fk_channel
+      FOREIGN KEY(channel)
+        REFERENCES fixed.loan_channels(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_loans_renter_id ON main.loans USING btree(renter_id);
+
+CREATE TABLE main.rental_extensions (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    rental_id INTEGER NOT NULL,
+    requested_by INTEGER NOT NULL,
+    previous_due_date TIMESTAMPTZ NOT NULL,
+    requested_due_date TIMESTAMPTZ NOT NULL,
+    note TEXT,
+    status INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    decided_by INTEGER,
+    decided_at TIMESTAMPTZ,
+    decision_note TEXT,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_status
+      FOREIGN KEY(status)
+        REFERENCES fixed.extension_statuses(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_rental_extensions_rental_id ON main.rental_extensions USING btree(rental_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_rental_extensions_pending_rental_id ON main.rental_extensions USING btree(rental_id) WHERE status = 1;
+
+CREATE TABLE main.rental_reminders (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    rental_id INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    days_overdue INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_kind
+      FOREIGN KEY(kind)
+        REFERENCES fixed.reminder_kinds(id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_rental_reminders_rental_id_kind_days ON main.rental_reminders USING btree(rental_id, kind, days_overdue);
+
+CREATE TABLE main.fee_schedules (
+    store_id INTEGER NOT NULL,
+    grace_hours INTEGER DEFAULT 0 NOT NULL,
+    fee_per_day INTEGER DEFAULT 0 NOT NULL,
+    fee_cap INTEGER,
+    max_balance INTEGER,
+    updated_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (store_id)
+);
+
+CREATE TABLE main.store_hours (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    store_id INTEGER NOT NULL,
+    weekday INTEGER NOT NULL,
+    opens_at TIME NOT NULL,
+    closes_at TIME NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_store_hours_store_id ON main.store_hours USING btree(store_id);
+
+CREATE TABLE main.store_holidays (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    store_id INTEGER NOT NULL,
+    starts_on DATE NOT NULL,
+    ends_on DATE NOT NULL,
+    name TEXT,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_store_holidays_store_id_ends_on ON main.store_holidays USING btree(store_id, ends_on);
+
+CREATE TABLE main.store_policies (
+    store_id INTEGER NOT NULL,
+    max_open_rentals INTEGER,
+    max_tools_per_check_out INTEGER,
+    min_account_age_days INTEGER,
+    block_overdue BOOLEAN DEFAULT FALSE NOT NULL,
+    max_rental_hours INTEGER,
+    updated_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (store_id)
+);
+
+CREATE TABLE main.ledger_entries (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    user_id INTEGER NOT NULL,
+    store_id INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    rental_id INTEGER,
+    note TEXT,
+    created_by INTEGER,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_kind
+      FOREIGN KEY(kind)
+        REFERENCES fixed.ledger_entry_kinds(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ledger_entries_user_id ON main.ledger_entries USING btree(user_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ledger_entries_overdue_fee_rental_id ON main.ledger_entries USING btree(rental_id) WHERE kind = 1;
+
+CREATE TABLE main.deposits (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    rental_id INTEGER NOT NULL,
+    tool_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    store_id INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    status INTEGER NOT NULL,
+    kept_amount INTEGER,
+    reason TEXT,
+    condition_report_id INTEGER,
+    taken_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    settled_at TIMESTAMPTZ,
+    settled_by INTEGER,
+    PRIMARY KEY (id),
+    UNIQUE (rental_id),
+    CONSTRAINT fk_status
+      FOREIGN KEY(status)
+        REFERENCES fixed.deposit_statuses(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_deposits_store_id_status ON main.deposits USING btree(store_id, status);
+
+CREATE TABLE main.condition_reports (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    tool_id INTEGER NOT NULL,
+    rental_id INTEGER,
+    reporter_id INTEGER NOT NULL,
+    grade INTEGER NOT NULL,
+    notes TEXT,
+    tool_status INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_grade
+      FOREIGN KEY(grade)
+        REFERENCES fixed.condition_grades(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_condition_reports_tool_id ON main.condition_reports USING btree(tool_id);
+CREATE INDEX IF NOT EXISTS idx_condition_reports_rental_id ON main.condition_reports USING btree(rental_id);
+
+CREATE TABLE main.condition_report_photos (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    report_id INTEGER NOT NULL,
+    photo_key TEXT NOT NULL,
+    original_name TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_condition_report_photos_report_id ON main.condition_report_photos USING btree(report_id);
+
+CREATE TABLE main.maintenance_schedules (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    tool_id INTEGER,
+    category_id INTEGER,
+    name TEXT NOT NULL,
+    every_rentals INTEGER,
+    every_days INTEGER,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_maintenance_schedules_tool_id ON main.maintenance_schedules USING btree(tool_id);
+CREATE INDEX IF NOT EXISTS idx_maintenance_schedules_category_id ON main.maintenance_schedules USING btree(category_id);
+
+CREATE TABLE main.service_log (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    tool_id INTEGER NOT NULL,
+    schedule_id INTEGER,
+    performed_by INTEGER NOT NULL,
+    notes TEXT,
+    performed_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_log_tool_id ON main.service_log USING btree(tool_id, performed_at);
+
+CREATE TABLE main.service_log_photos (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    entry_id INTEGER NOT NULL,
+    photo_key TEXT NOT NULL,
+    original_name TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_service_log_photos_entry_id ON main.service_log_photos USING btree(entry_id);
+
+CREATE TABLE main.repair_tickets (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    tool_id INTEGER NOT NULL,
+    reporter_id INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    priority INTEGER,
+    assigned_to INTEGER,
+    resolution TEXT,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    closed_at TIMESTAMPTZ,
+    closed_by INTEGER,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_status
+      FOREIGN KEY(status)
+        REFERENCES fixed.repair_ticket_statuses(id),
+    CONSTRAINT fk_priority
+      FOREIGN KEY(priority)
+        REFERENCES fixed.repair_ticket_priorities(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_repair_tickets_tool_id_status ON main.repair_tickets USING btree(tool_id, status);
+
+CREATE TABLE main.repair_ticket_photos (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    ticket_id INTEGER NOT NULL,
+    photo_key TEXT NOT NULL,
+    original_name TEXT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_repair_ticket_photos_ticket_id ON main.repair_ticket_photos USING btree(ticket_id);
+
+CREATE TABLE main.tool_transfers (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    from_store_id INTEGER NOT NULL,
+    to_store_id INTEGER NOT NULL,
+    proposed_by INTEGER NOT NULL,
+    note TEXT,
+    status INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    decided_by INTEGER,
+    decided_at TIMESTAMPTZ,
+    decision_note TEXT,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_status
+      FOREIGN KEY(status)
+        REFERENCES fixed.tool_transfer_statuses(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tool_transfers_from_store_id ON main.tool_transfers USING btree(from_store_id);
+CREATE INDEX IF NOT EXISTS idx_tool_transfers_to_store_id ON main.tool_transfers USING btree(to_store_id);
+
+CREATE TABLE main.tool_transfer_items (
+    transfer_id INTEGER NOT NULL,
+    tool_id INTEGER NOT NULL,
+    PRIMARY KEY (transfer_id, tool_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tool_transfer_items_tool_id ON main.tool_transfer_items USING btree(tool_id);
+
+CREATE TABLE main.stocktakes (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    store_id INTEGER NOT NULL,
+    opened_by INTEGER NOT NULL,
+    opened_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    note TEXT,
+    status INTEGER NOT NULL,
+    closed_by INTEGER,
+    closed_at TIMESTAMPTZ,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_status
+      FOREIGN KEY(status)
+        REFERENCES fixed.stocktake_statuses(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stocktakes_store_id ON main.stocktakes USING btree(store_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_stocktakes_open_store_id ON main.stocktakes USING btree(store_id) WHERE status = 1;
+
+CREATE TABLE main.stocktake_scans (
+    stocktake_id INTEGER NOT NULL,
+    tool_id INTEGER NOT NULL,
+    scanned_by INTEGER NOT NULL,
+    scanned_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (stocktake_id, tool_id)
+);
+
+CREATE TABLE main.stocktake_discrepancies (
+    stocktake_id INTEGER NOT NULL,
+    tool_id INTEGER NOT NULL,
+    scanned BOOLEAN NOT NULL,
+    tool_status INTEGER NOT NULL,
+    PRIMARY KEY (stocktake_id, tool_id)
+);
+
+CREATE TABLE main.tool_assets (
+    tool_id INTEGER NOT NULL,
+    brand TEXT,
+    model TEXT,
+    serial_number TEXT,
+    acquired_on DATE,
+    acquisition_source INTEGER,
+    donated_by INTEGER,
+    replacement_value INTEGER,
+    warranty_expires_on DATE,
+    updated_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    PRIMARY KEY (tool_id),
+    CONSTRAINT fk_acquisition_source
+      FOREIGN KEY(acquisition_source)
+        REFERENCES fixed.tool_acquisition_sources(id)
+);
+
+CREATE TABLE main.tool_holds (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    tool_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    status INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    ready_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_status
+      FOREIGN KEY(status)
+        REFERENCES fixed.hold_statuses(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tool_holds_tool_id ON main.tool_holds USING btree(tool_id);
+CREATE INDEX IF NOT EXISTS idx_tool_holds_user_id ON main.tool_holds USING btree(user_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_tool_holds_active_tool_user ON main.tool_holds USING btree(tool_id, user_id) WHERE status IN (1, 2);
+
+CREATE TABLE main.reservations (
+    id INTEGER GENERATED ALWAYS AS IDENTITY,
+    tool_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    start_date TIMESTAMPTZ NOT NULL,
+    end_date TIMESTAMPTZ NOT NULL,
+    status INTEGER NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT current_timestamp NOT NULL,
+    rental_id INTEGER,
+    quantity INTEGER DEFAULT 1 NOT NULL,
+    PRIMARY KEY (id),
+    CONSTRAINT fk_status
+      FOREIGN KEY(status)
+        REFERENCES fixed.reservation_statuses(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_reservations_tool_id_start_date ON main.reservations USING btree(tool_id, start_date);
+CREATE INDEX IF NOT EXISTS idx_reservations_user_id ON main.reservations USING btree(user_id);
 
 CREATE TABLE main.grievances (
     id INTEGER GENERATED ALWAYS AS IDENTITY,
@@ -164,5 +596,15 @@ CREATE INDEX IF NOT EXISTS idx_fuzzy_stores_with_contact ON main.stores
 CREATE INDEX IF NOT EXISTS idx_fuzzy_tools ON main.tools
   USING gist((real_id || ' ' || short_description || ' ' || COALESCE(long_description, '')) gist_trgm_ops(siglen=256));
 
+-- queries spell the expression out the same way to use it
+CREATE INDEX IF NOT EXISTS idx_tools_search_document ON main.tools
+  USING gin((setweight(to_tsvector('simple', real_id), 'A') || setweight(to_tsvector('english', short_description), 'A') || setweight(to_tsvector('english', COALESCE(long_description, '')), 'C')));
+
+CREATE INDEX IF NOT EXISTS idx_fuzzy_tool_assets ON main.tool_assets
+  USING gist((COALESCE(brand, '') || ' ' || COALESCE(model, '') || ' ' || COALESCE(serial_number, '')) gist_trgm_ops(siglen=256));
+
+CREATE INDEX IF NOT EXISTS idx_fuzzy_tool_kits ON main.tool_kits
+  USING gist((real_id || ' ' || short_description || ' ' || COALESCE(long_description, '')) gist_trgm_ops(siglen=256));
+
 CREATE INDEX IF NOT EXISTS idx_fuzzy_grievances ON main.grievances
   USING gist(title gist_trgm_ops(siglen=256));
\ No newline at end of file
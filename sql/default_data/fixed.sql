@@ -16,3 +16,44 @@ VALUES ('available'), ('rented'), ('maintenance'), ('broken'), ('lost'), ('stole
 INSERT INTO fixed.grievance_statuses (name)
 VALUES ('pending'), ('innocent'), ('guilty'), ('banned'), ('warned'), ('cautioned'), ('cheeky'), ('insulting'), ('time_served'), ('forgiven'), ('wrongly_convicted'), ('libelled'), ('at_large');
 
+INSERT INTO fixed.hold_statuses (name)
+VALUES ('waiting'), ('ready'), ('fulfilled'), ('expired'), ('cancelled');
+
+INSERT INTO fixed.reservation_statuses (name)
+VALUES ('active'), ('fulfilled'), ('cancelled');
+
+INSERT INTO fixed.extension_statuses (name)
+VALUES ('pending'), ('approved'), ('denied'), ('cancelled');
+
+INSERT INTO fixed.reminder_kinds (name)
+VALUES ('overdue'), ('marked_lost'), ('grievance_opened');
+
+INSERT INTO fixed.condition_grades (name)
+VALUES ('excellent'), ('good'), ('fair'), ('poor'), ('damaged');
+
+INSERT INTO fixed.loan_channels (name)
+VALUES ('staff'), ('self_checkout'), ('kiosk');
+
+INSERT INTO fixed.ledger_entry_kinds (name)
+VALUES ('overdue_fee'), ('adjustment'), ('waiver'), ('payment');
+
+INSERT INTO fixed.deposit_statuses (name)
+VALUES ('held'), ('released'), ('kept'), ('partially_kept');
+
+INSERT INTO fixed.tool_status_actions (name)
+VALUES ('created'), ('edited'), ('checked_out'), ('checked_in'), ('found'), ('marked_lost'), ('store_closed'), ('ticket_opened'), ('transferred'), ('stocktake');
+
+INSERT INTO fixed.repair_ticket_statuses (name)
+VALUES ('open'), ('triaged'), ('resolved'), ('dismissed');
+
+INSERT INTO fixed.repair_ticket_priorities (name)
+VALUES ('low'), ('normal'), ('high'), ('urgent');
+
+INSERT INTO fixed.tool_transfer_statuses (name)
+VALUES ('pending'), ('accepted'), ('declined'), ('cancelled');
+
+INSERT INTO fixed.stocktake_statuses (name)
+VALUES ('open'), ('closed'), ('cancelled');
+
+INSERT INTO fixed.tool_acquisition_sources (name)
+VALUES ('purchased'), ('donated');
@@ -4,5 +4,19 @@ DROP TABLE IF EXISTS fixed.permission_statuses;
 DROP TABLE IF EXISTS fixed.store_statuses;
 DROP TABLE IF EXISTS fixed.tool_statuses;
 DROP TABLE IF EXISTS fixed.grievance_statuses;
+DROP TABLE IF EXISTS fixed.hold_statuses;
+DROP TABLE IF EXISTS fixed.reservation_statuses;
+DROP TABLE IF EXISTS fixed.extension_statuses;
+DROP TABLE IF EXISTS fixed.reminder_kinds;
+DROP TABLE IF EXISTS fixed.condition_grades;
+DROP TABLE IF EXISTS fixed.loan_channels;
+DROP TABLE IF EXISTS fixed.ledger_entry_kinds;
+DROP TABLE IF EXISTS fixed.deposit_statuses;
+DROP TABLE IF EXISTS fixed.tool_status_actions;
+DROP TABLE IF EXISTS fixed.repair_ticket_statuses;
+DROP TABLE IF EXISTS fixed.repair_ticket_priorities;
+DROP TABLE IF EXISTS fixed.tool_transfer_statuses;
+DROP TABLE IF EXISTS fixed.stocktake_statuses;
+DROP TABLE IF EXISTS fixed.tool_acquisition_sources;
 
 DROP SCHEMA IF EXISTS fixed;
\ No newline at end of file
@@ -4,9 +4,38 @@ DROP TABLE IF EXISTS main.permissions;
 DROP TABLE IF EXISTS main.users;
 DROP TABLE IF EXISTS main.tool_categories;
 DROP TABLE IF EXISTS main.tool_classifications;
+DROP TABLE IF EXISTS main.tool_kits;
+DROP TABLE IF EXISTS main.tool_kit_members;
 DROP TABLE IF EXISTS main.tool_photos;
+DROP TABLE IF EXISTS main.tool_status_history;
+DROP TABLE IF EXISTS main.loans;
 DROP TABLE IF EXISTS main.rentals;
+DROP TABLE IF EXISTS main.rental_extensions;
+DROP TABLE IF EXISTS main.rental_reminders;
+DROP TABLE IF EXISTS main.fee_schedules;
+DROP TABLE IF EXISTS main.store_hours;
+DROP TABLE IF EXISTS main.store_holidays;
+DROP TABLE IF EXISTS main.store_policies;
+DROP TABLE IF EXISTS main.ledger_entries;
+DROP TABLE IF EXISTS main.deposits;
+DROP TABLE IF EXISTS main.condition_reports;
+DROP TABLE IF EXISTS main.condition_report_photos;
+DROP TABLE IF EXISTS main.maintenance_schedules;
+DROP TABLE IF EXISTS main.service_log;
+DROP TABLE IF EXISTS main.service_log_photos;
+DROP TABLE IF EXISTS main.repair_tickets;
+DROP TABLE IF EXISTS main.repair_ticket_photos;
+DROP TABLE IF EXISTS main.tool_transfers;
+DROP TABLE IF EXISTS main.tool_transfer_items;
+DROP TABLE IF EXISTS main.stocktakes;
+DROP TABLE IF EXISTS main.stocktake_scans;
+DROP TABLE IF EXISTS main.stocktake_discrepancies;
+DROP TABLE IF EXISTS main.tool_assets;
+DROP TABLE IF EXISTS main.tool_holds;
+DROP TABLE IF EXISTS main.reservations;
 DROP TABLE IF EXISTS main.tools;
 DROP TABLE IF EXISTS main.stores;
 
+DROP FUNCTION IF EXISTS main.check_open_rental_units;
+
 DROP SCHEMA IF EXISTS main;